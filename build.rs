@@ -14,4 +14,17 @@ fn main() {
     };
 
     println!("cargo:rustc-env=PASSRS_VERSION={}", version);
+
+    // The EFF long wordlist for `passrs generate --words`
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    let wordlist = std::fs::read_to_string("share/eff_large_wordlist.txt")
+        .expect("share/eff_large_wordlist.txt is missing");
+
+    assert_eq!(
+        wordlist.lines().filter(|l| !l.trim().is_empty()).count(),
+        7776,
+        "share/eff_large_wordlist.txt is not the EFF long wordlist"
+    );
+
+    std::fs::write(out_dir.join("eff_large_wordlist.txt"), wordlist).unwrap();
 }
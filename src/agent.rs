@@ -0,0 +1,329 @@
+//! Decryption agent
+//!
+//! # agent
+//!
+//! This module houses `passrs agent`, a long-running daemon that keeps
+//! decrypted entries in locked memory (see [`cache`]) so that pickers and shell
+//! integrations don't have to go through gpg-agent every time they show a
+//! secret. Clients talk to it over a Unix socket at [`PASSRS_AGENT_SOCKET`],
+//! which is only accessible by the user that started the agent. Both sides
+//! check the credentials of their peer: the agent only serves its own user, and
+//! clients only trust an agent run by their own user, since anybody could have
+//! created a socket at the fallback path in `/tmp`.
+//!
+//! The protocol is line-based:
+//!
+//! * `DECRYPT <path>` is answered with `OK <length>`, followed by exactly
+//!   `length` bytes of plaintext, or with `ERR <message>`
+//! * `STOP` is answered with `OK 0`, after which the agent exits
+//!
+//! The agent exits by itself after [`PASSRS_AGENT_TIMEOUT`] seconds without a
+//! request, and decrypts entries again once they have been cached for that
+//! long. `grep` starts an agent of its own when [`PASSRS_GREP_CACHE_TTL`] is
+//! set, so that the plaintext is reused by the next `grep`.
+//!
+//! [`cache`]: ../cache/index.html
+//! [`PASSRS_AGENT_SOCKET`]: ../consts/static.PASSRS_AGENT_SOCKET.html
+//! [`PASSRS_AGENT_TIMEOUT`]: ../consts/static.PASSRS_AGENT_TIMEOUT.html
+//! [`PASSRS_GREP_CACHE_TTL`]: ../consts/static.PASSRS_GREP_CACHE_TTL.html
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cache::{self, Plaintext};
+use crate::consts::{PASSRS_AGENT_SOCKET, PASSWORD_STORE_DIR};
+use crate::util;
+use crate::{PassrsError, Result};
+
+/// How often the agent checks whether it should exit.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a client waits for the agent; decrypting may require the user to
+/// enter their passphrase.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long [`spawn`] waits for the new agent to listen.
+///
+/// [`spawn`]: fn.spawn.html
+const SPAWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+struct State {
+    last_request: Mutex<Instant>,
+    stop: AtomicBool,
+    timeout: Duration,
+}
+
+impl State {
+    fn touch(&self) {
+        if let Ok(mut last_request) = self.last_request.lock() {
+            *last_request = Instant::now();
+        }
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_request
+            .lock()
+            .map(|last_request| last_request.elapsed())
+            .unwrap_or_default()
+    }
+}
+
+/// Listens on [`PASSRS_AGENT_SOCKET`] until stopped or until no request has
+/// been made for `timeout`. Entries are cached for at most `timeout` as well,
+/// and every cached entry is zeroed before returning.
+///
+/// [`PASSRS_AGENT_SOCKET`]: ../consts/static.PASSRS_AGENT_SOCKET.html
+pub fn serve(timeout: Duration) -> Result<()> {
+    let socket = &*PASSRS_AGENT_SOCKET;
+
+    if socket.exists() {
+        // A live agent accepts connections; a stale socket doesn't
+        if UnixStream::connect(socket).is_ok() {
+            return Err(format!("An agent is already listening on '{}'", socket.display()).into());
+        }
+
+        fs::remove_file(socket)?;
+    }
+
+    // Create the socket without any permissions for others, rather than
+    // restricting them once somebody could already have connected
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(socket);
+
+    unsafe { libc::umask(umask) };
+
+    let listener = listener?;
+
+    listener.set_nonblocking(true)?;
+
+    let state = Arc::new(State {
+        last_request: Mutex::new(Instant::now()),
+        stop: AtomicBool::new(false),
+        timeout,
+    });
+    let ret = loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let state = Arc::clone(&state);
+
+                state.touch();
+                thread::spawn(move || {
+                    // Errors are reported to the client, if possible; there's
+                    // nobody to report them to otherwise
+                    let _ = self::handle(stream, &state);
+                    state.touch();
+                });
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => break Err(e.into()),
+        }
+
+        if state.stop.load(Ordering::SeqCst) || state.idle_for() >= timeout {
+            break Ok(());
+        }
+    };
+
+    fs::remove_file(socket)?;
+    cache::clear()?;
+
+    ret
+}
+
+/// Starts `passrs agent` in the background, unless an agent is already
+/// running, and waits for it to listen. The new agent exits after `timeout`
+/// without a request.
+pub fn spawn(timeout: Duration) -> Result<()> {
+    if self::connect()?.is_some() {
+        return Ok(());
+    }
+
+    Command::new(env::current_exe()?)
+        .args(["agent", "--timeout", &timeout.as_secs().to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Keep running when this process is interrupted
+        .process_group(0)
+        .spawn()?;
+
+    let start = Instant::now();
+
+    // If it takes any longer, entries are decrypted without it
+    while start.elapsed() < SPAWN_TIMEOUT {
+        if self::connect()?.is_some() {
+            break;
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    Ok(())
+}
+
+/// Asks a running agent for the plaintext of the entry at `path`. Returns
+/// `Ok(None)` if no agent is running.
+pub fn request<P>(path: P) -> Result<Option<Vec<u8>>>
+where
+    P: AsRef<Path>,
+{
+    let mut stream = match self::connect()? {
+        Some(stream) => stream,
+        None => return Ok(None),
+    };
+    let path = fs::canonicalize(path)?;
+    let path = path.to_str().ok_or("Path was not valid UTF8")?;
+
+    if path.contains('\n') {
+        return Ok(None);
+    }
+
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    writeln!(stream, "DECRYPT {}", path)?;
+
+    Ok(Some(self::read_response(&stream)?))
+}
+
+/// Stops a running agent. Returns `false` if no agent was running.
+pub fn stop() -> Result<bool> {
+    let mut stream = match self::connect()? {
+        Some(stream) => stream,
+        None => return Ok(false),
+    };
+
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    writeln!(stream, "STOP")?;
+    self::read_response(&stream)?;
+
+    Ok(true)
+}
+
+/// Connects to the agent at [`PASSRS_AGENT_SOCKET`]. Returns `Ok(None)` if no
+/// agent is listening, and an `Err` if the agent is run by another user: it
+/// would learn which entries we decrypt, and could answer with secrets of its
+/// own choosing.
+///
+/// [`PASSRS_AGENT_SOCKET`]: ../consts/static.PASSRS_AGENT_SOCKET.html
+fn connect() -> Result<Option<UnixStream>> {
+    let socket = &*PASSRS_AGENT_SOCKET;
+    let stream = match UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        Err(_) => return Ok(None),
+    };
+
+    if self::peer_uid(&stream)? != unsafe { libc::getuid() } {
+        return Err(format!(
+            "Refusing to talk to agent '{}' run by another user",
+            socket.display()
+        )
+        .into());
+    }
+
+    Ok(Some(stream))
+}
+
+fn read_response(stream: &UnixStream) -> Result<Vec<u8>> {
+    let mut reader = BufReader::new(stream);
+    let mut header = String::new();
+
+    reader.read_line(&mut header)?;
+
+    let header = header.trim_end();
+
+    if let Some(err) = header.strip_prefix("ERR ") {
+        return Err(err.to_owned().into());
+    }
+
+    let len: usize = header
+        .strip_prefix("OK ")
+        .ok_or("Malformed response from agent")?
+        .parse()?;
+    let mut plain = vec![0; len];
+
+    reader.read_exact(&mut plain)?;
+
+    Ok(plain)
+}
+
+fn handle(stream: UnixStream, state: &State) -> Result<()> {
+    stream.set_nonblocking(false)?;
+
+    // The socket's permissions should already prevent this
+    if self::peer_uid(&stream)? != unsafe { libc::getuid() } {
+        return Err("Refusing request from another user".into());
+    }
+
+    let mut reader = BufReader::new(&stream);
+    let mut writer = &stream;
+    let mut line = String::new();
+
+    reader.read_line(&mut line)?;
+
+    let line = line.trim_end_matches('\n');
+
+    if line == "STOP" {
+        state.stop.store(true, Ordering::SeqCst);
+        writer.write_all(b"OK 0\n")?;
+
+        return Ok(());
+    }
+
+    let response = match line.strip_prefix("DECRYPT ") {
+        Some(path) => self::decrypt(Path::new(path), state.timeout),
+        None => Err(format!("Unknown request '{}'", line).into()),
+    };
+
+    match response {
+        Ok(plain) => {
+            writeln!(writer, "OK {}", plain.len())?;
+            writer.write_all(&plain)?;
+        }
+        Err(e) => writeln!(writer, "ERR {}", e.to_string().replace('\n', " "))?,
+    }
+
+    Ok(())
+}
+
+fn decrypt(path: &Path, ttl: Duration) -> Result<Plaintext> {
+    let path = fs::canonicalize(path)?;
+    let store = fs::canonicalize(&*PASSWORD_STORE_DIR)?;
+
+    // Only hand out entries of the store this agent was started for
+    if !path.starts_with(&store) || !util::is_entry(path.to_string_lossy()) {
+        return Err(PassrsError::NotInStore(path.display().to_string()).into());
+    }
+
+    cache::get_or_decrypt(&path, ttl, util::decrypt_file_locally)
+}
+
+fn peer_uid(stream: &UnixStream) -> Result<libc::uid_t> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    if ret != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    Ok(cred.uid)
+}
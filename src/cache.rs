@@ -0,0 +1,140 @@
+//! Decrypted entry cache
+//!
+//! # cache
+//!
+//! This module houses the cache of decrypted entries kept by [`agent`], so that
+//! other invocations (e.g. repeated `grep`s) don't have to go through gpg-agent
+//! for every entry again. Plaintext is kept in memory that is locked with
+//! `mlock(2)` (so it is never swapped out) and zeroed when it is evicted.
+//!
+//! Entries are invalidated when they expire, or when the file on disk is
+//! modified.
+//!
+//! [`agent`]: ../agent/index.html
+
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use once_cell::sync::Lazy;
+
+use crate::Result;
+
+static CACHE: Lazy<Mutex<HashMap<PathBuf, CachedEntry>>> = Lazy::new(Default::default);
+
+struct CachedEntry {
+    plaintext: Plaintext,
+    modified: SystemTime,
+    len: u64,
+    inserted: Instant,
+}
+
+/// The decrypted contents of an entry, held in locked memory that is zeroed
+/// once the last reference to it is dropped.
+#[derive(Clone)]
+pub struct Plaintext(Arc<LockedBuf>);
+
+struct LockedBuf(Box<[u8]>);
+
+impl LockedBuf {
+    fn new(buf: Vec<u8>) -> LockedBuf {
+        let buf = buf.into_boxed_slice();
+
+        // Locking is best-effort: if we hit RLIMIT_MEMLOCK, we still want to
+        // be able to grep
+        if !buf.is_empty() {
+            unsafe {
+                libc::mlock(buf.as_ptr() as *const libc::c_void, buf.len());
+            }
+        }
+
+        LockedBuf(buf)
+    }
+}
+
+impl Drop for LockedBuf {
+    fn drop(&mut self) {
+        for byte in self.0.iter_mut() {
+            unsafe { ptr::write_volatile(byte, 0) };
+        }
+
+        if !self.0.is_empty() {
+            unsafe {
+                libc::munlock(self.0.as_ptr() as *const libc::c_void, self.0.len());
+            }
+        }
+    }
+}
+
+impl Deref for Plaintext {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &(self.0).0
+    }
+}
+
+impl AsRef<[u8]> for Plaintext {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Returns the cached plaintext of the entry at `path` if it was cached less
+/// than `ttl` ago and hasn't been modified since; otherwise, decrypts it with
+/// `decrypt` and caches the result.
+pub(crate) fn get_or_decrypt<F>(path: &Path, ttl: Duration, decrypt: F) -> Result<Plaintext>
+where
+    F: FnOnce(&Path) -> Result<Vec<u8>>,
+{
+    let meta = fs::metadata(path)?;
+    let modified = meta.modified()?;
+    let len = meta.len();
+
+    {
+        let mut cache = CACHE
+            .lock()
+            .map_err(|_| "Decrypted entry cache was poisoned")?;
+
+        cache.retain(|_, entry| entry.inserted.elapsed() < ttl);
+
+        if let Some(entry) = cache.get(path) {
+            if entry.modified == modified && entry.len == len {
+                return Ok(entry.plaintext.clone());
+            }
+        }
+    }
+
+    // Don't hold the lock while decrypting so other threads can make progress
+    let plaintext = decrypt(path)?;
+    let plaintext = Plaintext(Arc::new(LockedBuf::new(plaintext)));
+    let mut cache = CACHE
+        .lock()
+        .map_err(|_| "Decrypted entry cache was poisoned")?;
+
+    cache.insert(
+        path.to_path_buf(),
+        CachedEntry {
+            plaintext: plaintext.clone(),
+            modified,
+            len,
+            inserted: Instant::now(),
+        },
+    );
+
+    Ok(plaintext)
+}
+
+/// Evicts (and zeroes) every cached entry.
+pub fn clear() -> Result<()> {
+    CACHE
+        .lock()
+        .map_err(|_| "Decrypted entry cache was poisoned")?
+        .clear();
+
+    Ok(())
+}
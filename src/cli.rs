@@ -5,10 +5,17 @@
 //! [structopt]: https://docs.rs/structopt
 
 use std::io::{self, Write};
+use std::path::PathBuf;
 
 use clap::{AppSettings, Clap, IntoApp};
 
-use crate::subcmds::{cp, edit, find, generate, git, grep, init, insert, ls, mv, rm, show, unclip};
+use crate::lock;
+use crate::passphrase::PassphraseOptions;
+use crate::policy::Policy;
+use crate::subcmds::{
+    agent, cp, edit, extension, find, generate, git, git_driver, grep, init, insert, log, ls, mv,
+    restore, rm, rotate, show, sync, unclip,
+};
 use crate::util;
 use crate::Result;
 
@@ -29,22 +36,37 @@ pub(crate) enum PassSubcmd {
     /// Initialize a new store or substore.
     Init {
         /// The gpg-id(s) to encrypt the store with (default:
-        /// $PASSWORD_STORE_KEY).
+        /// $PASSWORD_STORE_KEY). A single empty gpg-id deinitializes the
+        /// substore at --path, re-encrypting its secrets for its parent.
         gpg_ids: Vec<String>,
         #[clap(long, short = "p")]
         /// The path of the substore to initialize.
         /// The specified gpg-id(s) is assigned to the specified subfolder.
         path: Option<String>,
+        #[clap(long)]
+        /// Treat the specified ids as age recipients, writing them to
+        /// `.age-recipients` instead of `.gpg-id`. New secrets in the
+        /// (sub)store will be encrypted with age.
+        age: bool,
+        #[clap(long, short = "H", conflicts_with = "age")]
+        /// Encrypt secrets in the (sub)store without revealing which keys they
+        /// are encrypted to, like gpg's `--throw-keyids`. Initializing again
+        /// without this flag reveals them again.
+        hidden: bool,
     },
     /// List all secrets.
     Ls {
         /// The subfolder to list.
         subfolder: Option<String>,
+        #[clap(flatten)]
+        output: Output,
     },
     /// List all secrets that match secret-name.
     Find {
         /// The name of the secret to find.
         secret_name: String,
+        #[clap(flatten)]
+        output: Output,
     },
     /// Show existing secret.
     Show {
@@ -53,19 +75,55 @@ pub(crate) enum PassSubcmd {
         #[clap(long, short = "c", next_line_help = true)]
         #[allow(clippy::option_option)]
         /// Copy the secret to the clipboard. If a line number is specified,
-        /// that line (1-based) will be copied. Otherwise, the first line of the
-        /// file will be copied. The secret will be cleared in
-        /// $PASSWORD_STORE_CLIP_TIME seconds (default: 45).
+        /// that line (1-based) will be copied; if a field name is specified
+        /// (e.g. `user`), the value of that field will be copied. Otherwise,
+        /// the first line of the file will be copied. The secret will be
+        /// cleared in $PASSWORD_STORE_CLIP_TIME seconds (default: 45).
         /// NOTE: This flag must be the final argument.
-        // Some(Some(usize)) => contents of line at usize.saturating_sub(1)
-        // Some(None) => contents of first line
+        // Some(Some(String)) => contents of line or field
+        // Some(None) => contents of first line (or --field)
         // None => don't clip
-        clip: Option<Option<usize>>,
+        clip: Option<Option<String>>,
+        #[clap(long, value_name = "key")]
+        /// Only show (or copy) the value of the named field, e.g. `user` for
+        /// a `user: ...` line.
+        field: Option<String>,
+        #[clap(long, value_name = "commit")]
+        /// Show the secret as it was in this git revision (e.g. `HEAD~2` or a
+        /// commit hash), even if it has been removed since.
+        rev: Option<String>,
+        #[clap(flatten)]
+        output: Output,
+    },
+    /// List the commits that changed the store, or the specified secret.
+    Log {
+        /// The secret whose history to list, following renames.
+        secret_name: Option<String>,
+        #[clap(long, short = "p")]
+        /// Show the decrypted changes each commit made to the secret.
+        patch: bool,
+    },
+    /// Restore a secret to how it was in a previous git revision.
+    Restore {
+        /// The secret to restore.
+        secret_name: String,
+        #[clap(long, value_name = "commit")]
+        /// The git revision to restore the secret from (e.g. `HEAD~2` or a
+        /// commit hash). The secret is encrypted for its current recipients.
+        rev: String,
     },
     /// Search for pattern in secrets.
     Grep {
         /// The pattern to grep for.
         search_string: String,
+        /// The subfolder to search in (default: the entire store).
+        subfolder: Option<String>,
+        #[clap(long, value_name = "key", number_of_values = 1)]
+        /// Only search `key: value` lines with this key (e.g. `user` or
+        /// `url`). May be specified multiple times.
+        field: Vec<String>,
+        #[clap(flatten)]
+        options: grep::GrepOptions,
     },
     /// Insert a new secret.
     Insert {
@@ -93,6 +151,39 @@ pub(crate) enum PassSubcmd {
         secret_name: String,
         /// The length of the secret in characters (default: 24).
         length: Option<usize>,
+        #[clap(flatten)]
+        policy: PolicyArgs,
+        #[clap(
+            long,
+            value_name = "N",
+            conflicts_with_all = &[
+                "length",
+                "no-symbols",
+                "min-digits",
+                "min-upper",
+                "min-lower",
+                "min-special",
+                "exclude-ambiguous",
+                "no-exclude-ambiguous",
+                "max-repeat",
+                "symbols",
+            ]
+        )]
+        /// Generate a passphrase of N words instead.
+        words: Option<usize>,
+        #[clap(long, value_name = "separator", requires = "words")]
+        /// The separator between words (default: -).
+        separator: Option<String>,
+        #[clap(long, value_name = "path", requires = "words")]
+        /// The wordlist to pick words from, one word per line (default: the
+        /// EFF long wordlist).
+        wordlist: Option<PathBuf>,
+        #[clap(long, requires = "words")]
+        /// Capitalize every word.
+        capitalize: bool,
+        #[clap(long, requires = "words")]
+        /// Append a random digit to one of the words.
+        digit: bool,
         #[clap(long, short = "c", next_line_help = true)]
         /// Copy the secret to the clipboard. The secret will be cleared in
         /// $PASSWORD_STORE_CLIP_TIME seconds (default: 45).
@@ -107,6 +198,28 @@ pub(crate) enum PassSubcmd {
         /// Disable special symbols.
         no_symbols: bool,
     },
+    /// Generate new secrets for entries, keeping their other lines.
+    Rotate {
+        #[clap(required = true)]
+        /// The entries, folders or glob patterns (e.g. 'Internet/*') to
+        /// rotate.
+        targets: Vec<String>,
+        #[clap(long, short = "l", value_name = "length")]
+        /// The length of the new secrets in characters (default: 24).
+        length: Option<usize>,
+        #[clap(long, short = "n")]
+        /// Disable special symbols.
+        no_symbols: bool,
+        #[clap(flatten)]
+        policy: PolicyArgs,
+        #[clap(long, short = "p")]
+        /// Keep the old secrets in a `previous:` field (otherwise, they're
+        /// only kept in the git history).
+        keep_previous: bool,
+        #[clap(long, short = "f")]
+        /// Don't ask for confirmation.
+        force: bool,
+    },
     /// Remove existing secret or directory.
     Rm {
         /// The name of the secret to remove.
@@ -144,6 +257,26 @@ pub(crate) enum PassSubcmd {
         /// Arguments to pass to the git binary.
         git_command_args: Vec<String>,
     },
+    /// Pull changes from a git remote, merging them with local changes, and
+    /// push the result.
+    Sync {
+        #[clap(default_value = "origin")]
+        /// The remote to sync with.
+        remote: String,
+        #[clap(long)]
+        /// Only pull changes, without pushing.
+        no_push: bool,
+    },
+    /// Keep decrypted secrets in memory for other invocations of passrs.
+    Agent {
+        #[clap(long, short = "t", value_name = "seconds")]
+        /// Exit after this many seconds without a request (default:
+        /// $PASSRS_AGENT_TIMEOUT, or 600).
+        timeout: Option<u64>,
+        #[clap(long, short = "s")]
+        /// Stop the running agent.
+        stop: bool,
+    },
     #[clap(setting = AppSettings::Hidden)]
     /// Clipboard daemon functionality.
     Unclip {
@@ -153,9 +286,39 @@ pub(crate) enum PassSubcmd {
         /// Clear clipboard even if the checksum doesn't match.
         force: bool,
     },
+    #[clap(setting = AppSettings::Hidden)]
+    /// Print the decrypted contents of an entry, for git's `textconv`.
+    GitDiffDriver {
+        /// The encrypted file to print.
+        file: PathBuf,
+    },
+    #[clap(setting = AppSettings::Hidden)]
+    /// Verify the signature of a file against $PASSWORD_STORE_SIGNING_KEY, for
+    /// extensions.
+    VerifyFile {
+        /// The file to verify.
+        file: PathBuf,
+    },
+    #[clap(setting = AppSettings::Hidden)]
+    /// Merge the decrypted versions of an entry, as a git merge driver.
+    GitMergeDriver {
+        /// The common ancestor's version (%O).
+        base: PathBuf,
+        /// The current version, which receives the result (%A).
+        ours: PathBuf,
+        /// The other branch's version (%B).
+        theirs: PathBuf,
+        /// The entry's path in the store (%P).
+        path: String,
+    },
     #[cfg(feature = "otp")]
     /// Manage TOTP secrets.
     Otp(Otp),
+    #[clap(external_subcommand)]
+    /// Run an extension, either from $PASSWORD_STORE_EXTENSIONS_DIR, if
+    /// $PASSWORD_STORE_ENABLE_EXTENSIONS is `true`, or from the system
+    /// extension dir.
+    Extension(Vec<String>),
 }
 
 /// For managing one-time-password (OTP) tokens with passrs.
@@ -171,6 +334,8 @@ pub(crate) enum Otp {
         /// Copy the secret to the clipboard. The secret will be cleared in
         /// $PASSWORD_STORE_CLIP_TIME seconds (default: 45).
         clip: bool,
+        #[clap(flatten)]
+        output: Output,
     },
     /// Insert a TOTP secret to secret-name.
     Insert {
@@ -257,31 +422,144 @@ pub(crate) struct Flags {
     pub recursive: bool,
 }
 
+#[derive(Clap, Debug, Default, Clone)]
+/// Options selecting how a subcommand prints its results.
+pub(crate) struct Output {
+    #[clap(long, conflicts_with = "format")]
+    /// Print machine-readable JSON (same as `--format json`).
+    json: bool,
+    #[clap(long, value_name = "format", possible_values = &["plain", "json"])]
+    /// How to print the results (default: plain).
+    format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The format a subcommand prints its results in.
+pub(crate) enum OutputFormat {
+    /// Human-readable, possibly colored, text.
+    Plain,
+    /// JSON, for consumption by other tools.
+    Json,
+}
+
+#[derive(Clap, Debug, Default, Clone)]
+/// Options overriding the password policy `generate` follows.
+pub(crate) struct PolicyArgs {
+    #[clap(long, value_name = "N")]
+    /// Require at least N digits.
+    min_digits: Option<usize>,
+    #[clap(long, value_name = "N")]
+    /// Require at least N uppercase letters.
+    min_upper: Option<usize>,
+    #[clap(long, value_name = "N")]
+    /// Require at least N lowercase letters.
+    min_lower: Option<usize>,
+    #[clap(long, value_name = "N")]
+    /// Require at least N symbols.
+    min_special: Option<usize>,
+    #[clap(long)]
+    /// Leave out easily confused characters (0O1Il|).
+    exclude_ambiguous: bool,
+    #[clap(long, conflicts_with = "exclude-ambiguous")]
+    /// Allow easily confused characters, even if the policy file leaves them
+    /// out.
+    no_exclude_ambiguous: bool,
+    #[clap(long, value_name = "N")]
+    /// Allow the same character at most N times in a row.
+    max_repeat: Option<usize>,
+    #[clap(long, value_name = "symbols")]
+    /// Only allow these symbols.
+    symbols: Option<String>,
+}
+
+impl PolicyArgs {
+    /// The policy options that were passed, to override the policy file's.
+    pub(crate) fn into_policy(self) -> Policy {
+        Policy {
+            length: None,
+            min_digits: self.min_digits,
+            min_upper: self.min_upper,
+            min_lower: self.min_lower,
+            min_special: self.min_special,
+            exclude_ambiguous: if self.exclude_ambiguous {
+                Some(true)
+            } else if self.no_exclude_ambiguous {
+                Some(false)
+            } else {
+                None
+            },
+            max_repeat: self.max_repeat,
+            symbols: self.symbols.map(|symbols| symbols.chars().collect()),
+        }
+    }
+}
+
+impl Output {
+    pub(crate) fn format(&self) -> OutputFormat {
+        if self.json || self.format.as_deref() == Some("json") {
+            OutputFormat::Json
+        } else {
+            OutputFormat::Plain
+        }
+    }
+}
+
 pub fn opt() -> Result<()> {
     let matches = Pass::parse();
 
     // NOTE: committing is handled inside any subcommand that may modify the
-    // store
+    // store, which also hold the store lock while running
     match matches.subcmd {
         Some(sub) => match sub {
-            PassSubcmd::Init { gpg_ids, path } => {
-                init::init(gpg_ids, path)?;
+            PassSubcmd::Init {
+                gpg_ids,
+                path,
+                age,
+                hidden,
+            } => {
+                let _lock = lock::acquire()?;
+
+                init::init(gpg_ids, path, age, hidden)?;
             }
-            PassSubcmd::Ls { subfolder } => {
+            PassSubcmd::Ls { subfolder, output } => {
                 util::verify_store_exists()?;
-                ls::ls(subfolder)?;
+                ls::ls(subfolder, output.format())?;
             }
-            PassSubcmd::Find { secret_name } => {
+            PassSubcmd::Find {
+                secret_name,
+                output,
+            } => {
                 util::verify_store_exists()?;
-                find::find(secret_name)?;
+                find::find(secret_name, output.format())?;
             }
-            PassSubcmd::Show { secret_name, clip } => {
+            PassSubcmd::Show {
+                secret_name,
+                clip,
+                field,
+                rev,
+                output,
+            } => {
                 util::verify_store_exists()?;
-                show::show(secret_name, clip)?;
+                show::show(secret_name, clip, field, output.format(), rev)?;
             }
-            PassSubcmd::Grep { search_string } => {
+            PassSubcmd::Log { secret_name, patch } => {
                 util::verify_store_exists()?;
-                grep::grep(search_string)?;
+                log::log(secret_name, patch)?;
+            }
+            PassSubcmd::Restore { secret_name, rev } => {
+                util::verify_store_exists()?;
+                let _lock = lock::acquire()?;
+
+                restore::restore(secret_name, rev)?;
+            }
+            PassSubcmd::Grep {
+                search_string,
+                subfolder,
+                field,
+                options,
+            } => {
+                util::verify_store_exists()?;
+                grep::grep(search_string, subfolder, field, options)?;
             }
             PassSubcmd::Insert {
                 secret_name,
@@ -298,10 +576,14 @@ pub fn opt() -> Result<()> {
 
                 util::ensure_stdout_is_tty()?;
                 util::verify_store_exists()?;
+                let _lock = lock::acquire()?;
+
                 insert::insert(secret_name, flags)?;
             }
             PassSubcmd::Edit { secret_name } => {
                 util::verify_store_exists()?;
+                let _lock = lock::acquire()?;
+
                 edit::edit(secret_name)?;
             }
             PassSubcmd::Generate {
@@ -311,6 +593,12 @@ pub fn opt() -> Result<()> {
                 in_place,
                 no_symbols,
                 length,
+                policy,
+                words,
+                separator,
+                wordlist,
+                capitalize,
+                digit,
             } => {
                 let flags = Flags {
                     clip,
@@ -319,10 +607,42 @@ pub fn opt() -> Result<()> {
                     no_symbols,
                     ..Default::default()
                 };
+                let passphrase = words.map(|words| {
+                    let defaults = PassphraseOptions::default();
+                    let options = PassphraseOptions {
+                        words,
+                        separator: separator.unwrap_or(defaults.separator),
+                        capitalize,
+                        digit,
+                    };
+
+                    (options, wordlist)
+                });
 
                 util::ensure_stdout_is_tty()?;
                 util::verify_store_exists()?;
-                generate::generate(secret_name, length, flags)?;
+                let _lock = lock::acquire()?;
+
+                generate::generate(secret_name, length, policy.into_policy(), passphrase, flags)?;
+            }
+            PassSubcmd::Rotate {
+                targets,
+                length,
+                no_symbols,
+                policy,
+                keep_previous,
+                force,
+            } => {
+                let flags = Flags {
+                    force,
+                    no_symbols,
+                    ..Default::default()
+                };
+
+                util::verify_store_exists()?;
+                let _lock = lock::acquire()?;
+
+                rotate::rotate(targets, length, policy.into_policy(), keep_previous, flags)?;
             }
             PassSubcmd::Rm {
                 secret_name,
@@ -337,6 +657,8 @@ pub fn opt() -> Result<()> {
 
                 util::ensure_stdout_is_tty()?;
                 util::verify_store_exists()?;
+                let _lock = lock::acquire()?;
+
                 rm::rm(secret_name, flags)?;
             }
             PassSubcmd::Mv {
@@ -346,6 +668,8 @@ pub fn opt() -> Result<()> {
             } => {
                 util::ensure_stdout_is_tty()?;
                 util::verify_store_exists()?;
+                let _lock = lock::acquire()?;
+
                 mv::mv(old_path, new_path, force)?;
             }
             PassSubcmd::Cp {
@@ -355,25 +679,58 @@ pub fn opt() -> Result<()> {
             } => {
                 util::ensure_stdout_is_tty()?;
                 util::verify_store_exists()?;
+                let _lock = lock::acquire()?;
+
                 cp::cp(old_path, new_path, force)?;
             }
             PassSubcmd::Git { git_command_args } => {
                 util::ensure_stdout_is_tty()?;
                 util::verify_store_exists()?;
+                let _lock = lock::acquire()?;
+
                 git::git(git_command_args)?;
             }
+            PassSubcmd::Sync { remote, no_push } => {
+                util::verify_store_exists()?;
+                sync::sync(remote, !no_push)?;
+            }
+            PassSubcmd::Agent { timeout, stop } => {
+                util::verify_store_exists()?;
+                agent::agent(timeout, stop)?;
+            }
+            PassSubcmd::Extension(args) => {
+                extension::extension(args)?;
+            }
             PassSubcmd::Unclip { timeout, force } => {
                 util::verify_store_exists()?;
                 unclip::unclip(timeout, force)?;
             }
+            PassSubcmd::GitDiffDriver { file } => {
+                git_driver::diff_driver(file)?;
+            }
+            PassSubcmd::VerifyFile { file } => {
+                util::verify_file(file)?;
+            }
+            PassSubcmd::GitMergeDriver {
+                base,
+                ours,
+                theirs,
+                path,
+            } => {
+                git_driver::merge_driver(base, ours, theirs, path)?;
+            }
             #[cfg(feature = "otp")]
             PassSubcmd::Otp(otp) => {
                 use crate::subcmds::otp::{append, code, insert, uri, validate};
 
                 match otp {
-                    Otp::Code { secret_name, clip } => {
+                    Otp::Code {
+                        secret_name,
+                        clip,
+                        output,
+                    } => {
                         util::verify_store_exists()?;
-                        code::code(secret_name, clip)?;
+                        code::code(secret_name, clip, output.format())?;
                     }
                     Otp::Insert {
                         secret_name,
@@ -395,6 +752,8 @@ pub fn opt() -> Result<()> {
 
                         util::ensure_stdout_is_tty()?;
                         util::verify_store_exists()?;
+                        let _lock = lock::acquire()?;
+
                         insert::insert(secret_name, algorithm, digits, period, flags)?;
                     }
                     Otp::Append {
@@ -415,6 +774,8 @@ pub fn opt() -> Result<()> {
 
                         util::ensure_stdout_is_tty()?;
                         util::verify_store_exists()?;
+                        let _lock = lock::acquire()?;
+
                         append::append(secret_name, algorithm, digits, period, flags)?;
                     }
                     Otp::Uri {
@@ -445,7 +806,7 @@ pub fn opt() -> Result<()> {
         // If no command is specified, ls the entire password store, like pass
         // does
         None => match util::verify_store_exists() {
-            Ok(_) => ls::ls(None)?,
+            Ok(_) => ls::ls(None, OutputFormat::Plain)?,
             Err(_) => {
                 Pass::into_app()
                     .print_help()
@@ -2,53 +2,154 @@
 //!
 //! # clipboard
 //!
-//! This module houses the clipboard functionality, utilizing platform-specific
-//! binaries to interact with the clipboard.
+//! This module houses the clipboard functionality. The clipboard is accessed
+//! through a [`ClipboardBackend`], selected by the [`PASSRS_CLIPBOARD_BACKEND`]
+//! environment variable:
 //!
-//! Currently, only Wayland and X11 are supported.
+//! * `wayland`: `wl-copy` and `wl-paste`
+//! * `xclip`: `xclip`, using [`PASSWORD_STORE_X_SELECTION`]
+//! * `xsel`: `xsel`, using [`PASSWORD_STORE_X_SELECTION`]
+//! * `tmux`: the `passrs` tmux buffer
+//! * `osc52`: OSC 52 escape sequences, which many terminals (and tmux) accept
+//!   even over SSH; the clipboard can't be read back, though
+//! * `command`: the shell commands in `PASSRS_CLIPBOARD_COPY`,
+//!   `PASSRS_CLIPBOARD_PASTE` and `PASSRS_CLIPBOARD_CLEAR`
+//! * `file`: the file at `PASSRS_CLIPBOARD_FILE`, which is mostly useful for
+//!   tests
+//!
+//! If it isn't set, the backend is detected from the environment, in the order
+//! above (minus `file`).
+//!
+//! [`ClipboardBackend`]: trait.ClipboardBackend.html
+//! [`PASSRS_CLIPBOARD_BACKEND`]: ../consts/static.PASSRS_CLIPBOARD_BACKEND.html
+//! [`PASSWORD_STORE_X_SELECTION`]: ../consts/static.PASSWORD_STORE_X_SELECTION.html
 
 use std::env;
-use std::io::Write;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::thread;
 use std::time;
 
-use data_encoding::HEXLOWER;
+use data_encoding::{BASE64, HEXLOWER};
+use once_cell::sync::OnceCell;
 use ring::digest;
 
-use crate::consts::{PASSWORD_STORE_CLIP_TIME, PASSWORD_STORE_X_SELECTION};
+use crate::consts::{
+    PASSRS_CLIPBOARD_BACKEND, PASSWORD_STORE_CLIP_TIME, PASSWORD_STORE_X_SELECTION,
+};
 use crate::{PassrsError, Result};
 
-/// Copies the `contents` to the clipboard, optionally `force`fully.
+static BACKEND: OnceCell<Box<dyn ClipboardBackend>> = OnceCell::new();
+
+/// A way of getting secrets into (and out of) the clipboard.
+pub trait ClipboardBackend: Send + Sync {
+    /// Replaces the contents of the clipboard with `contents`.
+    fn copy(&self, contents: &[u8]) -> Result<()>;
+
+    /// Returns the contents of the clipboard. Backends that can't read the
+    /// clipboard must return an error, and `false` from [`can_paste`].
+    ///
+    /// [`can_paste`]: #method.can_paste
+    fn paste(&self) -> Result<Vec<u8>>;
+
+    /// Clears the clipboard.
+    fn clear(&self) -> Result<()> {
+        self.copy(b"")
+    }
+
+    /// Whether [`paste`] works, so that the clipboard can be checked before
+    /// it's cleared.
+    ///
+    /// [`paste`]: #tymethod.paste
+    fn can_paste(&self) -> bool {
+        true
+    }
+}
+
+/// Returns the backend selected by [`PASSRS_CLIPBOARD_BACKEND`] or detected
+/// from the environment, or the one set with [`set_backend`].
+///
+/// [`PASSRS_CLIPBOARD_BACKEND`]: ../consts/static.PASSRS_CLIPBOARD_BACKEND.html
+/// [`set_backend`]: fn.set_backend.html
+pub fn backend() -> Result<&'static dyn ClipboardBackend> {
+    let backend = BACKEND.get_or_try_init(|| {
+        if PASSRS_CLIPBOARD_BACKEND.is_empty() {
+            self::backend_named(self::detect().ok_or(PassrsError::ClipFailed)?)
+        } else {
+            self::backend_named(&PASSRS_CLIPBOARD_BACKEND)
+        }
+    })?;
+
+    Ok(backend.as_ref())
+}
+
+/// Overrides the backend used for the rest of the process. This must be called
+/// before the clipboard is used, and is mostly useful for tests.
+pub fn set_backend(backend: Box<dyn ClipboardBackend>) -> Result<()> {
+    BACKEND
+        .set(backend)
+        .map_err(|_| "Clipboard backend was already initialized")?;
+
+    Ok(())
+}
+
+/// Returns the backend called `name` (see the [module documentation]).
 ///
-/// On Wayland, this uses `wl-copy`, and on X11, `xclip`.
+/// [module documentation]: index.html
+pub fn backend_named(name: &str) -> Result<Box<dyn ClipboardBackend>> {
+    let backend: Box<dyn ClipboardBackend> = match name {
+        "wayland" => Box::new(WaylandBackend),
+        "xclip" => Box::new(XclipBackend),
+        "xsel" => Box::new(XselBackend),
+        "tmux" => Box::new(TmuxBackend),
+        "osc52" => Box::new(Osc52Backend),
+        "command" => Box::new(CommandBackend::from_env()?),
+        "file" => Box::new(FileBackend::new(
+            env::var("PASSRS_CLIPBOARD_FILE").map_err(|_| "PASSRS_CLIPBOARD_FILE is not set")?,
+        )),
+        other => return Err(PassrsError::InvalidClipboardBackend(other.to_owned()).into()),
+    };
+
+    Ok(backend)
+}
+
+/// Returns the name of the backend that suits the environment best, if any.
+pub fn detect() -> Option<&'static str> {
+    let is_set = |var| env::var_os(var).filter(|v| !v.is_empty()).is_some();
+
+    if is_set("PASSRS_CLIPBOARD_COPY") {
+        Some("command")
+    } else if is_set("WAYLAND_DISPLAY") {
+        Some("wayland")
+    } else if is_set("DISPLAY") {
+        if self::in_path("xclip") || !self::in_path("xsel") {
+            Some("xclip")
+        } else {
+            Some("xsel")
+        }
+    } else if is_set("TMUX") {
+        Some("tmux")
+    } else if is_set("SSH_TTY") || is_set("SSH_CONNECTION") {
+        Some("osc52")
+    } else {
+        None
+    }
+}
+
+/// Copies the `contents` to the clipboard, optionally `force`fully, and spawns
+/// `passrs unclip` to clear it after [`PASSWORD_STORE_CLIP_TIME`] seconds.
+///
+/// [`PASSWORD_STORE_CLIP_TIME`]: ../consts/static.PASSWORD_STORE_CLIP_TIME.html
 pub fn clip<S>(contents: S, force: bool) -> Result<()>
 where
     S: AsRef<[u8]>,
 {
     let contents = contents.as_ref();
 
-    if env::var("WAYLAND_DISPLAY").is_ok() {
-        Command::new("wl-copy")
-            .arg("--trim-newline")
-            .stdin(Stdio::piped())
-            .spawn()
-            .map_err(|e| format!("Failed to spawn wl-copy: {}", e))?
-            .stdin
-            .ok_or("stdin wasn't captured")?
-            .write_all(contents)?;
-    } else if env::var("DISPLAY").is_ok() {
-        Command::new("xclip")
-            .args(&["-in", "-selection", &PASSWORD_STORE_X_SELECTION])
-            .stdin(Stdio::piped())
-            .spawn()
-            .map_err(|e| format!("Failed to spawn xclip: {}", e))?
-            .stdin
-            .ok_or("stdin wasn't captured")?
-            .write_all(contents)?;
-    } else {
-        return Err(PassrsError::ClipFailed.into());
-    }
+    self::backend()?.copy(contents)?;
 
     let hash = HEXLOWER.encode(digest::digest(&digest::SHA256, &contents).as_ref());
     let args = [
@@ -69,44 +170,295 @@ where
 }
 
 /// Retrieves the contents of the clipboard as a `Vec<u8>`.
-///
-/// On Wayland, this uses `wl-paste`, and on X11, `xclip`.
 pub fn paste() -> Result<Vec<u8>> {
-    let bytes = if env::var("WAYLAND_DISPLAY").is_ok() {
-        Command::new("wl-paste")
-            .arg("--no-newline")
-            .output()
-            .map_err(|e| format!("Failed to spawn wl-paste: {}", e))?
-            .stdout
-    } else if env::var("DISPLAY").is_ok() {
-        Command::new("xclip")
-            .args(&["-out", "-selection", &PASSWORD_STORE_X_SELECTION])
-            .output()
-            .map_err(|e| format!("Failed to spawn xclip: {}", e))?
-            .stdout
-    } else {
-        return Err(PassrsError::PasteFailed.into());
-    };
-
-    Ok(bytes)
+    self::backend()?.paste()
 }
 
 /// Clears the contents of the clipboard.
 pub fn clear() -> Result<()> {
-    if env::var("WAYLAND_DISPLAY").is_ok() {
-        Command::new("wl-copy").arg("--clear").spawn()?;
-    } else if env::var("DISPLAY").is_ok() {
-        Command::new("xclip")
-            .args(&["-in", "-selection", &PASSWORD_STORE_X_SELECTION])
-            .stdin(Stdio::piped())
-            .spawn()
-            .map_err(|e| format!("Failed to spawn xclip: {}", e))?
-            .stdin
-            .ok_or("stdin wasn't captured")?
-            .write_all(b"")?;
+    self::backend()?.clear()
+}
+
+/// Returns the OSC 52 escape sequence that sets the clipboard to `contents`.
+/// Inside tmux, the sequence is wrapped so that tmux passes it on to the
+/// terminal.
+pub fn osc52_sequence(contents: &[u8], in_tmux: bool) -> Vec<u8> {
+    let sequence = format!("\x1b]52;c;{}\x07", BASE64.encode(contents));
+
+    if in_tmux {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b")).into_bytes()
     } else {
-        // unsupported system
+        sequence.into_bytes()
+    }
+}
+
+/// `wl-copy` and `wl-paste`, for Wayland.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WaylandBackend;
+
+impl ClipboardBackend for WaylandBackend {
+    fn copy(&self, contents: &[u8]) -> Result<()> {
+        self::pipe_to(Command::new("wl-copy").arg("--trim-newline"), contents)
+    }
+
+    fn paste(&self) -> Result<Vec<u8>> {
+        self::output_of(Command::new("wl-paste").arg("--no-newline"))
+    }
+
+    fn clear(&self) -> Result<()> {
+        self::run(Command::new("wl-copy").arg("--clear"))
+    }
+}
+
+/// `xclip`, for X11.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct XclipBackend;
+
+impl ClipboardBackend for XclipBackend {
+    fn copy(&self, contents: &[u8]) -> Result<()> {
+        self::pipe_to(
+            Command::new("xclip").args(&["-in", "-selection", &PASSWORD_STORE_X_SELECTION]),
+            contents,
+        )
+    }
+
+    fn paste(&self) -> Result<Vec<u8>> {
+        self::output_of(Command::new("xclip").args(&[
+            "-out",
+            "-selection",
+            &PASSWORD_STORE_X_SELECTION,
+        ]))
+    }
+}
+
+/// `xsel`, for X11.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct XselBackend;
+
+impl XselBackend {
+    fn selection() -> &'static str {
+        match PASSWORD_STORE_X_SELECTION.as_str() {
+            "p" | "primary" => "--primary",
+            "sec" | "secondary" => "--secondary",
+            _ => "--clipboard",
+        }
+    }
+}
+
+impl ClipboardBackend for XselBackend {
+    fn copy(&self, contents: &[u8]) -> Result<()> {
+        self::pipe_to(
+            Command::new("xsel").args(&[XselBackend::selection(), "--input"]),
+            contents,
+        )
+    }
+
+    fn paste(&self) -> Result<Vec<u8>> {
+        self::output_of(Command::new("xsel").args(&[XselBackend::selection(), "--output"]))
+    }
+
+    fn clear(&self) -> Result<()> {
+        self::run(Command::new("xsel").args(&[XselBackend::selection(), "--clear"]))
+    }
+}
+
+/// The `passrs` buffer of the tmux server `passrs` runs under.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TmuxBackend;
+
+impl ClipboardBackend for TmuxBackend {
+    fn copy(&self, contents: &[u8]) -> Result<()> {
+        self::pipe_to(
+            Command::new("tmux").args(&["load-buffer", "-b", "passrs", "-"]),
+            contents,
+        )
+    }
+
+    fn paste(&self) -> Result<Vec<u8>> {
+        self::output_of(Command::new("tmux").args(&["save-buffer", "-b", "passrs", "-"]))
+    }
+
+    fn clear(&self) -> Result<()> {
+        self::run(Command::new("tmux").args(&["delete-buffer", "-b", "passrs"]))
+    }
+}
+
+/// OSC 52 escape sequences written to the terminal, which sets the clipboard
+/// of the machine the terminal runs on. Terminals don't let programs read the
+/// clipboard this way, so this backend can't paste.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Osc52Backend;
+
+impl ClipboardBackend for Osc52Backend {
+    fn copy(&self, contents: &[u8]) -> Result<()> {
+        let sequence = self::osc52_sequence(contents, env::var_os("TMUX").is_some());
+        let mut tty = OpenOptions::new()
+            .write(true)
+            .open("/dev/tty")
+            .map_err(|e| format!("Failed to open the terminal: {}", e))?;
+
+        tty.write_all(&sequence)?;
+
+        Ok(())
+    }
+
+    fn paste(&self) -> Result<Vec<u8>> {
+        Err(PassrsError::PasteFailed.into())
+    }
+
+    fn can_paste(&self) -> bool {
+        false
+    }
+}
+
+/// User-configured shell commands: `copy` reads the contents from stdin,
+/// `paste` writes them to stdout, and `clear` clears the clipboard (if not
+/// set, the empty string is copied instead).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandBackend {
+    pub copy: String,
+    pub paste: Option<String>,
+    pub clear: Option<String>,
+}
+
+impl CommandBackend {
+    /// Reads the commands from `PASSRS_CLIPBOARD_COPY`, `PASSRS_CLIPBOARD_PASTE`
+    /// and `PASSRS_CLIPBOARD_CLEAR`.
+    pub fn from_env() -> Result<CommandBackend> {
+        let var = |name| env::var(name).ok().filter(|cmd: &String| !cmd.is_empty());
+
+        Ok(CommandBackend {
+            copy: var("PASSRS_CLIPBOARD_COPY").ok_or("PASSRS_CLIPBOARD_COPY is not set")?,
+            paste: var("PASSRS_CLIPBOARD_PASTE"),
+            clear: var("PASSRS_CLIPBOARD_CLEAR"),
+        })
+    }
+
+    fn shell(command: &str) -> Command {
+        let mut shell = Command::new("sh");
+
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+impl ClipboardBackend for CommandBackend {
+    fn copy(&self, contents: &[u8]) -> Result<()> {
+        self::pipe_to(&mut CommandBackend::shell(&self.copy), contents)
+    }
+
+    fn paste(&self) -> Result<Vec<u8>> {
+        match &self.paste {
+            Some(paste) => self::output_of(&mut CommandBackend::shell(paste)),
+            None => Err(PassrsError::PasteFailed.into()),
+        }
+    }
+
+    fn clear(&self) -> Result<()> {
+        match &self.clear {
+            Some(clear) => self::run(&mut CommandBackend::shell(clear)),
+            None => self.copy(b""),
+        }
+    }
+
+    fn can_paste(&self) -> bool {
+        self.paste.is_some()
+    }
+}
+
+/// A "clipboard" that is just a file (readable only by its owner), so that
+/// tests can check what was copied, even from other processes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileBackend {
+    path: PathBuf,
+}
+
+impl FileBackend {
+    pub fn new<P>(path: P) -> FileBackend
+    where
+        P: Into<PathBuf>,
+    {
+        FileBackend { path: path.into() }
+    }
+}
+
+impl ClipboardBackend for FileBackend {
+    fn copy(&self, contents: &[u8]) -> Result<()> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.path)?
+            .write_all(contents)?;
+
+        Ok(())
+    }
+
+    fn paste(&self) -> Result<Vec<u8>> {
+        match fs::read(&self.path) {
+            Ok(contents) => Ok(contents),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn program(command: &Command) -> String {
+    command.get_program().to_string_lossy().into_owned()
+}
+
+/// Runs `command` with `contents` on its stdin.
+fn pipe_to(command: &mut Command, contents: &[u8]) -> Result<()> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to spawn {}: {}", self::program(command), e))?;
+
+    child
+        .stdin
+        .take()
+        .ok_or("stdin wasn't captured")?
+        .write_all(contents)?;
+
+    if !child.wait()?.success() {
+        return Err(PassrsError::ClipFailed.into());
     }
 
     Ok(())
 }
+
+/// Runs `command` and returns its stdout.
+fn output_of(command: &mut Command) -> Result<Vec<u8>> {
+    let output = command
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Failed to spawn {}: {}", self::program(command), e))?;
+
+    if !output.status.success() {
+        return Err(PassrsError::PasteFailed.into());
+    }
+
+    Ok(output.stdout)
+}
+
+/// Runs `command`.
+fn run(command: &mut Command) -> Result<()> {
+    let status = command
+        .status()
+        .map_err(|e| format!("Failed to spawn {}: {}", self::program(command), e))?;
+
+    if !status.success() {
+        return Err(PassrsError::ClipFailed.into());
+    }
+
+    Ok(())
+}
+
+/// Returns whether `program` is an executable in `$PATH`.
+fn in_path(program: &str) -> bool {
+    match env::var_os("PATH") {
+        Some(path) => env::split_paths(&path).any(|dir| dir.join(program).is_file()),
+        None => false,
+    }
+}
@@ -14,6 +14,9 @@ pub const DIGITS: &[u8] = b"0123456789"; // [:digit:]
 pub const ALPHA_UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ"; // [:upper:]
 pub const ALPHA_LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz"; // [:lower:]
 pub const SPECIAL: &[u8] = b"!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~"; // [:punct:]
+/// Characters that are easily mistaken for one another, left out of generated
+/// passwords with `--exclude-ambiguous`.
+pub const AMBIGUOUS: &[u8] = b"0O1Il|";
 
 pub static EDITOR: Lazy<String> = Lazy::new(|| {
     let editor = if let Ok(editor) = env::var("EDITOR") {
@@ -31,11 +34,36 @@ pub static EDITOR: Lazy<String> = Lazy::new(|| {
     }
 });
 pub static HOME: Lazy<String> = Lazy::new(|| env::var("HOME").expect("HOME was not set"));
+/// Marks a (sub)store whose entries are encrypted without revealing the key IDs
+/// of their recipients. Lives next to the store's `.gpg-id`.
+pub const HIDDEN_RECIPIENTS_FILE: &str = ".gpg-id.hidden";
+/// Extra gpg options for the entries of a (sub)store, on top of
+/// `PASSWORD_STORE_GPG_OPTS`. Lives next to the store's `.gpg-id`.
+pub const GPG_OPTS_FILE: &str = ".gpg-opts";
+/// The password policy for `passrs generate` in a (sub)store. The closest one to
+/// an entry applies.
+pub const POLICY_FILE: &str = ".passrs-policy";
 pub static GPG_ID_FILE: Lazy<PathBuf> = Lazy::new(|| PASSWORD_STORE_DIR.join(".gpg-id"));
+pub static AGE_RECIPIENTS_FILE: Lazy<PathBuf> =
+    Lazy::new(|| PASSWORD_STORE_DIR.join(".age-recipients"));
 pub static PASSRS_UNCLIP_HASH: Lazy<String> =
     Lazy::new(|| env::var("PASSRS_UNCLIP_HASH").unwrap_or_default());
 pub static PASSRS_GIT_BINARY: Lazy<String> =
     Lazy::new(|| env::var("PASSRS_GIT_BINARY").unwrap_or_else(|_| String::from("git")));
+pub static PASSRS_CLIPBOARD_BACKEND: Lazy<String> =
+    Lazy::new(|| env::var("PASSRS_CLIPBOARD_BACKEND").unwrap_or_default());
+pub static PASSRS_CRYPTO_BACKEND: Lazy<String> =
+    Lazy::new(|| env::var("PASSRS_CRYPTO_BACKEND").unwrap_or_else(|_| String::from("gpgme")));
+#[cfg(feature = "openpgp")]
+pub static PASSRS_OPENPGP_CERTS: Lazy<String> =
+    Lazy::new(|| env::var("PASSRS_OPENPGP_CERTS").unwrap_or_default());
+#[cfg(feature = "openpgp")]
+pub static PASSRS_OPENPGP_SECRET_KEYS: Lazy<String> =
+    Lazy::new(|| env::var("PASSRS_OPENPGP_SECRET_KEYS").unwrap_or_default());
+#[cfg(feature = "age")]
+pub static PASSRS_AGE_IDENTITIES: Lazy<String> = Lazy::new(|| {
+    env::var("PASSRS_AGE_IDENTITIES").unwrap_or_else(|_| format!("{}/.passage/identities", *HOME))
+});
 pub static STORE_STRING: Lazy<String> = Lazy::new(|| PASSWORD_STORE_DIR.display().to_string());
 // if the store_string doesn't end with a '/', account for that (subpaths *will* have the '/')
 pub static STORE_LEN: Lazy<usize> = Lazy::new(|| {
@@ -59,6 +87,13 @@ pub static PASSWORD_STORE_KEY: Lazy<Vec<String>> = Lazy::new(|| {
         .map(ToOwned::to_owned)
         .collect()
 });
+pub static PASSWORD_STORE_GPG_OPTS: Lazy<Vec<String>> = Lazy::new(|| {
+    env::var("PASSWORD_STORE_GPG_OPTS")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(ToOwned::to_owned)
+        .collect()
+});
 pub static PASSWORD_STORE_X_SELECTION: Lazy<String> =
     Lazy::new(|| match env::var("PASSWORD_STORE_X_SELECTION") {
         Ok(sel) => match sel.as_ref() {
@@ -70,6 +105,48 @@ pub static PASSWORD_STORE_X_SELECTION: Lazy<String> =
     });
 pub static PASSWORD_STORE_CLIP_TIME: Lazy<String> =
     Lazy::new(|| env::var("PASSWORD_STORE_CLIP_TIME").unwrap_or_else(|_| "45".to_owned()));
+pub static PASSWORD_STORE_ENABLE_EXTENSIONS: Lazy<bool> =
+    Lazy::new(|| env::var("PASSWORD_STORE_ENABLE_EXTENSIONS").as_deref() == Ok("true"));
+pub static PASSWORD_STORE_EXTENSIONS_DIR: Lazy<PathBuf> =
+    Lazy::new(|| match env::var("PASSWORD_STORE_EXTENSIONS_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PASSWORD_STORE_DIR.join(".extensions"),
+    });
+pub static PASSRS_SYSTEM_EXTENSION_DIR: Lazy<PathBuf> = Lazy::new(|| {
+    PathBuf::from(
+        env::var("PASSRS_SYSTEM_EXTENSION_DIR")
+            .unwrap_or_else(|_| "/usr/lib/password-store/extensions".to_owned()),
+    )
+});
+pub static PASSRS_AGENT_SOCKET: Lazy<PathBuf> = Lazy::new(|| {
+    if let Ok(socket) = env::var("PASSRS_AGENT_SOCKET") {
+        PathBuf::from(socket)
+    } else if let Ok(dir) = env::var("XDG_RUNTIME_DIR") {
+        PathBuf::from(dir).join("passrs-agent.sock")
+    } else {
+        let uid = unsafe { libc::getuid() };
+
+        PathBuf::from(format!("/tmp/passrs-agent-{}.sock", uid))
+    }
+});
+pub static PASSRS_AGENT_TIMEOUT: Lazy<u64> = Lazy::new(|| {
+    env::var("PASSRS_AGENT_TIMEOUT")
+        .unwrap_or_else(|_| "600".to_owned())
+        .parse()
+        .expect("agent timeout was not a valid number of seconds")
+});
+pub static PASSRS_LOCK_TIMEOUT: Lazy<u64> = Lazy::new(|| {
+    env::var("PASSRS_LOCK_TIMEOUT")
+        .unwrap_or_else(|_| "10".to_owned())
+        .parse()
+        .expect("lock timeout was not a valid number of seconds")
+});
+pub static PASSRS_GREP_CACHE_TTL: Lazy<u64> = Lazy::new(|| {
+    env::var("PASSRS_GREP_CACHE_TTL")
+        .unwrap_or_else(|_| "0".to_owned())
+        .parse()
+        .expect("grep cache TTL was not a valid number of seconds")
+});
 pub static PASSWORD_STORE_UMASK: Lazy<u32> = Lazy::new(|| {
     u32::from_str_radix(
         &env::var("PASSWORD_STORE_UMASK").unwrap_or_else(|_| "077".to_owned()),
@@ -83,18 +160,25 @@ pub static PASSWORD_STORE_GENERATED_LENGTH: Lazy<usize> = Lazy::new(|| {
         .parse::<usize>()
         .expect("length was not a usize")
 });
-pub static PASSWORD_STORE_CHARACTER_SET: Lazy<Vec<u8>> =
+pub static PASSWORD_STORE_CHARACTER_SET: Lazy<Vec<char>> =
     Lazy::new(|| match env::var("PASSWORD_STORE_CHARACTER_SET") {
-        Ok(set) => set.bytes().collect(),
-        Err(_) => [DIGITS, ALPHA_LOWER, ALPHA_UPPER, SPECIAL].concat(),
+        Ok(set) => set.chars().collect(),
+        Err(_) => [DIGITS, ALPHA_LOWER, ALPHA_UPPER, SPECIAL]
+            .concat()
+            .into_iter()
+            .map(char::from)
+            .collect(),
     });
-pub static PASSWORD_STORE_CHARACTER_SET_NO_SYMBOLS: Lazy<Vec<u8>> =
-    Lazy::new(
-        || match env::var("PASSWORD_STORE_CHARACTER_SET_NO_SYMBOLS") {
-            Ok(set) => set.bytes().collect(),
-            Err(_) => [DIGITS, ALPHA_LOWER, ALPHA_UPPER].concat(),
-        },
-    );
+pub static PASSWORD_STORE_CHARACTER_SET_NO_SYMBOLS: Lazy<Vec<char>> = Lazy::new(|| match env::var(
+    "PASSWORD_STORE_CHARACTER_SET_NO_SYMBOLS",
+) {
+    Ok(set) => set.chars().collect(),
+    Err(_) => [DIGITS, ALPHA_LOWER, ALPHA_UPPER]
+        .concat()
+        .into_iter()
+        .map(char::from)
+        .collect(),
+});
 pub static PASSWORD_STORE_SIGNING_KEY: Lazy<Vec<String>> = Lazy::new(|| {
     env::var("PASSWORD_STORE_SIGNING_KEY")
         .unwrap_or_default()
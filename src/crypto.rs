@@ -0,0 +1,651 @@
+//! Encryption backends
+//!
+//! # crypto
+//!
+//! This module houses the [`CryptoBackend`] trait, which is used by the
+//! encryption and decryption helpers in [`util`]. The backend is selected with
+//! the [`PASSRS_CRYPTO_BACKEND`] environment variable:
+//!
+//! * `gpgme` (default): uses [gpgme], and thus the user's gpg-agent and keyring
+//! * `openpgp`: a pure-Rust OpenPGP implementation using [rpgp], which does not
+//!   require a running gpg-agent. Requires the `openpgp` feature to be enabled.
+//!
+//! Entries ending in `.age` (in substores initialized with `passrs init --age`)
+//! are always handled by the [age] backend instead, which requires the `age`
+//! feature to be enabled.
+//!
+//! [`CryptoBackend`]: trait.CryptoBackend.html
+//! [`util`]: ../util/index.html
+//! [`PASSRS_CRYPTO_BACKEND`]: ../consts/static.PASSRS_CRYPTO_BACKEND.html
+//! [gpgme]: https://docs.rs/gpgme
+//! [rpgp]: https://docs.rs/pgp
+//! [age]: https://age-encryption.org
+
+use gpgme::{Context, Data, EncryptFlags, PinentryMode, Protocol};
+use once_cell::sync::OnceCell;
+
+use crate::consts::{PASSRS_CRYPTO_BACKEND, PASSWORD_STORE_GPG_OPTS};
+use crate::util::{self, EntryKind};
+use crate::{PassrsError, Result};
+
+static BACKEND: OnceCell<Box<dyn CryptoBackend>> = OnceCell::new();
+static GPG_OPTIONS: OnceCell<GpgOptions> = OnceCell::new();
+#[cfg(feature = "age")]
+static AGE_BACKEND: OnceCell<AgeBackend> = OnceCell::new();
+
+/// A backend capable of encrypting and decrypting entries in the store.
+pub trait CryptoBackend: Send + Sync {
+    /// Decrypts `ciphertext`, returning the plaintext.
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>>;
+
+    /// Encrypts `plaintext` to every one of `recipients` (as listed in a
+    /// `.gpg-id`). Must return an error if any recipient can't be resolved.
+    fn encrypt(&self, recipients: &[String], plaintext: &[u8]) -> Result<Vec<u8>>;
+
+    /// Like [`encrypt`], but without revealing which keys the ciphertext was
+    /// encrypted to (gpg's `--throw-keyids`). Backends that can't do this must
+    /// return an error rather than silently revealing the recipients.
+    ///
+    /// [`encrypt`]: #tymethod.encrypt
+    fn encrypt_hidden(&self, recipients: &[String], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let _ = (recipients, plaintext);
+
+        Err("This crypto backend can't hide recipients".into())
+    }
+
+    /// Like [`encrypt`], or [`encrypt_hidden`] if `hidden` is set, but with
+    /// `options` in place of those from `PASSWORD_STORE_GPG_OPTS`, for entries
+    /// of a (sub)store with its own [`GPG_OPTS_FILE`]. Only gpgme understands
+    /// gpg's options; other backends ignore them, as they ignore
+    /// `PASSWORD_STORE_GPG_OPTS`.
+    ///
+    /// [`encrypt`]: #tymethod.encrypt
+    /// [`encrypt_hidden`]: #method.encrypt_hidden
+    /// [`GPG_OPTS_FILE`]: ../consts/constant.GPG_OPTS_FILE.html
+    fn encrypt_with_options(
+        &self,
+        recipients: &[String],
+        plaintext: &[u8],
+        options: &GpgOptions,
+        hidden: bool,
+    ) -> Result<Vec<u8>> {
+        let _ = options;
+
+        if hidden {
+            self.encrypt_hidden(recipients, plaintext)
+        } else {
+            self.encrypt(recipients, plaintext)
+        }
+    }
+
+    /// Resolves `recipient` and returns the user ID (preferably the email
+    /// address) that should be written to `.gpg-id`.
+    fn user_id(&self, recipient: &str) -> Result<String>;
+}
+
+/// Returns the backend selected by [`PASSRS_CRYPTO_BACKEND`], or the one set
+/// with [`set_backend`].
+///
+/// [`PASSRS_CRYPTO_BACKEND`]: ../consts/static.PASSRS_CRYPTO_BACKEND.html
+/// [`set_backend`]: fn.set_backend.html
+pub fn backend() -> Result<&'static dyn CryptoBackend> {
+    let backend = BACKEND.get_or_try_init(|| -> Result<Box<dyn CryptoBackend>> {
+        match PASSRS_CRYPTO_BACKEND.as_str() {
+            "gpgme" => Ok(Box::new(GpgmeBackend)),
+            #[cfg(feature = "openpgp")]
+            "openpgp" => Ok(Box::new(openpgp::OpenPgpBackend::new()?)),
+            other => Err(PassrsError::InvalidCryptoBackend(other.to_owned()).into()),
+        }
+    })?;
+
+    Ok(backend.as_ref())
+}
+
+/// Returns the backend responsible for entries of `kind`: [`backend`] for
+/// OpenPGP entries, and [`AgeBackend`] for age entries.
+///
+/// [`backend`]: fn.backend.html
+/// [`AgeBackend`]: struct.AgeBackend.html
+pub fn backend_for(kind: EntryKind) -> Result<&'static dyn CryptoBackend> {
+    match kind {
+        EntryKind::Gpg => self::backend(),
+        #[cfg(feature = "age")]
+        EntryKind::Age => Ok(AGE_BACKEND.get_or_init(|| AgeBackend)),
+        #[cfg(not(feature = "age"))]
+        EntryKind::Age => Err(PassrsError::InvalidCryptoBackend(String::from("age")).into()),
+    }
+}
+
+/// Overrides the backend used for the rest of the process. This must be called
+/// before anything is encrypted or decrypted, and is mostly useful for tests.
+pub fn set_backend(backend: Box<dyn CryptoBackend>) -> Result<()> {
+    BACKEND
+        .set(backend)
+        .map_err(|_| "Crypto backend was already initialized")?;
+
+    Ok(())
+}
+
+/// The default backend, backed by [gpgme].
+///
+/// [gpgme]: https://docs.rs/gpgme
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GpgmeBackend;
+
+impl CryptoBackend for GpgmeBackend {
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let mut ctx = self::gpg_context()?;
+        let mut cipher = Data::from_bytes(ciphertext)?;
+        let mut plain = Vec::new();
+
+        ctx.decrypt(&mut cipher, &mut plain)?;
+
+        Ok(plain)
+    }
+
+    fn encrypt(&self, recipients: &[String], plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.encrypt_with_options(recipients, plaintext, self::gpg_options()?, false)
+    }
+
+    fn encrypt_hidden(&self, recipients: &[String], plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.encrypt_with_options(recipients, plaintext, self::gpg_options()?, true)
+    }
+
+    fn encrypt_with_options(
+        &self,
+        recipients: &[String],
+        plaintext: &[u8],
+        options: &GpgOptions,
+        hidden: bool,
+    ) -> Result<Vec<u8>> {
+        let mut ctx = options.context()?;
+        let recipients = [recipients, &options.hidden_recipients].concat();
+        let keys = util::get_encryption_keys(&mut ctx, &recipients)?;
+        let mut flags = options.encrypt_flags();
+        let mut cipher = Vec::new();
+
+        if keys.is_empty() {
+            return Err(PassrsError::NoPrivateKeyFound.into());
+        }
+
+        if hidden {
+            flags |= EncryptFlags::THROW_KEYIDS;
+        }
+
+        ctx.encrypt_with_flags(&keys, plaintext, &mut cipher, flags)?;
+
+        Ok(cipher)
+    }
+
+    fn user_id(&self, recipient: &str) -> Result<String> {
+        let mut ctx = self::gpg_context()?;
+        let key = util::get_encryption_keys(&mut ctx, &[recipient.to_owned()])?
+            .pop()
+            .ok_or_else(|| PassrsError::NoPublicKeyFound(recipient.to_owned()))?;
+        let email = key
+            .user_ids()
+            .next()
+            .ok_or("Public key didn't have any user IDs")?
+            .email();
+
+        match email {
+            Ok(email) if !email.is_empty() => Ok(email.to_owned()),
+            _ => Ok(recipient.to_owned()),
+        }
+    }
+}
+
+/// The subset of gpg's options that can be expressed through gpgme, as parsed
+/// from [`PASSWORD_STORE_GPG_OPTS`] and a (sub)store's [`GPG_OPTS_FILE`]. Like
+/// `pass`, entries are encrypted without compression and without `encrypt-to`
+/// keys unless specified otherwise.
+///
+/// [`PASSWORD_STORE_GPG_OPTS`]: ../consts/static.PASSWORD_STORE_GPG_OPTS.html
+/// [`GPG_OPTS_FILE`]: ../consts/constant.GPG_OPTS_FILE.html
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GpgOptions {
+    /// `--armor`, `-a`
+    pub armor: bool,
+    /// `--trust-model`, `--always-trust`
+    pub trust_model: Option<String>,
+    /// `--throw-keyids`, implied by any hidden recipient
+    pub throw_keyids: bool,
+    /// `--hidden-recipient`, `-R`, `--hidden-encrypt-to`
+    pub hidden_recipients: Vec<String>,
+    /// `--compress-algo`, `-z`
+    pub compress: bool,
+    /// `--pinentry-mode`
+    pub pinentry_mode: Option<PinentryMode>,
+    /// `--homedir`
+    pub homedir: Option<String>,
+}
+
+impl GpgOptions {
+    /// Parses gpg command line options. Options that don't change how entries
+    /// are encrypted (like `--quiet`) are ignored; any other option is an
+    /// error, so that settings meant to harden gpg aren't silently dropped.
+    pub fn parse<S>(opts: &[S]) -> Result<GpgOptions>
+    where
+        S: AsRef<str>,
+    {
+        let mut options = GpgOptions::default();
+        let mut opts = opts.iter().map(AsRef::as_ref);
+
+        while let Some(opt) = opts.next() {
+            let (name, inline) = match opt.find('=') {
+                Some(idx) if opt.starts_with("--") => (&opt[..idx], Some(&opt[idx + 1..])),
+                _ => (opt, None),
+            };
+            let mut value = || {
+                inline
+                    .or_else(|| opts.next())
+                    .ok_or_else(|| format!("Option '{}' requires a value", name))
+            };
+
+            match name {
+                "--armor" | "-a" => options.armor = true,
+                "--no-armor" => options.armor = false,
+                "--always-trust" => options.trust_model = Some(String::from("always")),
+                "--trust-model" => {
+                    let model = value()?;
+
+                    if !matches!(
+                        model,
+                        "always" | "direct" | "pgp" | "classic" | "tofu" | "tofu+pgp" | "auto"
+                    ) {
+                        return Err(format!("Unknown trust model '{}'", model).into());
+                    }
+
+                    options.trust_model = Some(model.to_owned());
+                }
+                "--throw-keyids" | "--throw-keyid" => options.throw_keyids = true,
+                "--no-throw-keyids" | "--no-throw-keyid" => options.throw_keyids = false,
+                "--hidden-recipient" | "-R" | "--hidden-encrypt-to" => {
+                    options.hidden_recipients.push(value()?.to_owned());
+                }
+                "--compress-algo" | "--compression-algo" => {
+                    options.compress = !matches!(
+                        value()?.to_ascii_lowercase().as_str(),
+                        "none" | "uncompressed" | "0"
+                    )
+                }
+                "-z" | "--compress-level" => options.compress = value()?.parse::<u8>()? != 0,
+                "--pinentry-mode" => {
+                    options.pinentry_mode = Some(match value()? {
+                        "default" => PinentryMode::Default,
+                        "ask" => PinentryMode::Ask,
+                        "cancel" => PinentryMode::Cancel,
+                        "error" => PinentryMode::Error,
+                        "loopback" => PinentryMode::Loopback,
+                        other => return Err(format!("Unknown pinentry mode '{}'", other).into()),
+                    })
+                }
+                "--homedir" => options.homedir = Some(value()?.to_owned()),
+                "--quiet" | "-q" | "--yes" | "--batch" | "--no-tty" | "--verbose" | "-v"
+                | "--no-encrypt-to" => {}
+                _ => return Err(format!("Unsupported gpg option '{}'", opt).into()),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// The flags to pass to gpgme when encrypting.
+    pub fn encrypt_flags(&self) -> EncryptFlags {
+        let mut flags = EncryptFlags::NO_ENCRYPT_TO;
+
+        if self.trust_model.as_deref() == Some("always") {
+            flags |= EncryptFlags::ALWAYS_TRUST;
+        }
+
+        if self.throw_keyids || !self.hidden_recipients.is_empty() {
+            flags |= EncryptFlags::THROW_KEYIDS;
+        }
+
+        if !self.compress {
+            flags |= EncryptFlags::NO_COMPRESS;
+        }
+
+        flags
+    }
+
+    /// Creates a gpgme `Context` configured according to these options.
+    pub fn context(&self) -> Result<Context> {
+        let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+
+        self.apply(&mut ctx)?;
+
+        Ok(ctx)
+    }
+
+    /// Applies the options that gpgme stores in its `Context`.
+    pub fn apply(&self, ctx: &mut Context) -> Result<()> {
+        ctx.set_armor(self.armor);
+
+        if let Some(mode) = self.pinentry_mode {
+            ctx.set_pinentry_mode(mode)?;
+        }
+
+        if let Some(homedir) = &self.homedir {
+            ctx.set_engine_home_dir(homedir.as_str())?;
+        }
+
+        // `always` is an encryption flag; any other model is left to gpg
+        match self.trust_model.as_deref() {
+            Some("always") | None => {}
+            Some(model) => ctx.set_flag("trust-model", model)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the options parsed from [`PASSWORD_STORE_GPG_OPTS`].
+///
+/// [`PASSWORD_STORE_GPG_OPTS`]: ../consts/static.PASSWORD_STORE_GPG_OPTS.html
+pub fn gpg_options() -> Result<&'static GpgOptions> {
+    GPG_OPTIONS.get_or_try_init(|| {
+        GpgOptions::parse(&PASSWORD_STORE_GPG_OPTS)
+            .map_err(|e| format!("Invalid PASSWORD_STORE_GPG_OPTS: {}", e).into())
+    })
+}
+
+/// Creates a gpgme `Context` configured according to
+/// [`PASSWORD_STORE_GPG_OPTS`].
+///
+/// [`PASSWORD_STORE_GPG_OPTS`]: ../consts/static.PASSWORD_STORE_GPG_OPTS.html
+pub fn gpg_context() -> Result<Context> {
+    self::gpg_options()?.context()
+}
+
+#[cfg(feature = "openpgp")]
+pub use self::openpgp::OpenPgpBackend;
+
+#[cfg(feature = "openpgp")]
+mod openpgp {
+    use std::fs;
+    use std::io::{self, Write};
+
+    use data_encoding::HEXUPPER;
+    use pgp::crypto::sym::SymmetricKeyAlgorithm;
+    use pgp::ser::Serialize;
+    use pgp::types::PublicKeyTrait;
+    use pgp::{Deserializable, Message, SignedPublicKey, SignedSecretKey};
+    use termion::input::TermRead;
+
+    use super::CryptoBackend;
+    use crate::consts::{PASSRS_OPENPGP_CERTS, PASSRS_OPENPGP_SECRET_KEYS};
+    use crate::{PassrsError, Result};
+
+    /// A pure-Rust backend, backed by [rpgp]. Public keys are read from the
+    /// armored keyring at [`PASSRS_OPENPGP_CERTS`], and secret keys from
+    /// [`PASSRS_OPENPGP_SECRET_KEYS`]. Secret keys protected by a passphrase
+    /// are unlocked by prompting on the terminal.
+    ///
+    /// [rpgp]: https://docs.rs/pgp
+    /// [`PASSRS_OPENPGP_CERTS`]: ../consts/static.PASSRS_OPENPGP_CERTS.html
+    /// [`PASSRS_OPENPGP_SECRET_KEYS`]: ../consts/static.PASSRS_OPENPGP_SECRET_KEYS.html
+    #[derive(Debug)]
+    pub struct OpenPgpBackend {
+        certs: Vec<SignedPublicKey>,
+        secret_keys: Vec<SignedSecretKey>,
+    }
+
+    impl OpenPgpBackend {
+        pub fn new() -> Result<OpenPgpBackend> {
+            let mut certs = Vec::new();
+            let mut secret_keys = Vec::new();
+
+            if !PASSRS_OPENPGP_CERTS.is_empty() {
+                let file = fs::File::open(&*PASSRS_OPENPGP_CERTS)
+                    .map_err(|e| format!("Failed to open '{}': {}", *PASSRS_OPENPGP_CERTS, e))?;
+
+                for cert in SignedPublicKey::from_armor_many(file)?.0 {
+                    certs.push(cert?);
+                }
+            }
+
+            if !PASSRS_OPENPGP_SECRET_KEYS.is_empty() {
+                let file = fs::File::open(&*PASSRS_OPENPGP_SECRET_KEYS).map_err(|e| {
+                    format!("Failed to open '{}': {}", *PASSRS_OPENPGP_SECRET_KEYS, e)
+                })?;
+
+                for key in SignedSecretKey::from_armor_many(file)?.0 {
+                    let key = key?;
+
+                    // Our own keys can be encrypted to, too
+                    certs.push(SignedPublicKey::from(key.clone()));
+                    secret_keys.push(key);
+                }
+            }
+
+            Ok(OpenPgpBackend { certs, secret_keys })
+        }
+
+        /// Finds the certificate matching `recipient`, which may be the
+        /// fingerprint or key ID of its primary key or one of its subkeys, one
+        /// of its user IDs, or the email address of one of its user IDs. Unlike
+        /// gpg, parts of user IDs don't match, so that a recipient can't
+        /// resolve to someone else's key; neither can it match more than one.
+        fn find_cert(&self, recipient: &str) -> Result<&SignedPublicKey> {
+            let hex = recipient.trim_start_matches("0x").to_ascii_uppercase();
+            let email = recipient.trim_start_matches('<').trim_end_matches('>');
+            let matches_key = |fingerprint: &[u8]| {
+                let fingerprint = HEXUPPER.encode(fingerprint);
+
+                // Long and short key IDs are the end of the fingerprint
+                fingerprint == hex || (matches!(hex.len(), 8 | 16) && fingerprint.ends_with(&hex))
+            };
+            let mut matches: Vec<&SignedPublicKey> = Vec::new();
+
+            for cert in &self.certs {
+                let matches_id = matches_key(cert.fingerprint().as_bytes())
+                    || cert
+                        .public_subkeys
+                        .iter()
+                        .any(|sub| matches_key(sub.key.fingerprint().as_bytes()));
+                let matches_user = cert.details.users.iter().any(|user| {
+                    let user_id = user.id.id().to_string();
+
+                    user_id == recipient || self::email(&user_id) == Some(email)
+                });
+                // Our own keys are listed as both certificates and secret keys
+                let seen = matches
+                    .iter()
+                    .any(|other| other.fingerprint() == cert.fingerprint());
+
+                if (matches_id || matches_user) && !seen {
+                    matches.push(cert);
+                }
+            }
+
+            match matches.as_slice() {
+                [cert] => Ok(cert),
+                [] => Err(PassrsError::NoPublicKeyFound(recipient.to_owned()).into()),
+                _ => Err(PassrsError::AmbiguousRecipient(
+                    recipient.to_owned(),
+                    matches
+                        .iter()
+                        .map(|cert| HEXUPPER.encode(cert.fingerprint().as_bytes()))
+                        .collect(),
+                )
+                .into()),
+            }
+        }
+    }
+
+    impl CryptoBackend for OpenPgpBackend {
+        fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+            let message = if ciphertext.starts_with(b"-----BEGIN PGP MESSAGE-----") {
+                Message::from_armor_single(ciphertext)?.0
+            } else {
+                Message::from_bytes(ciphertext)?
+            };
+            let keys: Vec<&SignedSecretKey> = self.secret_keys.iter().collect();
+
+            if keys.is_empty() {
+                return Err(PassrsError::NoPrivateKeyFound.into());
+            }
+
+            let (message, _) = message.decrypt(self::passphrase, &keys)?;
+            let plain = message
+                .get_content()?
+                .ok_or("Decrypted message did not contain any data")?;
+
+            Ok(plain)
+        }
+
+        fn encrypt(&self, recipients: &[String], plaintext: &[u8]) -> Result<Vec<u8>> {
+            let mut keys = Vec::new();
+
+            for recipient in recipients
+                .iter()
+                .map(|r| r.trim())
+                .filter(|r| !r.is_empty())
+            {
+                let subkey = self
+                    .find_cert(recipient)?
+                    .public_subkeys
+                    .iter()
+                    .find(|sub| sub.key.is_encryption_key())
+                    .ok_or_else(|| PassrsError::NoPublicKeyFound(recipient.to_owned()))?;
+
+                keys.push(&subkey.key);
+            }
+
+            if keys.is_empty() {
+                return Err(PassrsError::NoPrivateKeyFound.into());
+            }
+
+            let cipher = Message::new_literal_bytes("", plaintext)
+                .encrypt_to_keys_seipdv1(rand::thread_rng(), SymmetricKeyAlgorithm::AES256, &keys)?
+                .to_bytes()?;
+
+            Ok(cipher)
+        }
+
+        fn user_id(&self, recipient: &str) -> Result<String> {
+            let cert = self.find_cert(recipient)?;
+            let user_id = cert
+                .details
+                .users
+                .first()
+                .ok_or("Public key didn't have any user IDs")?
+                .id
+                .id()
+                .to_string();
+            let email = self::email(&user_id).map(ToOwned::to_owned);
+
+            Ok(email.unwrap_or(user_id))
+        }
+    }
+
+    /// Returns the email address of a user ID: "Name <email>" -> "email".
+    fn email(user_id: &str) -> Option<&str> {
+        match (user_id.rfind('<'), user_id.rfind('>')) {
+            (Some(start), Some(end)) if start < end => Some(&user_id[start + 1..end]),
+            _ => None,
+        }
+    }
+
+    fn passphrase() -> String {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        let mut stderr = io::stderr();
+
+        let _ = write!(stderr, "Enter passphrase for secret key: ");
+        let _ = stderr.flush();
+
+        let passphrase = stdin.read_passwd(&mut stderr).ok().flatten();
+        let _ = writeln!(stderr);
+
+        passphrase.unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "age")]
+pub use self::age::AgeBackend;
+
+#[cfg(feature = "age")]
+mod age {
+    use std::io::{Read, Write};
+    use std::str::FromStr;
+
+    use ::age::x25519;
+    use ::age::{Decryptor, Encryptor, Identity, IdentityFile, Recipient};
+
+    use super::CryptoBackend;
+    use crate::consts::PASSRS_AGE_IDENTITIES;
+    use crate::{PassrsError, Result};
+
+    /// A backend for [age], compatible with [passage]. Entries are encrypted to
+    /// the X25519 recipients listed in `.age-recipients`, and decrypted with
+    /// the identities in [`PASSRS_AGE_IDENTITIES`].
+    ///
+    /// [age]: https://age-encryption.org
+    /// [passage]: https://github.com/FiloSottile/passage
+    /// [`PASSRS_AGE_IDENTITIES`]: ../consts/static.PASSRS_AGE_IDENTITIES.html
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct AgeBackend;
+
+    impl CryptoBackend for AgeBackend {
+        fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+            let identities = IdentityFile::from_file(PASSRS_AGE_IDENTITIES.clone())
+                .map_err(|e| format!("Failed to open '{}': {}", *PASSRS_AGE_IDENTITIES, e))?
+                .into_identities()?;
+
+            if identities.is_empty() {
+                return Err(PassrsError::NoPrivateKeyFound.into());
+            }
+
+            let mut reader = Decryptor::new(ciphertext)?
+                .decrypt(identities.iter().map(|i| i.as_ref() as &dyn Identity))?;
+            let mut plain = Vec::new();
+
+            reader.read_to_end(&mut plain)?;
+
+            Ok(plain)
+        }
+
+        fn encrypt(&self, recipients: &[String], plaintext: &[u8]) -> Result<Vec<u8>> {
+            let mut keys = Vec::new();
+
+            for recipient in recipients
+                .iter()
+                .map(|r| r.trim())
+                .filter(|r| !r.is_empty())
+            {
+                keys.push(self::parse_recipient(recipient)?);
+            }
+
+            if keys.is_empty() {
+                return Err(PassrsError::NoPrivateKeyFound.into());
+            }
+
+            let encryptor = Encryptor::with_recipients(keys.iter().map(|k| k as &dyn Recipient))?;
+            let mut cipher = Vec::new();
+            let mut writer = encryptor.wrap_output(&mut cipher)?;
+
+            writer.write_all(plaintext)?;
+            writer.finish()?;
+
+            Ok(cipher)
+        }
+
+        // X25519 stanzas don't identify their recipient to begin with
+        fn encrypt_hidden(&self, recipients: &[String], plaintext: &[u8]) -> Result<Vec<u8>> {
+            self.encrypt(recipients, plaintext)
+        }
+
+        fn user_id(&self, recipient: &str) -> Result<String> {
+            self::parse_recipient(recipient)?;
+
+            Ok(recipient.to_owned())
+        }
+    }
+
+    fn parse_recipient(recipient: &str) -> Result<x25519::Recipient> {
+        x25519::Recipient::from_str(recipient)
+            .map_err(|_| PassrsError::NoPublicKeyFound(recipient.to_owned()).into())
+    }
+}
@@ -0,0 +1,97 @@
+//! Structured entries
+//!
+//! # entry
+//!
+//! This module houses [`Entry`], which parses the contents of a secret
+//! according to the `pass` convention: the first line is the password, and the
+//! following lines are free-form, though usually `key: value` pairs like
+//! `user: me@example.com` or `url: example.com`.
+//!
+//! [`Entry`]: struct.Entry.html
+
+/// The parsed contents of a secret.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The first line of the secret.
+    pub password: String,
+    /// Every `key: value` line after the first, in order. Keys may appear more
+    /// than once.
+    pub fields: Vec<(String, String)>,
+    /// Every line after the first that is neither a field nor an OTP URI.
+    pub notes: Vec<String>,
+    /// The first `otpauth://` URI in the secret, if any.
+    pub otp: Option<String>,
+}
+
+impl Entry {
+    /// Parses `contents` into an `Entry`. This never fails: lines that don't
+    /// look like `key: value` pairs end up in `notes`.
+    pub fn parse<S>(contents: S) -> Entry
+    where
+        S: AsRef<str>,
+    {
+        let mut lines = contents.as_ref().lines();
+        let password = lines.next().unwrap_or_default().to_owned();
+        let mut entry = Entry {
+            otp: if password.starts_with(OTP_SCHEME) {
+                Some(password.clone())
+            } else {
+                None
+            },
+            password,
+            ..Default::default()
+        };
+
+        for line in lines {
+            if line.starts_with(OTP_SCHEME) {
+                if entry.otp.is_none() {
+                    entry.otp = Some(line.to_owned());
+                }
+            } else if let Some((key, value)) = self::parse_field(line) {
+                entry.fields.push((key.to_owned(), value.to_owned()));
+            } else {
+                entry.notes.push(line.to_owned());
+            }
+        }
+
+        entry
+    }
+
+    /// Returns the value of the first field named `key`, ignoring case.
+    pub fn field<S>(&self, key: S) -> Option<&str>
+    where
+        S: AsRef<str>,
+    {
+        self.fields_named(key).next()
+    }
+
+    /// Returns the values of every field named `key`, ignoring case.
+    pub fn fields_named<S>(&self, key: S) -> impl Iterator<Item = &str>
+    where
+        S: AsRef<str>,
+    {
+        let key = key.as_ref().trim().to_owned();
+
+        self.fields
+            .iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(&key))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+const OTP_SCHEME: &str = "otpauth://";
+
+/// Splits `key: value` into its key and value. The colon must be followed by
+/// whitespace or the end of the line, so URLs like `https://example.com` aren't
+/// mistaken for fields.
+pub(crate) fn parse_field(line: &str) -> Option<(&str, &str)> {
+    let colon = line.find(':')?;
+    let key = line[..colon].trim();
+    let value = &line[colon + 1..];
+
+    if key.is_empty() || !(value.is_empty() || value.starts_with(char::is_whitespace)) {
+        return None;
+    }
+
+    Some((key, value.trim()))
+}
@@ -15,16 +15,22 @@ const RESET: style::Reset = style::Reset;
 
 pub type Result<T, E = Box<dyn Error + Send + Sync + 'static>> = core::result::Result<T, E>;
 
+/// Errors specific to passrs, as opposed to those of the libraries it uses.
+/// Callers can tell them apart by downcasting the boxed error.
 #[derive(Debug)]
-pub(crate) enum PassrsError {
+pub enum PassrsError {
     NoPrivateKeyFound,
+    NoPublicKeyFound(String),
+    AmbiguousRecipient(String, Vec<String>),
     NoMatchesFound(String),
+    MultipleMatches(String, Vec<String>),
     SecretsDontMatch,
     HashMismatch,
     UserAbort,
     InvalidKeyUri,
     InvalidHashAlgorithm(String),
     NoUriFound(String),
+    NoFieldFound(String),
     NoSigningKeyFound,
     PathDoesntExist(String),
     SneakyPath(String),
@@ -37,6 +43,14 @@ pub(crate) enum PassrsError {
     NoGpgIdFile(String),
     ClipFailed,
     StdoutNotTty,
+    InvalidCryptoBackend(String),
+    InvalidClipboardBackend(String),
+    NoAgeRecipientsFile(String),
+    NoSignatureFound(String),
+    InvalidSignature(String),
+    InsecureExtension(String),
+    UnknownSubcommand(String),
+    StoreLocked(String),
     Other(String),
 }
 
@@ -51,6 +65,21 @@ impl fmt::Display for PassrsError {
                 RED = RED,
                 RESET = RESET
             ),
+            NoPublicKeyFound(s) => write!(
+                f,
+                "{RED}Error: No public key found for '{}'{RESET}",
+                s,
+                RED = RED,
+                RESET = RESET
+            ),
+            AmbiguousRecipient(s, keys) => write!(
+                f,
+                "{RED}Error: '{}' matches more than one public key: {}{RESET}",
+                s,
+                keys.join(", "),
+                RED = RED,
+                RESET = RESET
+            ),
             NoMatchesFound(s) => write!(
                 f,
                 "{RED}Error: No matches found for '{}'{RESET}",
@@ -58,6 +87,14 @@ impl fmt::Display for PassrsError {
                 RED = RED,
                 RESET = RESET
             ),
+            MultipleMatches(s, matches) => write!(
+                f,
+                "{RED}Error: '{}' matches more than one entry: {}{RESET}",
+                s,
+                matches.join(", "),
+                RED = RED,
+                RESET = RESET
+            ),
             SecretsDontMatch => write!(
                 f,
                 "{RED}Error: The entered secrets do not match.{RESET}",
@@ -97,6 +134,13 @@ impl fmt::Display for PassrsError {
                 RED = RED,
                 RESET = RESET
             ),
+            NoFieldFound(s) => write!(
+                f,
+                "{RED}Error: No field named '{}' was found{RESET}",
+                s,
+                RED = RED,
+                RESET = RESET
+            ),
             NoSigningKeyFound => write!(
                 f,
                 "{RED}Error: No signing key found{RESET}",
@@ -171,6 +215,62 @@ impl fmt::Display for PassrsError {
                 RED = RED,
                 RESET = RESET
             ),
+            InvalidCryptoBackend(s) => write!(
+                f,
+                "{RED}Error: Unknown or disabled crypto backend '{}'{RESET}",
+                s,
+                RED = RED,
+                RESET = RESET
+            ),
+            InvalidClipboardBackend(s) => write!(
+                f,
+                "{RED}Error: Unknown clipboard backend '{}'{RESET}",
+                s,
+                RED = RED,
+                RESET = RESET
+            ),
+            NoAgeRecipientsFile(s) => write!(
+                f,
+                "{RED}Error: No `.age-recipients` was found in '{}'{RESET}",
+                s,
+                RED = RED,
+                RESET = RESET
+            ),
+            NoSignatureFound(s) => write!(
+                f,
+                "{RED}Error: Signature for '{}' does not exist{RESET}",
+                s,
+                RED = RED,
+                RESET = RESET
+            ),
+            InvalidSignature(s) => write!(
+                f,
+                "{RED}Error: Signature for '{}' is invalid{RESET}",
+                s,
+                RED = RED,
+                RESET = RESET
+            ),
+            InsecureExtension(s) => write!(
+                f,
+                "{RED}Error: Refusing to run world-writable extension '{}'{RESET}",
+                s,
+                RED = RED,
+                RESET = RESET
+            ),
+            UnknownSubcommand(s) => write!(
+                f,
+                "{RED}Error: '{}' is neither a subcommand nor an enabled extension{RESET}",
+                s,
+                RED = RED,
+                RESET = RESET
+            ),
+            StoreLocked(s) => write!(
+                f,
+                "{RED}Error: The password store is locked by another passrs process (PID {}){RESET}",
+                s,
+                RED = RED,
+                RESET = RESET
+            ),
             Other(s) => write!(
                 f,
                 "{RED}Error: {}{RESET}",
@@ -0,0 +1,181 @@
+//! Entry history
+//!
+//! # history
+//!
+//! This module houses the git2-based history behind `passrs log`,
+//! `passrs show --rev` and `passrs restore`. Paths are relative to the store,
+//! including the entry's extension (e.g. `Internet/example.com.gpg`), and
+//! revisions are anything git understands (e.g. `HEAD~2` or a commit hash).
+
+use std::path::{Path, PathBuf};
+
+use git2::{Commit, Delta, DiffFindOptions, Oid, Repository, Sort};
+
+use crate::consts::PASSWORD_STORE_DIR;
+use crate::crypto;
+use crate::util::{self, EntryKind};
+use crate::Result;
+
+/// A commit in the history of the store, or of one entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revision {
+    pub id: Oid,
+    pub summary: String,
+    pub author: String,
+    /// The entry's path in this commit, when looking at the history of an
+    /// entry.
+    pub path: Option<PathBuf>,
+    /// The entry's path in the commit's first parent, if it existed there.
+    pub old_path: Option<PathBuf>,
+}
+
+/// Lists the commits of the current branch, newest first. If `entry` is
+/// specified, only the commits that touched it are listed, following it
+/// through renames.
+pub fn log<P>(entry: Option<P>) -> Result<Vec<Revision>>
+where
+    P: AsRef<Path>,
+{
+    let repo = Repository::open(&*PASSWORD_STORE_DIR)?;
+    let mut walk = repo.revwalk()?;
+    let mut tracked = entry.map(|entry| entry.as_ref().to_path_buf());
+    let mut revisions = Vec::new();
+
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    walk.push_head()?;
+
+    for id in walk {
+        let commit = repo.find_commit(id?)?;
+        let mut revision = Revision {
+            id: commit.id(),
+            summary: commit.summary().unwrap_or_default().to_owned(),
+            author: commit.author().name().unwrap_or_default().to_owned(),
+            path: None,
+            old_path: None,
+        };
+
+        match &mut tracked {
+            None => revisions.push(revision),
+            Some(path) => {
+                if let Some(old_path) = self::change_to(&repo, &commit, path)? {
+                    revision.path = Some(path.clone());
+                    revision.old_path = old_path.clone();
+                    revisions.push(revision);
+
+                    // Older commits know the entry by its old name
+                    if let Some(old_path) = old_path {
+                        *path = old_path;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(revisions)
+}
+
+/// Decrypts the entry at `path` as of `rev`. Returns `None` if the entry
+/// didn't exist then.
+pub fn plaintext_at<P>(rev: &str, path: P) -> Result<Option<Vec<u8>>>
+where
+    P: AsRef<Path>,
+{
+    let repo = Repository::open(&*PASSWORD_STORE_DIR)?;
+    let commit = repo.revparse_single(rev)?.peel_to_commit()?;
+
+    self::plaintext_in(&repo, &commit, path.as_ref())
+}
+
+/// The plaintext of an entry before and after a commit changed it. Either is
+/// `None` if the entry didn't exist at that point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub old: Option<Vec<u8>>,
+    pub new: Option<Vec<u8>>,
+}
+
+/// Decrypts the entry a revision from [`log`] changed, before and after the
+/// change.
+///
+/// [`log`]: fn.log.html
+pub fn changes(revision: &Revision) -> Result<Change> {
+    let repo = Repository::open(&*PASSWORD_STORE_DIR)?;
+    let commit = repo.find_commit(revision.id)?;
+    let old = match (&revision.old_path, commit.parents().next()) {
+        (Some(old_path), Some(parent)) => self::plaintext_in(&repo, &parent, old_path)?,
+        _ => None,
+    };
+    let new = match &revision.path {
+        Some(path) => self::plaintext_in(&repo, &commit, path)?,
+        None => None,
+    };
+
+    Ok(Change { old, new })
+}
+
+/// Restores the entry at `path` to its contents as of `rev`, encrypted for its
+/// current recipients, and commits it.
+pub fn restore<P>(rev: &str, path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let plaintext = self::plaintext_at(rev, path)?
+        .ok_or_else(|| format!("'{}' did not exist in {}", path.display(), rev))?;
+    let file = PASSWORD_STORE_DIR.join(path);
+    let ciphertext = util::encrypt_bytes_for(&file, None, &plaintext)?;
+
+    util::create_dirs_to_file(&file)?;
+    util::write_atomically(&file, ciphertext)?;
+    util::commit(
+        Some([&file]),
+        format!(
+            "Restore {} from {}",
+            util::strip_extension(&path.to_string_lossy()),
+            rev
+        ),
+    )
+}
+
+/// Returns `Some` if `commit` touched the entry at `path`, containing where
+/// the entry was in the commit's first parent (if anywhere).
+fn change_to(repo: &Repository, commit: &Commit, path: &Path) -> Result<Option<Option<PathBuf>>> {
+    let tree = commit.tree()?;
+    let parent_tree = match commit.parents().next() {
+        Some(parent) => Some(parent.tree()?),
+        None => None,
+    };
+    let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+    let mut find_opts = DiffFindOptions::new();
+
+    find_opts.renames(true);
+    diff.find_similar(Some(&mut find_opts))?;
+
+    for delta in diff.deltas() {
+        // Removed files keep their path in `new_file`
+        if delta.new_file().path() != Some(path) {
+            continue;
+        }
+
+        let old_path = match delta.status() {
+            Delta::Added => None,
+            _ => delta.old_file().path().map(Path::to_path_buf),
+        };
+
+        return Ok(Some(old_path));
+    }
+
+    Ok(None)
+}
+
+fn plaintext_in(repo: &Repository, commit: &Commit, path: &Path) -> Result<Option<Vec<u8>>> {
+    let entry = match commit.tree()?.get_path(path) {
+        Ok(entry) => entry,
+        Err(ref e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let blob = repo.find_blob(entry.id())?;
+    let backend = crypto::backend_for(EntryKind::from_path(path))?;
+
+    Ok(Some(backend.decrypt(blob.content())?))
+}
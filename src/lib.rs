@@ -3,18 +3,30 @@
 //!
 //! ## Present functionality
 //! * initialize a new password store: `passrs init <YOUR_GPG_ID>`
+//!   * encrypt a (sub)store without revealing its recipients with `--hidden`
 //! * list of all secrets in the store inspired by the original implementation
 //! using `tree`: `passrs ls` or just `passrs`
 //! * find all secrets that match the specified name: `passrs find <entry-name>`
 //!   * For now, this prints the full path of the entry and does not display it
 //!   as a tree
 //! * grep for secrets that match a string when decrypted: `passrs grep
-//! <search-string>`
+//! <search-string> [subfolder]`
+//!   * only search certain fields (e.g. `user: ...` lines) with `--field user`
+//!   * supports `-i`, `-F`, `-w`, `-v`, `-A`/`-B`/`-C`, `-c` and `-l`, as well
+//!   as default options in `GREPOPTIONS`
+//! * show a secret, or a single `key: value` field of it: `passrs show
+//! <entry-name> [--field <key>]`
 //! * insert a new secret: `passrs insert <entry-name>`
 //! * edit a secret using the `$EDITOR` environment variable: `passrs edit
 //! <entry-name>`
 //! * generate a new secret with a specified length (defaults to 24): `passrs
 //! generate <entry-name> [pass-length]`
+//! * generate diceware passphrases from the EFF long wordlist (or your own):
+//! `passrs generate --words 6 <entry-name>`
+//! * generate secrets that meet a password policy, from flags or the closest
+//! `.passrs-policy`: `passrs generate --min-digits 2 --max-repeat 2 <entry-name>`
+//! * rotate the secrets of many entries in one commit, keeping their other
+//! lines: `passrs rotate 'Internet/*'`
 //! * remove an entry from the store: `passrs rm <entry-name>`
 //! * move an entry to a new location: `passrs mv <old-path> <new-path>`
 //! * copy an entry to a new location: `passrs cp <old-path> <new-path>`
@@ -29,15 +41,31 @@
 //!   * print the key URI of the specified entry: `passrs otp uri <entry-name>`
 //!   * validate a URI string for adherence to the [Key Uri Format]: `passrs otp
 //!   validate <uri>`
+//! * run `pass` extensions from `PASSWORD_STORE_EXTENSIONS_DIR` (with
+//! `PASSWORD_STORE_ENABLE_EXTENSIONS=true`) or the system extension dir, with
+//! the helper functions `pass` provides: `passrs <extension> [args]`
+//! * honour `PASSWORD_STORE_GPG_OPTS` for the options gpgme supports: armor,
+//! trust model, hidden recipients, compression, pinentry mode and homedir
+//! * add gpg options for the entries of a (sub)store in a `.gpg-opts` file next
+//! to its `.gpg-id`
+//! * sync with a git remote, merging entries changed on both sides by their
+//! plaintext: `passrs sync [remote]`
+//! * show decrypted diffs of entries in `git log -p`, and merge them by their
+//! plaintext in git, through the drivers `passrs init` configures
+//! * list the history of an entry across renames, show old versions and restore
+//! them: `passrs log [entry]`, `passrs show --rev <commit> <entry>` and
+//! `passrs restore <entry> --rev <commit>`
+//! * keep decrypted secrets in memory for other invocations: `passrs agent`
+//! * copy secrets with `wl-copy`, `xclip`, `xsel`, tmux buffers, OSC 52 escape
+//! sequences (over SSH) or your own commands, picked by
+//! `PASSRS_CLIPBOARD_BACKEND` or detected from the environment
 //! * print shell completion information to stdout: `passrs complete bash`
+//! * print machine-readable JSON from `ls`, `find`, `show` and `otp code` with
+//! `--json` (or `--format json`)
 //!
 //! ## Missing functionality
-//! * support for certain environment variables:
-//!   * `PASSWORD_STORE_ENABLE_EXTENSIONS`
-//!   * `PASSWORD_STORE_EXTENSIONS_DIR`
-//!   * `PASSWORD_STORE_GPG_OPTS`
-//!   * `GREPOPTIONS`
-//! * deinitialization of the password store
+//! * deinitialization of the root of the password store (substores can be
+//! deinitialized with `passrs init --path <substore> ""`)
 //! * entries found with `passrs find` being displayed as a tree
 //! * probably more
 //!
@@ -45,15 +73,26 @@
 //! [gpgme]: https://docs.rs/gpgme
 //! [Key Uri Format]: https://github.com/google/google-authenticator/wiki/Key-Uri-Format
 
+pub mod agent;
+pub mod cache;
 #[doc(hidden)]
 pub mod cli;
 pub mod clipboard;
 pub mod consts;
+pub mod crypto;
+pub mod entry;
 pub mod error;
+pub mod history;
+pub mod lock;
 #[cfg(feature = "otp")]
 pub mod otp;
+pub mod passphrase;
+pub mod policy;
+pub mod rotate;
 #[doc(hidden)]
 pub mod subcmds;
+pub mod sync;
+pub mod transaction;
 pub mod tree;
 pub mod ui;
 pub mod util;
@@ -0,0 +1,126 @@
+//! Store locking
+//!
+//! # lock
+//!
+//! This module makes sure that only one passrs process modifies the store at a
+//! time. Subcommands that modify the store, as well as `util::commit`, hold an
+//! advisory lock (see `flock(2)`) on `passrs.lock` in the store's `.git`
+//! directory, or on `.passrs.lock` in the store itself if it isn't a git
+//! repository. The lock file contains the PID of the process holding it.
+//!
+//! Waiting for the lock gives up after [`PASSRS_LOCK_TIMEOUT`] seconds. Within
+//! one process the lock can be acquired any number of times.
+//!
+//! [`PASSRS_LOCK_TIMEOUT`]: ../consts/static.PASSRS_LOCK_TIMEOUT.html
+
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+
+use crate::consts::{PASSRS_LOCK_TIMEOUT, PASSWORD_STORE_DIR};
+use crate::{PassrsError, Result};
+
+/// The locked file, and how many times this process acquired the lock.
+static HELD: Lazy<Mutex<Option<(File, usize)>>> = Lazy::new(|| Mutex::new(None));
+
+/// The store stays locked until every `StoreLock` of this process was dropped.
+pub struct StoreLock {
+    held: bool,
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        if !self.held {
+            return;
+        }
+
+        let mut held = match HELD.lock() {
+            Ok(held) => held,
+            Err(e) => e.into_inner(),
+        };
+
+        if let Some((_, count)) = held.as_mut() {
+            *count -= 1;
+
+            if *count == 0 {
+                if let Some((file, _)) = held.take() {
+                    // Closing the file releases the lock
+                    let _ = file.set_len(0);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the path to the lock file of the store.
+pub fn lock_path() -> PathBuf {
+    let git_dir = PASSWORD_STORE_DIR.join(".git");
+
+    if git_dir.is_dir() {
+        git_dir.join("passrs.lock")
+    } else {
+        PASSWORD_STORE_DIR.join(".passrs.lock")
+    }
+}
+
+/// Locks the store, waiting for other passrs processes to release it for at
+/// most [`PASSRS_LOCK_TIMEOUT`] seconds. If the store doesn't exist yet, there
+/// is nothing to lock.
+///
+/// [`PASSRS_LOCK_TIMEOUT`]: ../consts/static.PASSRS_LOCK_TIMEOUT.html
+pub fn acquire() -> Result<StoreLock> {
+    let mut held = HELD.lock().map_err(|_| "Store lock was poisoned")?;
+
+    if let Some((_, count)) = held.as_mut() {
+        *count += 1;
+
+        return Ok(StoreLock { held: true });
+    }
+
+    if !PASSWORD_STORE_DIR.is_dir() {
+        return Ok(StoreLock { held: false });
+    }
+
+    let path = self::lock_path();
+    let file = fs::OpenOptions::new()
+        .mode(0o600)
+        .read(true)
+        .write(true)
+        .create(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(&path)?;
+    let deadline = Instant::now() + Duration::from_secs(*PASSRS_LOCK_TIMEOUT);
+
+    while unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let err = io::Error::last_os_error();
+
+        if err.raw_os_error() != Some(libc::EWOULDBLOCK) {
+            return Err(err.into());
+        }
+
+        if Instant::now() >= deadline {
+            let holder = fs::read_to_string(&path).unwrap_or_default();
+            let holder = match holder.trim() {
+                "" => "unknown",
+                pid => pid,
+            };
+
+            return Err(PassrsError::StoreLocked(holder.to_owned()).into());
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    file.set_len(0)?;
+    file.write_all_at(std::process::id().to_string().as_bytes(), 0)?;
+    *held = Some((file, 1));
+
+    Ok(StoreLock { held: true })
+}
@@ -0,0 +1,160 @@
+//! Passphrase generation
+//!
+//! # passphrase
+//!
+//! This module houses the diceware-style passphrases of `passrs generate
+//! --words`: a number of words picked at random from a wordlist, by default
+//! the [EFF long wordlist].
+//!
+//! [EFF long wordlist]: https://www.eff.org/dice
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::util;
+use crate::Result;
+
+/// The EFF long wordlist, embedded by the build script from
+/// `share/eff_large_wordlist.txt`.
+const EFF_LARGE_WORDLIST: &str = include_str!(concat!(env!("OUT_DIR"), "/eff_large_wordlist.txt"));
+
+/// A list of distinct words to pick passphrases from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wordlist {
+    words: Vec<String>,
+}
+
+impl Wordlist {
+    /// Returns the bundled EFF long wordlist.
+    pub fn bundled() -> Result<Wordlist> {
+        Wordlist::parse(EFF_LARGE_WORDLIST)
+    }
+
+    /// Reads a wordlist from `path`. See [`parse`] for the format.
+    ///
+    /// [`parse`]: #method.parse
+    pub fn from_file<P>(path: P) -> Result<Wordlist>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read wordlist '{}': {}", path.display(), e))?;
+
+        Wordlist::parse(&contents)
+    }
+
+    /// Parses a wordlist with one word per line. Like in the EFF wordlists, the
+    /// word may be preceded by its dice roll (e.g. `11111\tabacus`). Empty lines
+    /// are ignored.
+    pub fn parse(contents: &str) -> Result<Wordlist> {
+        let mut seen = HashSet::new();
+        let mut words = Vec::new();
+
+        for line in contents.lines() {
+            let word = match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [] => continue,
+                [word] => *word,
+                [roll, word] if roll.chars().all(|c| c.is_ascii_digit()) => *word,
+                _ => return Err(format!("Invalid line in wordlist: '{}'", line).into()),
+            };
+
+            // Duplicates would make some words likelier than others
+            if !seen.insert(word) {
+                return Err(format!("Duplicate word in wordlist: '{}'", word).into());
+            }
+
+            words.push(word.to_owned());
+        }
+
+        if words.len() < 2 {
+            return Err("Wordlist must contain at least 2 words".into());
+        }
+
+        Ok(Wordlist { words })
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+/// How to build a passphrase from the words.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassphraseOptions {
+    pub words: usize,
+    pub separator: String,
+    /// Capitalize the first letter of every word.
+    pub capitalize: bool,
+    /// Append a random digit to one of the words.
+    pub digit: bool,
+}
+
+impl Default for PassphraseOptions {
+    fn default() -> Self {
+        PassphraseOptions {
+            words: 6,
+            separator: String::from("-"),
+            capitalize: false,
+            digit: false,
+        }
+    }
+}
+
+/// A generated passphrase and its entropy in bits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Passphrase {
+    pub text: String,
+    pub entropy: f64,
+}
+
+/// Generates a passphrase from `wordlist`.
+pub fn generate(wordlist: &Wordlist, options: &PassphraseOptions) -> Result<Passphrase> {
+    if options.words == 0 {
+        return Err("Passphrases need at least 1 word".into());
+    }
+
+    let mut words = Vec::with_capacity(options.words);
+
+    for _ in 0..options.words {
+        let word = &wordlist.words[util::random_below(wordlist.len())?];
+
+        if options.capitalize {
+            let mut chars = word.chars();
+            let first = chars.next().into_iter().flat_map(char::to_uppercase);
+
+            words.push(first.chain(chars).collect());
+        } else {
+            words.push(word.clone());
+        }
+    }
+
+    if options.digit {
+        let word = util::random_below(words.len())?;
+        let digit = util::random_below(10)?;
+
+        words[word].push_str(&digit.to_string());
+    }
+
+    Ok(Passphrase {
+        text: words.join(&options.separator),
+        entropy: self::entropy(wordlist.len(), options),
+    })
+}
+
+/// Returns the entropy in bits of passphrases generated with `options` from a
+/// wordlist of `wordlist_len` words, assuming an attacker knows both.
+pub fn entropy(wordlist_len: usize, options: &PassphraseOptions) -> f64 {
+    let mut entropy = options.words as f64 * (wordlist_len as f64).log2();
+
+    if options.digit {
+        entropy += 10f64.log2() + (options.words as f64).log2();
+    }
+
+    entropy
+}
@@ -0,0 +1,270 @@
+//! Password policies
+//!
+//! # policy
+//!
+//! This module houses the policies `passrs generate` follows: a minimum number
+//! of characters from each of [`DIGITS`], [`ALPHA_UPPER`], [`ALPHA_LOWER`] and
+//! [`SPECIAL`], excluded [`AMBIGUOUS`] characters, a maximum run of repeated
+//! characters and a subset of allowed symbols.
+//!
+//! Policies come from the closest [`POLICY_FILE`] to an entry (much like
+//! `.gpg-id`) and from the flags to `generate`, which take precedence. The file
+//! has one `key = value` per line, with blank lines and `#` comments ignored:
+//!
+//! ```text
+//! length = 20
+//! min-digits = 2
+//! min-upper = 1
+//! min-lower = 1
+//! min-special = 1
+//! exclude-ambiguous = true
+//! max-repeat = 2
+//! symbols = !#$%&*+-=?@^_
+//! ```
+//!
+//! Passwords are drawn uniformly from the allowed characters and drawn again
+//! until they meet the policy, so every acceptable password is equally likely.
+//!
+//! [`DIGITS`]: ../consts/constant.DIGITS.html
+//! [`ALPHA_UPPER`]: ../consts/constant.ALPHA_UPPER.html
+//! [`ALPHA_LOWER`]: ../consts/constant.ALPHA_LOWER.html
+//! [`SPECIAL`]: ../consts/constant.SPECIAL.html
+//! [`AMBIGUOUS`]: ../consts/constant.AMBIGUOUS.html
+//! [`POLICY_FILE`]: ../consts/constant.POLICY_FILE.html
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::consts::{
+    ALPHA_LOWER, ALPHA_UPPER, AMBIGUOUS, DIGITS, PASSWORD_STORE_DIR, POLICY_FILE, SPECIAL,
+};
+use crate::util;
+use crate::Result;
+
+/// How many candidates to draw before deciding that the policy is too strict to
+/// be met in practice.
+const MAX_ATTEMPTS: usize = 100_000;
+
+/// Requirements for generated passwords. Unset options don't constrain them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    pub length: Option<usize>,
+    pub min_digits: Option<usize>,
+    pub min_upper: Option<usize>,
+    pub min_lower: Option<usize>,
+    pub min_special: Option<usize>,
+    pub exclude_ambiguous: Option<bool>,
+    /// The longest allowed run of the same character.
+    pub max_repeat: Option<usize>,
+    /// The only symbols (characters from `SPECIAL`) allowed.
+    pub symbols: Option<Vec<char>>,
+}
+
+impl Policy {
+    /// Parses a policy file. See the [module documentation] for the format.
+    ///
+    /// [module documentation]: index.html
+    pub fn parse(contents: &str) -> Result<Policy> {
+        let mut policy = Policy::default();
+
+        for line in contents.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.find('=') {
+                Some(idx) => (line[..idx].trim(), line[idx + 1..].trim()),
+                None => return Err(format!("Invalid line in policy: '{}'", line).into()),
+            };
+            let count = || -> Result<usize> {
+                value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {} in policy: '{}'", key, value).into())
+            };
+
+            match key {
+                "length" => policy.length = Some(count()?),
+                "min-digits" => policy.min_digits = Some(count()?),
+                "min-upper" => policy.min_upper = Some(count()?),
+                "min-lower" => policy.min_lower = Some(count()?),
+                "min-special" => policy.min_special = Some(count()?),
+                "max-repeat" => policy.max_repeat = Some(count()?),
+                "exclude-ambiguous" => {
+                    policy.exclude_ambiguous =
+                        Some(value.parse().map_err(|_| {
+                            format!("Invalid value for {} in policy: '{}'", key, value)
+                        })?)
+                }
+                "symbols" => policy.symbols = Some(value.chars().collect()),
+                _ => return Err(format!("Unknown key in policy: '{}'", key).into()),
+            }
+        }
+
+        Ok(policy)
+    }
+
+    /// Returns the policy for the entry at `path`: the closest policy file's, or
+    /// the default policy if there is none.
+    pub fn for_entry<P>(path: P) -> Result<Policy>
+    where
+        P: AsRef<Path>,
+    {
+        match self::closest_policy_file(path) {
+            Some(file) => {
+                let contents = fs::read_to_string(&file)?;
+
+                Policy::parse(&contents).map_err(|e| format!("{}: {}", file.display(), e).into())
+            }
+            None => Ok(Policy::default()),
+        }
+    }
+
+    /// Returns this policy with the options set in `overrides` replaced.
+    pub fn with_overrides(self, overrides: Policy) -> Policy {
+        Policy {
+            length: overrides.length.or(self.length),
+            min_digits: overrides.min_digits.or(self.min_digits),
+            min_upper: overrides.min_upper.or(self.min_upper),
+            min_lower: overrides.min_lower.or(self.min_lower),
+            min_special: overrides.min_special.or(self.min_special),
+            exclude_ambiguous: overrides.exclude_ambiguous.or(self.exclude_ambiguous),
+            max_repeat: overrides.max_repeat.or(self.max_repeat),
+            symbols: overrides.symbols.or(self.symbols),
+        }
+    }
+
+    /// Narrows `set` down to the characters this policy allows.
+    pub fn allowed_chars<V>(&self, set: V) -> Vec<char>
+    where
+        V: AsRef<[char]>,
+    {
+        set.as_ref()
+            .iter()
+            .copied()
+            .filter(|&c| match &self.symbols {
+                Some(symbols) if self::is_in(SPECIAL, c) => symbols.contains(&c),
+                _ => true,
+            })
+            .filter(|&c| !(self.exclude_ambiguous == Some(true) && self::is_in(AMBIGUOUS, c)))
+            .collect()
+    }
+
+    /// Returns whether `password` meets this policy.
+    pub fn accepts<S>(&self, password: S) -> bool
+    where
+        S: AsRef<str>,
+    {
+        let password: Vec<char> = password.as_ref().chars().collect();
+        let count = |class: &[u8]| password.iter().filter(|&&c| self::is_in(class, c)).count();
+        let longest_run = password
+            .iter()
+            .enumerate()
+            .map(|(i, c)| password[i..].iter().take_while(|&d| d == c).count())
+            .max()
+            .unwrap_or(0);
+        let short_runs = match self.max_repeat {
+            Some(max) => longest_run <= max,
+            None => true,
+        };
+
+        count(DIGITS) >= self.min_digits.unwrap_or(0)
+            && count(ALPHA_UPPER) >= self.min_upper.unwrap_or(0)
+            && count(ALPHA_LOWER) >= self.min_lower.unwrap_or(0)
+            && count(SPECIAL) >= self.min_special.unwrap_or(0)
+            && short_runs
+    }
+
+    /// Generates a password of `len` characters from the characters of `set`
+    /// that this policy allows, meeting the policy.
+    pub fn generate<V>(&self, set: V, len: usize) -> Result<String>
+    where
+        V: AsRef<[char]>,
+    {
+        let allowed = self.allowed_chars(set);
+
+        self.check_feasible(&allowed, len)?;
+
+        for _ in 0..MAX_ATTEMPTS {
+            let password = util::generate_chars_from_set(&allowed, len)?;
+
+            if self.accepts(&password) {
+                return Ok(password);
+            }
+        }
+
+        Err("The password policy is too strict to generate a password; \
+             try a longer one or fewer requirements"
+            .into())
+    }
+
+    fn check_feasible(&self, allowed: &[char], len: usize) -> Result<()> {
+        let classes = [
+            ("digits", DIGITS, self.min_digits),
+            ("uppercase letters", ALPHA_UPPER, self.min_upper),
+            ("lowercase letters", ALPHA_LOWER, self.min_lower),
+            ("symbols", SPECIAL, self.min_special),
+        ];
+        let mut required = 0;
+
+        if allowed.is_empty() {
+            return Err("The password policy doesn't allow any characters".into());
+        }
+
+        for (name, class, min) in classes.iter() {
+            let min = min.unwrap_or(0);
+
+            if min > 0 && !allowed.iter().any(|&c| self::is_in(class, c)) {
+                return Err(
+                    format!("The password policy requires {} but allows none", name).into(),
+                );
+            }
+
+            required += min;
+        }
+
+        if required > len {
+            return Err(format!(
+                "The password policy requires {} characters, but the password is only {} long",
+                required, len
+            )
+            .into());
+        }
+
+        if self.max_repeat == Some(0) && len > 0 {
+            return Err("The password policy's max-repeat must be at least 1".into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns whether `c` is one of the (ASCII) characters in `class`.
+fn is_in(class: &[u8], c: char) -> bool {
+    c.is_ascii() && class.contains(&(c as u8))
+}
+
+/// Returns the policy file closest to `path`, searching up to the root of the
+/// store.
+fn closest_policy_file<P>(path: P) -> Option<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let mut dir = if path.is_dir() { path } else { path.parent()? };
+
+    loop {
+        let file = dir.join(POLICY_FILE);
+
+        if file.is_file() {
+            return Some(file);
+        }
+
+        if dir == *PASSWORD_STORE_DIR {
+            return None;
+        }
+
+        dir = dir.parent()?;
+    }
+}
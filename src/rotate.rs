@@ -0,0 +1,209 @@
+//! Secret rotation
+//!
+//! # rotate
+//!
+//! This module houses `passrs rotate`, which replaces the secrets (first lines)
+//! of many entries at once, keeping the rest of their lines, and commits them
+//! together. If anything fails along the way, no entry is changed.
+
+use std::path::{Path, PathBuf};
+use std::str;
+
+use walkdir::WalkDir;
+
+use crate::consts::{PASSWORD_STORE_DIR, STORE_LEN};
+use crate::entry::{self, Entry};
+use crate::lock;
+use crate::transaction::Transaction;
+use crate::util;
+use crate::{PassrsError, Result};
+
+/// The field the old secret is kept in when asked to.
+pub const PREVIOUS_FIELD: &str = "previous";
+
+/// A rotated entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rotation {
+    /// The entry's name, relative to the store and without its extension.
+    pub name: String,
+    /// The entry's `url` field, if it has one.
+    pub url: Option<String>,
+}
+
+/// Returns the entries `targets` refer to: entries, folders (whose entries are
+/// all included) or glob patterns matching entry names, where `*` and `?`
+/// don't match `/` but `**` does.
+pub fn find_entries<S>(targets: &[S]) -> Result<Vec<PathBuf>>
+where
+    S: AsRef<str>,
+{
+    let mut entries = Vec::new();
+
+    for target in targets {
+        let target = target.as_ref();
+        let found = if target.contains(['*', '?']) {
+            let pattern: Vec<char> = target.trim_start_matches('/').chars().collect();
+
+            self::entries_in(&PASSWORD_STORE_DIR)?
+                .into_iter()
+                .filter(|entry| {
+                    let name = util::strip_extension(&entry.to_string_lossy()[*STORE_LEN..])
+                        .chars()
+                        .collect::<Vec<_>>();
+
+                    self::glob_matches(&pattern, &name)
+                })
+                .collect()
+        } else {
+            let path = util::canonicalize_path(target)?;
+
+            if path.is_dir() {
+                self::entries_in(&path)?
+            } else if path.is_file() {
+                vec![path]
+            } else {
+                return Err(PassrsError::PathDoesntExist(path.display().to_string()).into());
+            }
+        };
+
+        if found.is_empty() {
+            return Err(PassrsError::NoMatchesFound(target.to_owned()).into());
+        }
+
+        entries.extend(found);
+    }
+
+    entries.sort();
+    entries.dedup();
+
+    Ok(entries)
+}
+
+/// Returns `plaintext` with its secret replaced by `secret`. Every other line
+/// is kept; with `keep_previous`, the old secret is recorded in a `previous:`
+/// field right below the new one, replacing any older `previous:` fields.
+pub fn rotated(plaintext: &str, secret: &str, keep_previous: bool) -> String {
+    let mut lines = plaintext.lines();
+    let old = lines.next().unwrap_or_default();
+    let mut rotated = vec![secret.to_owned()];
+
+    if keep_previous {
+        rotated.push(format!("{}: {}", PREVIOUS_FIELD, old));
+    }
+
+    for line in lines {
+        let is_previous = match entry::parse_field(line) {
+            Some((key, _)) => key.eq_ignore_ascii_case(PREVIOUS_FIELD),
+            None => false,
+        };
+
+        if !(keep_previous && is_previous) {
+            rotated.push(line.to_owned());
+        }
+    }
+
+    let mut rotated = rotated.join("\n");
+
+    if plaintext.ends_with('\n') {
+        rotated.push('\n');
+    }
+
+    rotated
+}
+
+/// Replaces the secrets of `entries` with the ones `new_secret` generates for
+/// them (see [`rotated`]), and commits them all at once.
+///
+/// [`rotated`]: fn.rotated.html
+pub fn rotate<F>(
+    entries: &[PathBuf],
+    keep_previous: bool,
+    mut new_secret: F,
+) -> Result<Vec<Rotation>>
+where
+    F: FnMut(&Path) -> Result<String>,
+{
+    let _lock = lock::acquire()?;
+    let mut tx = Transaction::new();
+    let mut rotations = Vec::with_capacity(entries.len());
+
+    for path in entries {
+        let name = util::strip_extension(&path.to_string_lossy()[*STORE_LEN..]).to_owned();
+        let plaintext = util::decrypt_file_into_bytes(path)?;
+        let plaintext = str::from_utf8(&plaintext)?;
+        let entry = Entry::parse(plaintext);
+
+        if entry.otp.as_deref() == Some(entry.password.as_str()) {
+            return Err(format!("{} has no secret to rotate, only an OTP URI", name).into());
+        }
+
+        let contents = self::rotated(plaintext, &new_secret(path)?, keep_previous);
+        let ciphertext = util::encrypt_bytes_for(path, None, contents.as_bytes())?;
+
+        tx.write(path, ciphertext)?;
+        rotations.push(Rotation {
+            url: entry.field("url").map(ToOwned::to_owned),
+            name,
+        });
+    }
+
+    let message = match rotations.as_slice() {
+        [rotation] => format!("Rotate secret for {}", rotation.name),
+        _ => format!(
+            "Rotate secrets for {} entries\n\n{}",
+            rotations.len(),
+            rotations
+                .iter()
+                .map(|r| r.name.as_str())
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+    };
+
+    tx.commit(|| util::commit(Some(entries), message))?;
+
+    Ok(rotations)
+}
+
+/// Returns every entry in the folder `dir`, recursively, skipping hidden files
+/// and folders.
+fn entries_in(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = Vec::new();
+
+    for entry in WalkDir::new(dir).into_iter().filter_entry(|entry| {
+        entry
+            .file_name()
+            .to_str()
+            .map(|s| entry.depth() == 0 || !s.starts_with('.'))
+            .unwrap_or(false)
+    }) {
+        let entry = entry?;
+
+        if entry.file_type().is_file() && util::is_entry(entry.path().to_string_lossy()) {
+            entries.push(entry.into_path());
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Matches `name` against the glob `pattern`.
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern {
+        [] => name.is_empty(),
+        // `**/` also matches no folder at all
+        ['*', '*', '/', rest @ ..] if self::glob_matches(rest, name) => true,
+        ['*', '*', rest @ ..] => (0..=name.len()).any(|i| self::glob_matches(rest, &name[i..])),
+        ['*', rest @ ..] => (0..=name.len())
+            .take_while(|&i| i == 0 || name[i - 1] != '/')
+            .any(|i| self::glob_matches(rest, &name[i..])),
+        ['?', rest @ ..] => match name {
+            [c, name @ ..] if *c != '/' => self::glob_matches(rest, name),
+            _ => false,
+        },
+        [p, rest @ ..] => match name {
+            [c, name @ ..] if c == p => self::glob_matches(rest, name),
+            _ => false,
+        },
+    }
+}
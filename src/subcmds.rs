@@ -1,15 +1,22 @@
+pub(crate) mod agent;
 pub(crate) mod cp;
 pub(crate) mod edit;
+pub(crate) mod extension;
 pub(crate) mod find;
 pub(crate) mod generate;
 pub(crate) mod git;
+pub(crate) mod git_driver;
 pub(crate) mod grep;
 pub(crate) mod init;
 pub(crate) mod insert;
+pub(crate) mod log;
 pub(crate) mod ls;
 pub(crate) mod mv;
 #[cfg(feature = "otp")]
 pub(crate) mod otp;
+pub(crate) mod restore;
 pub(crate) mod rm;
+pub(crate) mod rotate;
 pub(crate) mod show;
+pub(crate) mod sync;
 pub(crate) mod unclip;
@@ -0,0 +1,20 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::agent;
+use crate::consts::PASSRS_AGENT_TIMEOUT;
+use crate::Result;
+
+pub(crate) fn agent(timeout: Option<u64>, stop: bool) -> Result<()> {
+    if stop {
+        if !agent::stop()? {
+            writeln!(io::stderr(), "No agent is running")?;
+        }
+
+        return Ok(());
+    }
+
+    let timeout = Duration::from_secs(timeout.unwrap_or(*PASSRS_AGENT_TIMEOUT));
+
+    agent::serve(timeout)
+}
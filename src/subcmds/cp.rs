@@ -1,7 +1,6 @@
 use std::fs;
-use std::os::unix::fs::OpenOptionsExt;
 
-use crate::consts::PASSWORD_STORE_UMASK;
+use crate::transaction::Transaction;
 use crate::util;
 use crate::{PassrsError, Result};
 
@@ -12,11 +11,23 @@ pub(crate) fn cp(source: String, dest: String, force: bool) -> Result<()> {
         Err(_) => false,
     };
     let dest_path = if is_file {
-        util::exact_path([&dest, ".gpg"].concat())?
+        let dest_dir = util::exact_path(&dest)?
+            .parent()
+            .ok_or("Destination had no parent directory")?
+            .to_path_buf();
+        let extension = util::closest_entry_kind(dest_dir).extension();
+
+        util::exact_path([&dest, extension].concat())?
     } else {
         util::exact_path(&dest)?
     };
 
+    if source_path == dest_path {
+        return Err(PassrsError::SourceIsDestination.into());
+    }
+
+    let mut tx = Transaction::new();
+
     if is_file {
         if !util::path_exists(&source_path)? {
             return Err(PassrsError::NotInStore(source).into());
@@ -26,22 +37,12 @@ pub(crate) fn cp(source: String, dest: String, force: bool) -> Result<()> {
             let display = dest_path.display();
             let prompt = format!("An entry exists for {}. Overwrite it?", display);
 
-            if util::prompt_yesno(prompt)? {
-                fs::OpenOptions::new()
-                    .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
-                    .write(true)
-                    .truncate(true)
-                    .open(&dest_path)?;
-            } else {
+            if !util::prompt_yesno(prompt)? {
                 return Err(PassrsError::UserAbort.into());
             }
         }
 
-        util::copy(&source_path, &dest_path)?;
-        util::commit(
-            Some([&source_path, &dest_path]),
-            format!("Copy {} to {}", source, dest),
-        )?;
+        util::stage_copy_entry(&mut tx, &source_path, &dest_path)?;
     } else {
         if !util::path_exists(&source_path)? {
             return Err(PassrsError::PathDoesntExist(source).into());
@@ -52,18 +53,21 @@ pub(crate) fn cp(source: String, dest: String, force: bool) -> Result<()> {
             let prompt = format!("An entry exists for {}. Overwrite it?", display);
 
             if util::prompt_yesno(prompt)? {
-                fs::remove_dir_all(&dest_path)?;
+                tx.remove(&dest_path)?;
             } else {
                 return Err(PassrsError::UserAbort.into());
             }
         }
 
-        util::copy(&source_path, &dest_path)?;
+        util::stage_copy_dir(&mut tx, &source_path, &dest_path, true)?;
+    }
+
+    tx.commit(|| {
         util::commit(
             Some([&source_path, &dest_path]),
             format!("Copy {} to {}", source, dest),
-        )?;
-    }
+        )
+    })?;
 
     Ok(())
 }
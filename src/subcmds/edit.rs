@@ -4,7 +4,6 @@ use std::io::{Read, Seek, SeekFrom, Write};
 use std::os::unix::fs::OpenOptionsExt;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
-use std::str;
 
 use data_encoding::HEXLOWER;
 use ring::digest;
@@ -107,7 +106,7 @@ where
     Ok(())
 }
 
-fn temp_file<S>(path: S) -> Result<String>
+pub(crate) fn temp_file<S>(path: S) -> Result<String>
 where
     S: AsRef<str>,
 {
@@ -136,8 +135,8 @@ where
             .file_name()
             .ok_or("Current executable's path ends in `..`")?
             .to_string_lossy(),
-        folder = str::from_utf8(&folder)?,
-        file = str::from_utf8(&file)?,
+        folder = folder,
+        file = file,
         path = path
     );
 
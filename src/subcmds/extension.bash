@@ -0,0 +1,142 @@
+# Sourced by `passrs <extension>`, with the path to the extension and its
+# arguments. It defines the environment and helper functions that `pass` makes
+# available to extensions, and then sources the extension itself, just like
+# `pass` does. The cmd_* functions are implemented by running passrs.
+
+set -o pipefail
+umask "${PASSWORD_STORE_UMASK:-077}"
+
+GPG_OPTS=( $GPG_OPTS )
+GPG_TTY="${GPG_TTY:-$(tty 2>/dev/null)}"
+[[ -n $GPG_TTY ]] && export GPG_TTY
+
+yesno() {
+	[[ -t 0 ]] || return 0
+	local response
+	read -r -p "$1 [y/N] " response
+	[[ $response == [yY] ]] || exit 1
+}
+
+die() {
+	echo "$@" >&2
+	exit 1
+}
+
+check_sneaky_paths() {
+	local path
+	for path in "$@"; do
+		[[ $path =~ /\.\.$ || $path =~ ^\.\./ || $path =~ /\.\./ || $path =~ ^\.\.$ ]] && die "Error: You've attempted to pass a sneaky path to $PROGRAM. Go home."
+	done
+}
+
+set_git() {
+	INNER_GIT_DIR="${1%/*}"
+	while [[ ! -d $INNER_GIT_DIR && ${INNER_GIT_DIR%/*}/ == "${PREFIX%/}/"* ]]; do
+		INNER_GIT_DIR="${INNER_GIT_DIR%/*}"
+	done
+	[[ $(git -C "$INNER_GIT_DIR" rev-parse --is-inside-work-tree 2>/dev/null) == true ]] || INNER_GIT_DIR=""
+}
+
+git_commit() {
+	local sign=""
+	[[ -n $INNER_GIT_DIR ]] || return
+	[[ $(git -C "$INNER_GIT_DIR" config --bool --get pass.signcommits) == true ]] && sign="-S"
+	git -C "$INNER_GIT_DIR" commit $sign -m "$1"
+}
+
+git_add_file() {
+	[[ -n $INNER_GIT_DIR ]] || return
+	git -C "$INNER_GIT_DIR" add "$1" || return
+	[[ -n $(git -C "$INNER_GIT_DIR" status --porcelain "$1") ]] || return
+	git_commit "$2"
+}
+
+verify_file() {
+	"$PASSRS" verify-file "$1" || exit 1
+}
+
+set_gpg_recipients() {
+	GPG_RECIPIENT_ARGS=( )
+	GPG_RECIPIENTS=( )
+
+	local gpg_id
+	if [[ -n $PASSWORD_STORE_KEY ]]; then
+		for gpg_id in $PASSWORD_STORE_KEY; do
+			GPG_RECIPIENT_ARGS+=( "-r" "$gpg_id" )
+			GPG_RECIPIENTS+=( "$gpg_id" )
+		done
+		return
+	fi
+
+	local current="$PREFIX/$1"
+	while [[ $current != "$PREFIX" && ! -f $current/.gpg-id ]]; do
+		current="${current%/*}"
+	done
+	current="$current/.gpg-id"
+
+	[[ -f $current ]] || die "Error: You must run: $PROGRAM init your-gpg-id before you may use the password store."
+	verify_file "$current"
+
+	while read -r gpg_id; do
+		gpg_id="${gpg_id%%#*}"
+		gpg_id="${gpg_id%"${gpg_id##*[![:space:]]}"}"
+		[[ -n $gpg_id ]] || continue
+		GPG_RECIPIENT_ARGS+=( "-r" "$gpg_id" )
+		GPG_RECIPIENTS+=( "$gpg_id" )
+	done < "$current"
+}
+
+tmpdir() {
+	[[ -n $SECURE_TMPDIR ]] && return
+	local template="$PROGRAM.XXXXXXXXXXXXX"
+	if [[ -d /dev/shm && -w /dev/shm && -x /dev/shm ]]; then
+		SECURE_TMPDIR="$(mktemp -d "/dev/shm/$template")"
+	else
+		[[ $1 == "nowarn" ]] || yesno "Your system does not have /dev/shm, so temporary files will be written to disk. Continue?"
+		SECURE_TMPDIR="$(mktemp -d "${TMPDIR:-/tmp}/$template")"
+	fi
+	trap 'rm -rf "$SECURE_TMPDIR"' EXIT
+}
+
+clip() {
+	if [[ -n $WAYLAND_DISPLAY ]]; then
+		local copy=( wl-copy ) clear=( wl-copy --clear )
+		[[ $X_SELECTION == primary ]] && copy+=( --primary ) clear+=( --primary )
+	elif [[ -n $DISPLAY ]]; then
+		local copy=( xclip -selection "$X_SELECTION" ) clear=( xclip -selection "$X_SELECTION" )
+	else
+		die "Error: No X11 or Wayland display detected"
+	fi
+	echo -n "$1" | "${copy[@]}" || die "Error: Could not copy data to the clipboard"
+	( sleep "$CLIP_TIME"; echo -n "" | "${clear[@]}" ) >/dev/null 2>&1 &
+	disown
+	echo "Copied $2 to clipboard. Will clear in $CLIP_TIME seconds."
+}
+
+qrcode() {
+	command -v qrencode >/dev/null || die "Error: qrencode is not installed"
+	echo -n "$1" | qrencode -t utf8
+}
+
+cmd_version() { "$PASSRS" --version; }
+cmd_usage() { "$PASSRS" --help; }
+cmd_init() { "$PASSRS" init "$@"; }
+cmd_show() { "$PASSRS" show "$@"; }
+cmd_find() { "$PASSRS" find "$@"; }
+cmd_grep() { "$PASSRS" grep "$@"; }
+cmd_insert() { "$PASSRS" insert "$@"; }
+cmd_edit() { "$PASSRS" edit "$@"; }
+cmd_generate() { "$PASSRS" generate "$@"; }
+cmd_delete() { "$PASSRS" rm "$@"; }
+cmd_git() { "$PASSRS" git "$@"; }
+
+cmd_copy_move() {
+	local command=mv
+	[[ $1 == copy ]] && command=cp
+	shift
+	"$PASSRS" "$command" "$@"
+}
+
+extension="$1"
+shift
+source "$extension" "$@"
@@ -0,0 +1,130 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use crate::consts::{
+    PASSRS_SYSTEM_EXTENSION_DIR, PASSWORD_STORE_CHARACTER_SET,
+    PASSWORD_STORE_CHARACTER_SET_NO_SYMBOLS, PASSWORD_STORE_CLIP_TIME, PASSWORD_STORE_DIR,
+    PASSWORD_STORE_ENABLE_EXTENSIONS, PASSWORD_STORE_EXTENSIONS_DIR,
+    PASSWORD_STORE_GENERATED_LENGTH, PASSWORD_STORE_GPG_OPTS, PASSWORD_STORE_X_SELECTION,
+};
+use crate::util;
+use crate::{PassrsError, Result};
+
+/// The options `pass` always passes to gpg, after `PASSWORD_STORE_GPG_OPTS`.
+const GPG_OPTS: &str = "--quiet --yes --compress-algo=none --no-encrypt-to";
+/// Defines the helpers `pass` provides (`cmd_show`, `git_add_file`,
+/// `check_sneaky_paths`, ...) and then sources the extension.
+const SHIM: &str = include_str!("extension.bash");
+
+pub(crate) fn extension(args: Vec<String>) -> Result<()> {
+    let (command, args) = args.split_first().ok_or("No subcommand was specified")?;
+
+    util::check_sneaky_paths(command)?;
+
+    let extension = self::find_extension(command)?;
+    let status = Command::new("bash")
+        .arg("-c")
+        .arg(SHIM)
+        .arg("passrs")
+        .arg(&extension)
+        .args(args)
+        .envs(self::environment(command))
+        .env("PASSRS", env::current_exe()?)
+        .status()?;
+
+    // Like `pass`, exit with whatever the extension exited with
+    if !status.success() {
+        process::exit(status.code().unwrap_or(1));
+    }
+
+    Ok(())
+}
+
+/// Looks up `<command>.bash` in the user's extension dir, if enabled, and then
+/// in the system extension dir, in the same order as `pass`. User extensions
+/// have to be signed by one of `PASSWORD_STORE_SIGNING_KEY` if it is set, just
+/// like `pass` requires.
+fn find_extension(command: &str) -> Result<PathBuf> {
+    let filename = format!("{}.bash", command);
+    let user_extension = PASSWORD_STORE_EXTENSIONS_DIR.join(&filename);
+    let system_extension = PASSRS_SYSTEM_EXTENSION_DIR.join(&filename);
+
+    if *PASSWORD_STORE_ENABLE_EXTENSIONS && self::is_executable(&user_extension) {
+        self::verify_permissions(&user_extension)?;
+        util::verify_file(&user_extension)?;
+
+        return Ok(user_extension);
+    }
+
+    if self::is_executable(&system_extension) {
+        self::verify_permissions(&system_extension)?;
+
+        return Ok(system_extension);
+    }
+
+    Err(PassrsError::UnknownSubcommand(command.to_owned()).into())
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Anybody could swap out an extension that is, or lives in a directory that
+/// is, world-writable.
+fn verify_permissions(path: &Path) -> Result<()> {
+    let dir = path.parent().ok_or("Extension had no parent directory")?;
+
+    for path in &[path, dir] {
+        if fs::metadata(path)?.permissions().mode() & 0o002 != 0 {
+            return Err(PassrsError::InsecureExtension(path.display().to_string()).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// The variables `pass` defines before sourcing an extension.
+fn environment(command: &str) -> Vec<(&'static str, String)> {
+    let prefix = PASSWORD_STORE_DIR.display().to_string();
+    let gpg_opts = [PASSWORD_STORE_GPG_OPTS.join(" ").as_str(), GPG_OPTS]
+        .join(" ")
+        .trim_start()
+        .to_owned();
+
+    vec![
+        ("PASSWORD_STORE_DIR", prefix.clone()),
+        ("GIT_CEILING_DIRECTORIES", format!("{}/..", prefix)),
+        ("PREFIX", prefix),
+        (
+            "EXTENSIONS",
+            PASSWORD_STORE_EXTENSIONS_DIR.display().to_string(),
+        ),
+        (
+            "SYSTEM_EXTENSION_DIR",
+            PASSRS_SYSTEM_EXTENSION_DIR.display().to_string(),
+        ),
+        ("X_SELECTION", PASSWORD_STORE_X_SELECTION.to_owned()),
+        ("CLIP_TIME", PASSWORD_STORE_CLIP_TIME.to_owned()),
+        (
+            "GENERATED_LENGTH",
+            PASSWORD_STORE_GENERATED_LENGTH.to_string(),
+        ),
+        (
+            "CHARACTER_SET",
+            PASSWORD_STORE_CHARACTER_SET.iter().collect(),
+        ),
+        (
+            "CHARACTER_SET_NO_SYMBOLS",
+            PASSWORD_STORE_CHARACTER_SET_NO_SYMBOLS.iter().collect(),
+        ),
+        ("GPG", "gpg".to_owned()),
+        ("GPG_OPTS", gpg_opts),
+        ("PROGRAM", "passrs".to_owned()),
+        ("COMMAND", command.to_owned()),
+    ]
+}
@@ -3,13 +3,25 @@ use std::io::{self, Write};
 use termion::color;
 use termion::style;
 
+use crate::cli::OutputFormat;
 use crate::consts::STORE_LEN;
 use crate::util;
 use crate::{PassrsError, Result};
 
-pub(crate) fn find(name: String) -> Result<()> {
+pub(crate) fn find(name: String, format: OutputFormat) -> Result<()> {
     let matches = util::find_matches(&name)?;
 
+    if format == OutputFormat::Json {
+        let matches: Vec<&str> = matches
+            .iter()
+            .map(|matched| util::strip_extension(&matched[*STORE_LEN..]))
+            .collect();
+
+        writeln!(io::stdout(), "{}", serde_json::to_string(&matches)?)?;
+
+        return Ok(());
+    }
+
     // FIXME: For now, just following `gopass`s lead: straight-up print the
     // matches to stdout.
     // Printing trees is a pain in the ass. Filtering them is even worse.
@@ -18,7 +30,7 @@ pub(crate) fn find(name: String) -> Result<()> {
         for matched in &matches {
             let separator = matched.rfind('/').ok_or("Path did not contain a folder")? + 1;
             let pre = &matched[*STORE_LEN..separator];
-            let file = &matched[separator..matched.rfind(".gpg").unwrap()];
+            let file = util::strip_extension(&matched[separator..]);
             let formatted_path = format!(
                 "{blue}{bold}{}{nobold}{}{reset}",
                 pre,
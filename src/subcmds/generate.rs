@@ -1,7 +1,5 @@
-use std::fs;
 use std::io::{self, Write};
-use std::os::unix::fs::OpenOptionsExt;
-use std::str;
+use std::path::{Path, PathBuf};
 
 use termion::color;
 use termion::style;
@@ -9,13 +7,21 @@ use termion::style;
 use crate::clipboard;
 use crate::consts::{
     PASSWORD_STORE_CHARACTER_SET, PASSWORD_STORE_CHARACTER_SET_NO_SYMBOLS,
-    PASSWORD_STORE_CLIP_TIME, PASSWORD_STORE_GENERATED_LENGTH, PASSWORD_STORE_UMASK,
+    PASSWORD_STORE_CLIP_TIME, PASSWORD_STORE_GENERATED_LENGTH,
 };
+use crate::passphrase::{self, PassphraseOptions, Wordlist};
+use crate::policy::Policy;
 use crate::util;
 use crate::util::EditMode;
 use crate::{Flags, PassrsError, Result};
 
-pub(crate) fn generate(secret_name: String, length: Option<usize>, flags: Flags) -> Result<()> {
+pub(crate) fn generate(
+    secret_name: String,
+    length: Option<usize>,
+    policy: Policy,
+    passphrase: Option<(PassphraseOptions, Option<PathBuf>)>,
+    flags: Flags,
+) -> Result<()> {
     let clip = flags.clip;
     let force = flags.force;
     let in_place = flags.in_place;
@@ -27,31 +33,23 @@ pub(crate) fn generate(secret_name: String, length: Option<usize>, flags: Flags)
     if !force && !in_place && util::path_exists(&path)? {
         let prompt = format!("An entry exists for {}. Overwrite it?", secret_name);
 
-        if util::prompt_yesno(prompt)? {
-            fs::OpenOptions::new()
-                .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
-                .write(true)
-                .truncate(!in_place)
-                .open(&path)?;
-        } else {
+        if !util::prompt_yesno(prompt)? {
             return Err(PassrsError::UserAbort.into());
         }
     }
 
-    // NOTE: default character sets defined in consts.rs
-    let set = if no_symbols {
-        &*PASSWORD_STORE_CHARACTER_SET_NO_SYMBOLS
-    } else {
-        &*PASSWORD_STORE_CHARACTER_SET
-    };
-    let len = if let Some(length) = length {
-        length
-    } else {
-        *PASSWORD_STORE_GENERATED_LENGTH
-    };
+    let (secret, entropy) = match passphrase {
+        Some((options, wordlist)) => {
+            let wordlist = match wordlist {
+                Some(wordlist) => Wordlist::from_file(wordlist)?,
+                None => Wordlist::bundled()?,
+            };
+            let passphrase = passphrase::generate(&wordlist, &options)?;
 
-    let secret_bytes = util::generate_chars_from_set(set, len)?;
-    let secret = str::from_utf8(&secret_bytes)?.to_owned();
+            (passphrase.text, Some(passphrase.entropy))
+        }
+        None => (self::new_secret(&path, length, policy, no_symbols)?, None),
+    };
 
     if clip {
         clipboard::clip(&secret, force)?;
@@ -91,7 +89,7 @@ pub(crate) fn generate(secret_name: String, length: Option<usize>, flags: Flags)
             )?;
         }
     } else {
-        util::encrypt_bytes_into_file(&secret_bytes, &path, EditMode::Clobber)?;
+        util::encrypt_bytes_into_file(secret.as_bytes(), &path, EditMode::Clobber)?;
         util::commit(
             Some([&path]),
             format!("Save generated secret for {}", secret_name),
@@ -110,5 +108,31 @@ pub(crate) fn generate(secret_name: String, length: Option<usize>, flags: Flags)
         }
     }
 
+    if let Some(entropy) = entropy {
+        writeln!(io::stdout(), "Entropy: {:.1} bits", entropy)?;
+    }
+
     Ok(())
 }
+
+/// Generates a secret for the entry at `path` from the configured character
+/// set, following the entry's password policy with `policy` taking precedence.
+pub(crate) fn new_secret(
+    path: &Path,
+    length: Option<usize>,
+    policy: Policy,
+    no_symbols: bool,
+) -> Result<String> {
+    // NOTE: default character sets defined in consts.rs
+    let set = if no_symbols {
+        &*PASSWORD_STORE_CHARACTER_SET_NO_SYMBOLS
+    } else {
+        &*PASSWORD_STORE_CHARACTER_SET
+    };
+    let policy = Policy::for_entry(path)?.with_overrides(policy);
+    let len = length
+        .or(policy.length)
+        .unwrap_or(*PASSWORD_STORE_GENERATED_LENGTH);
+
+    policy.generate(set, len)
+}
@@ -0,0 +1,40 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+
+use crate::sync;
+use crate::util;
+use crate::Result;
+
+/// Prints the decrypted contents of `file`, for git's `textconv`.
+pub(crate) fn diff_driver(file: PathBuf) -> Result<()> {
+    let plain = util::decrypt_file_into_bytes(&file)?;
+
+    io::stdout().write_all(&plain)?;
+
+    Ok(())
+}
+
+/// Merges the decrypted versions of an entry, for git's merge drivers. Like any
+/// merge driver, exits with 1 if conflicts are left in `ours`.
+pub(crate) fn merge_driver(
+    base: PathBuf,
+    ours: PathBuf,
+    theirs: PathBuf,
+    path: String,
+) -> Result<()> {
+    let conflicts = sync::merge_entry_files(&base, &ours, &theirs, &path)?;
+
+    if conflicts > 0 {
+        writeln!(
+            io::stderr(),
+            "{} has {} conflicts; resolve them with `passrs edit {}`",
+            path,
+            conflicts,
+            util::strip_extension(&path)
+        )?;
+        process::exit(1);
+    }
+
+    Ok(())
+}
@@ -1,79 +1,385 @@
+use std::collections::BTreeMap;
+use std::env;
 use std::io;
+use std::iter;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
 
-use grep_printer::{ColorSpecs, StandardBuilder};
-use grep_regex::RegexMatcher;
-use grep_searcher::{BinaryDetection, SearcherBuilder};
+use clap::Clap;
+use grep_matcher::{LineTerminator, Match, Matcher, NoCaptures, NoError};
+use grep_printer::{ColorSpecs, Standard, StandardBuilder, Summary, SummaryBuilder, SummaryKind};
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_searcher::{BinaryDetection, Searcher, SearcherBuilder};
 use termcolor::{BufferedStandardStream, ColorChoice, StandardStream};
 use termion::style;
 use walkdir::WalkDir;
 
-use crate::consts::{PASSWORD_STORE_DIR, STORE_LEN};
+use crate::agent;
+use crate::consts::{PASSRS_GREP_CACHE_TTL, PASSWORD_STORE_DIR, STORE_LEN};
+use crate::entry;
 use crate::util;
-use crate::Result;
-
-// Takes ~40 seconds to search the entirety of my ~400 file store
-pub(crate) fn grep(search: String) -> Result<()> {
-    let mut searcher = SearcherBuilder::new()
-        .binary_detection(BinaryDetection::quit(b'\x00'))
-        .build();
-    let matcher = RegexMatcher::new_line_matcher(&search)?;
-    let mut printer = StandardBuilder::new()
-        .color_specs(ColorSpecs::new(&[
-            "path:fg:blue".parse()?,
-            "line:fg:green".parse()?,
-            "match:fg:red".parse()?,
-            "match:style:bold".parse()?,
-        ]))
-        .heading(true)
-        // Shorter and more concise way to do this is to use grep_cli, but we
-        // only use it for 2 things, and it pulls in 3 extra dependencies. Both
-        // cli::stdout() and cli::is_tty_stdout() can be implemented here fairly
-        // easily. We use `termcolor` because I want colors for matches, so we
-        // can get `StandardStream` and `BufferedStandardStream` for free.
-        .build({
-            if termion::is_tty(&io::stdout()) {
-                let out = StandardStream::stdout(ColorChoice::Auto);
-                stream::StandardStreamKind::LineBuffered(out)
-            } else {
-                let out = BufferedStandardStream::stdout(ColorChoice::Never);
-                stream::StandardStreamKind::BlockBuffered(out)
+use crate::{PassrsError, Result};
+
+#[derive(Clap, Debug, Default, Clone, PartialEq)]
+#[clap(name = "GREPOPTIONS")]
+/// Options accepted by `grep`, either on the command line or in $GREPOPTIONS.
+pub(crate) struct GrepOptions {
+    #[clap(long, short = "i")]
+    /// Ignore case distinctions in the pattern.
+    pub ignore_case: bool,
+    #[clap(long, short = "F")]
+    /// Interpret the pattern as a fixed string instead of a regex.
+    pub fixed_strings: bool,
+    #[clap(long, short = "w")]
+    /// Only match whole words.
+    pub word_regexp: bool,
+    #[clap(long, short = "v")]
+    /// Select non-matching lines.
+    pub invert_match: bool,
+    #[clap(long, short = "A", value_name = "num")]
+    /// Print num lines of trailing context after matching lines.
+    pub after_context: Option<usize>,
+    #[clap(long, short = "B", value_name = "num")]
+    /// Print num lines of leading context before matching lines.
+    pub before_context: Option<usize>,
+    #[clap(long, short = "C", value_name = "num")]
+    /// Print num lines of context around matching lines.
+    pub context: Option<usize>,
+    #[clap(long, short = "c", conflicts_with = "files-with-matches")]
+    /// Only print the number of matching lines in each secret.
+    pub count: bool,
+    #[clap(long, short = "l", conflicts_with = "count")]
+    /// Only print the names of secrets with matching lines.
+    pub files_with_matches: bool,
+}
+
+impl GrepOptions {
+    /// Parses $GREPOPTIONS (whitespace-separated, like `pass` does when it
+    /// passes them on to grep).
+    pub(crate) fn from_env() -> Result<GrepOptions> {
+        let opts = env::var("GREPOPTIONS").unwrap_or_default();
+
+        if opts.trim().is_empty() {
+            return Ok(GrepOptions::default());
+        }
+
+        let args = iter::once("GREPOPTIONS").chain(opts.split_whitespace());
+
+        Ok(GrepOptions::try_parse_from(args).map_err(|e| format!("Invalid GREPOPTIONS: {}", e))?)
+    }
+
+    /// Combines these options with `other`, preferring the values set in
+    /// `self` (e.g. on the command line) over those in `other` (e.g. in
+    /// $GREPOPTIONS).
+    pub(crate) fn merge(self, other: GrepOptions) -> GrepOptions {
+        GrepOptions {
+            ignore_case: self.ignore_case || other.ignore_case,
+            fixed_strings: self.fixed_strings || other.fixed_strings,
+            word_regexp: self.word_regexp || other.word_regexp,
+            invert_match: self.invert_match || other.invert_match,
+            after_context: self.after_context.or(other.after_context),
+            before_context: self.before_context.or(other.before_context),
+            context: self.context.or(other.context),
+            count: self.count || (other.count && !self.files_with_matches),
+            files_with_matches: self.files_with_matches
+                || (other.files_with_matches && !self.count),
+        }
+    }
+
+    fn matcher(&self, search: &str) -> Result<RegexMatcher> {
+        let pattern = if self.fixed_strings {
+            regex::escape(search)
+        } else {
+            search.to_owned()
+        };
+        let matcher = RegexMatcherBuilder::new()
+            .line_terminator(Some(b'\n'))
+            .case_insensitive(self.ignore_case)
+            .word(self.word_regexp)
+            .build(&pattern)?;
+
+        Ok(matcher)
+    }
+
+    fn searcher(&self) -> Searcher {
+        SearcherBuilder::new()
+            .binary_detection(BinaryDetection::quit(b'\x00'))
+            .invert_match(self.invert_match)
+            .after_context(self.after_context.or(self.context).unwrap_or(0))
+            .before_context(self.before_context.or(self.context).unwrap_or(0))
+            .build()
+    }
+}
+
+enum Printer<W> {
+    Standard(Standard<W>),
+    Summary(Summary<W>),
+}
+
+// Decrypting is by far the slowest part (~40 seconds for the entirety of my
+// ~400 file store when done sequentially), so it's spread over a pool of
+// workers; searching and printing happen on this thread, in walk order, so the
+// output is the same no matter which worker finishes first
+pub(crate) fn grep(
+    search: String,
+    subfolder: Option<String>,
+    fields: Vec<String>,
+    options: GrepOptions,
+) -> Result<()> {
+    let root = match subfolder {
+        Some(subfolder) => {
+            let root = util::exact_path(&subfolder)?;
+
+            if !root.is_dir() {
+                return Err(PassrsError::PathDoesntExist(subfolder).into());
+            }
+
+            root
+        }
+        None => PASSWORD_STORE_DIR.to_path_buf(),
+    };
+    let options = options.merge(GrepOptions::from_env()?);
+    let mut searcher = options.searcher();
+    let matcher = FieldMatcher {
+        matcher: options.matcher(&search)?,
+        fields,
+        invert_match: options.invert_match,
+    };
+    let color_specs = ColorSpecs::new(&[
+        "path:fg:blue".parse()?,
+        "line:fg:green".parse()?,
+        "match:fg:red".parse()?,
+        "match:style:bold".parse()?,
+    ]);
+    // Shorter and more concise way to do this is to use grep_cli, but we only
+    // use it for 2 things, and it pulls in 3 extra dependencies. Both
+    // cli::stdout() and cli::is_tty_stdout() can be implemented here fairly
+    // easily. We use `termcolor` because I want colors for matches, so we can
+    // get `StandardStream` and `BufferedStandardStream` for free.
+    let out = if termion::is_tty(&io::stdout()) {
+        let out = StandardStream::stdout(ColorChoice::Auto);
+        stream::StandardStreamKind::LineBuffered(out)
+    } else {
+        let out = BufferedStandardStream::stdout(ColorChoice::Never);
+        stream::StandardStreamKind::BlockBuffered(out)
+    };
+    let mut printer = if options.count || options.files_with_matches {
+        let kind = if options.count {
+            SummaryKind::Count
+        } else {
+            SummaryKind::PathWithMatch
+        };
+
+        // Like pass, which skips secrets that grep found nothing in, don't
+        // print counts of 0
+        Printer::Summary(
+            SummaryBuilder::new()
+                .kind(kind)
+                .color_specs(color_specs)
+                .exclude_zero(true)
+                .build(out),
+        )
+    } else {
+        Printer::Standard(
+            StandardBuilder::new()
+                .color_specs(color_specs)
+                .heading(true)
+                .build(out),
+        )
+    };
+    let entries = Arc::new(self::entries(&root)?);
+    let next = Arc::new(AtomicUsize::new(0));
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(entries.len());
+    let (tx, rx) = mpsc::channel();
+
+    // The plaintext is kept by an agent, so that the next grep can reuse it
+    if *PASSRS_GREP_CACHE_TTL > 0 && !entries.is_empty() {
+        agent::spawn(Duration::from_secs(*PASSRS_GREP_CACHE_TTL))?;
+    }
+
+    for _ in 0..workers {
+        let entries = Arc::clone(&entries);
+        let next = Arc::clone(&next);
+        let tx = tx.clone();
+
+        thread::spawn(move || loop {
+            let idx = next.fetch_add(1, Ordering::SeqCst);
+
+            if idx >= entries.len() {
+                break;
+            }
+
+            // If the receiver hung up, an earlier entry failed to decrypt and
+            // there's no point in continuing
+            let contents = util::decrypt_file_into_bytes(&entries[idx]);
+
+            if tx.send((idx, contents)).is_err() {
+                break;
             }
         });
+    }
+
+    drop(tx);
+
+    let mut pending = BTreeMap::new();
+    let mut current = 0;
+
+    for (idx, contents) in rx {
+        pending.insert(idx, contents);
 
-    for dirent in WalkDir::new(&*PASSWORD_STORE_DIR) {
+        while let Some(contents) = pending.remove(&current) {
+            let path = &entries[current];
+            let contents = contents?;
+            // The password is never a field, so it's blanked out rather than
+            // shown as context; the line numbers stay the same
+            let contents = if matcher.fields.is_empty() {
+                &contents[..]
+            } else {
+                let newline = contents.iter().position(|&b| b == b'\n');
+
+                &contents[newline.unwrap_or(contents.len())..]
+            };
+            // I want path[..separator] to include the final slash, so add 1
+            let separator = path.rfind('/').ok_or("Path did not contain a folder")? + 1;
+            let pre = &path[*STORE_LEN..separator];
+            let file = util::strip_extension(&path[separator..]);
+            let formatted_path = format!(
+                "{bold}{}{nobold}{}",
+                pre,
+                file,
+                bold = style::Bold,
+                nobold = style::NoBold
+            );
+
+            match printer {
+                Printer::Standard(ref mut printer) => searcher.search_slice(
+                    &matcher,
+                    contents,
+                    printer.sink_with_path(&matcher, &formatted_path),
+                )?,
+                Printer::Summary(ref mut printer) => searcher.search_slice(
+                    &matcher,
+                    contents,
+                    printer.sink_with_path(&matcher, &formatted_path),
+                )?,
+            }
+
+            current += 1;
+        }
+    }
+
+    if current < entries.len() {
+        return Err("A worker panicked while decrypting entries".into());
+    }
+
+    Ok(())
+}
+
+/// Wraps the matcher built from the pattern so that it only matches in the
+/// `key: value` lines whose key is one of `fields` (ignoring case), or in every
+/// line if `fields` is empty. The whole entry is still searched, so line
+/// numbers and context are those of the entry itself.
+struct FieldMatcher {
+    matcher: RegexMatcher,
+    fields: Vec<String>,
+    invert_match: bool,
+}
+
+impl FieldMatcher {
+    fn is_field(&self, line: &[u8]) -> bool {
+        if self.fields.is_empty() {
+            return true;
+        }
+
+        let line = String::from_utf8_lossy(line);
+
+        match entry::parse_field(&line) {
+            Some((key, _)) => self
+                .fields
+                .iter()
+                .any(|f| f.trim().eq_ignore_ascii_case(key)),
+            None => false,
+        }
+    }
+}
+
+impl Matcher for FieldMatcher {
+    type Captures = NoCaptures;
+    type Error = NoError;
+
+    fn find_at(&self, haystack: &[u8], mut at: usize) -> Result<Option<Match>, NoError> {
+        while at <= haystack.len() {
+            let start = haystack[..at]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |i| i + 1);
+            let end = haystack[at..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(haystack.len(), |i| at + i);
+
+            if self.is_field(&haystack[start..end]) {
+                // Don't let the pattern match past the end of this line
+                if let Some(m) = self.matcher.find_at(&haystack[..end], at)? {
+                    return Ok(Some(m));
+                }
+            } else if self.invert_match && at < haystack.len() {
+                // Other lines are reported as matching, so that inverting the
+                // match doesn't select them either
+                return Ok(Some(Match::new(at, end)));
+            }
+
+            at = end + 1;
+        }
+
+        Ok(None)
+    }
+
+    fn new_captures(&self) -> Result<NoCaptures, NoError> {
+        Ok(NoCaptures::new())
+    }
+
+    fn line_terminator(&self) -> Option<LineTerminator> {
+        self.matcher.line_terminator()
+    }
+}
+
+/// Collects the paths of every entry under `root`, skipping hidden files and
+/// folders, sorted by name so that the output of `grep` is deterministic.
+fn entries(root: &Path) -> Result<Vec<String>> {
+    let mut entries = Vec::new();
+
+    for dirent in WalkDir::new(root)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .map(|s| entry.depth() == 0 || !s.starts_with('.'))
+                .unwrap_or(false)
+        })
+    {
         let entry = dirent?;
         let path = entry
             .path()
             .to_str()
             .ok_or("Entry did not contain a valid path")?;
 
-        if !entry.file_type().is_file() || !path.ends_with(".gpg") {
+        if !entry.file_type().is_file() || !util::is_entry(path) {
             continue;
         }
 
-        // I want path[..separator] to include the final slash, so add 1
-        let separator = path.rfind('/').ok_or("Path did not contain a folder")? + 1;
-        let pre = &path[*STORE_LEN..separator];
-        // We guarantee all paths end in .gpg by this point, so we can cut it
-        // off without a problem (famous last words)
-        let file = &path[separator..path.rfind(".gpg").unwrap()];
-        let contents = util::decrypt_file_into_bytes(path)?;
-        let formatted_path = format!(
-            "{bold}{}{nobold}{}",
-            pre,
-            file,
-            bold = style::Bold,
-            nobold = style::NoBold
-        );
-
-        searcher.search_slice(
-            &matcher,
-            &contents,
-            printer.sink_with_path(&matcher, &formatted_path),
-        )?;
+        entries.push(path.to_owned());
     }
 
-    Ok(())
+    Ok(entries)
 }
 
 mod stream {
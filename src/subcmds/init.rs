@@ -5,24 +5,31 @@ use std::path::{Path, PathBuf};
 use std::str;
 
 use git2::{Commit, Oid, Repository, Signature};
-use gpgme::{Context, Protocol};
 
 use crate::consts::{
-    PASSWORD_STORE_DIR, PASSWORD_STORE_KEY, PASSWORD_STORE_SIGNING_KEY, PASSWORD_STORE_UMASK,
-    STORE_LEN, STORE_STRING,
+    HIDDEN_RECIPIENTS_FILE, PASSWORD_STORE_DIR, PASSWORD_STORE_KEY, PASSWORD_STORE_SIGNING_KEY,
+    PASSWORD_STORE_UMASK, STORE_LEN, STORE_STRING,
 };
-use crate::util;
+use crate::crypto;
+use crate::sync;
+use crate::transaction::Transaction;
+use crate::util::{self, EntryKind};
 use crate::{PassrsError, Result};
 
-pub(crate) fn init(keys: Vec<String>, path: Option<String>) -> Result<()> {
+pub(crate) fn init(keys: Vec<String>, path: Option<String>, age: bool, hidden: bool) -> Result<()> {
     let store = &*PASSWORD_STORE_DIR;
-    let keys = if keys.is_empty() {
+    let kind = if age { EntryKind::Age } else { EntryKind::Gpg };
+    // Like pass, `init ""` deinitializes
+    let deinit = !keys.is_empty() && keys.iter().all(|k| k.trim().is_empty());
+    let keys = if keys.is_empty() && !age {
         &*PASSWORD_STORE_KEY
     } else {
         &keys
     };
 
-    if !util::path_exists(&store)? || util::verify_store_exists().is_err() {
+    let initialized = util::path_exists(&store)? && util::verify_store_exists().is_ok();
+
+    if !initialized {
         if let Some(path) = path {
             writeln!(
                 io::stderr(),
@@ -31,25 +38,32 @@ pub(crate) fn init(keys: Vec<String>, path: Option<String>) -> Result<()> {
                 *STORE_STRING
             )?;
         }
-        if keys.is_empty() {
+        if keys.is_empty() || deinit {
             return Err(PassrsError::StoreDoesntExist.into());
         }
 
-        self::create_store(&store, &keys)?;
+        self::create_store(&store, &keys, kind, hidden)?;
 
         let list = &keys.join(", ");
         let keys = if keys.len() > 1 { &list } else { &keys[0] };
 
         writeln!(io::stdout(), "Password store initialized for {}", keys)?;
-    } else if keys.is_empty() {
-        // Although pass allows the deinitialization of a store, we don't
+    } else if deinit {
+        // Although pass allows the deinitialization of the whole store, we
+        // don't: there would be nothing left to re-encrypt its secrets for
         //   https://git.zx2c4.com/password-store/commit/src/password-store.sh?id=0f0483f789e4819b029cf2f9d8168a6172da4d92
+        let path = path.ok_or("Only substores can be deinitialized")?;
+
+        self::deinit_substore(&util::exact_path(&path)?, kind)?;
+
+        writeln!(io::stdout(), "Password store deinitialized ({})", &path)?;
+    } else if keys.is_empty() {
         return Err(PassrsError::NoPrivateKeyFound.into());
     } else if let Some(path) = path {
         let substore_path = util::exact_path(&path)?;
 
         if !util::path_exists(&substore_path)? {
-            self::create_substore(&store, &substore_path, &keys)?;
+            self::create_substore(&store, &substore_path, &keys, kind, hidden)?;
 
             let list = &keys.join(", ");
             let keys = if keys.len() > 1 { &list } else { &keys[0] };
@@ -61,40 +75,75 @@ pub(crate) fn init(keys: Vec<String>, path: Option<String>) -> Result<()> {
                 &path
             )?;
         } else {
-            util::recrypt_dir(&substore_path, Some(keys))?;
-
             let list = &keys.join(", ");
             let new_keys = if keys.len() > 1 { &list } else { &keys[0] };
-
-            self::update_key(&substore_path, &keys)?;
-            util::commit(
-                None::<&[PathBuf]>,
-                format!(
-                    "Re-encrypt {} using new GPG ID {}",
-                    &substore_path.display().to_string()[*STORE_LEN..],
-                    &new_keys,
-                ),
-            )
-            .map_err(|e| format!("Failed to commit re-encrypting substore: {}", e))?;
+            let message = format!(
+                "Re-encrypt {} using new {} {}",
+                &substore_path.display().to_string()[*STORE_LEN..],
+                self::recipients_name(kind),
+                &new_keys,
+            );
+
+            self::reencrypt(&substore_path, keys, kind, hidden, || {
+                util::commit(None::<&[PathBuf]>, message)
+                    .map_err(|e| format!("Failed to commit re-encrypting substore: {}", e).into())
+            })?;
         }
     } else {
-        util::recrypt_dir(&store, Some(keys))?;
-
         let list = &keys.join(", ");
         let new_keys = if keys.len() > 1 { &list } else { &keys[0] };
+        let message = format!(
+            "Re-encrypt password store using new {} {}",
+            self::recipients_name(kind),
+            &new_keys
+        );
 
-        self::update_key(&store, &keys)?;
-        util::commit(
-            None::<&[PathBuf]>,
-            format!("Re-encrypt password store using new GPG ID {}", &new_keys),
-        )
-        .map_err(|e| format!("Failed to commit re-encrypted store: {}", e))?;
+        self::reencrypt(store, keys, kind, hidden, || {
+            util::commit(None::<&[PathBuf]>, message)
+                .map_err(|e| format!("Failed to commit re-encrypted store: {}", e).into())
+        })?;
+    }
+
+    if initialized {
+        self::setup_git(store)?;
     }
 
     Ok(())
 }
 
-fn update_key<K, P>(path: P, keys: K) -> Result<()>
+/// Updates the recipients of the (sub)store at `path` and re-encrypts its
+/// secrets for them, then runs `commit`. If anything fails, the (sub)store is
+/// left as it was.
+fn reencrypt<F>(
+    path: &Path,
+    keys: &[String],
+    kind: EntryKind,
+    hidden: bool,
+    commit: F,
+) -> Result<()>
+where
+    F: FnOnce() -> Result<()>,
+{
+    let mut tx = Transaction::new();
+
+    self::track_recipients(&mut tx, path, kind)?;
+    self::update_key(path, keys, kind)?;
+    self::update_hidden(path, hidden)?;
+    util::stage_recrypt_dir(&mut tx, path, Some(keys), kind)?;
+    tx.commit(commit)
+}
+
+/// Tracks the recipients file of the (sub)store at `path`, along with its
+/// signature and hidden-recipients marker, in `tx`.
+fn track_recipients(tx: &mut Transaction, path: &Path, kind: EntryKind) -> Result<()> {
+    let recipients_file = path.join(kind.recipients_file());
+
+    tx.track(format!("{}.sig", recipients_file.display()))?;
+    tx.track(&recipients_file)?;
+    tx.track(path.join(HIDDEN_RECIPIENTS_FILE))
+}
+
+fn update_key<K, P>(path: P, keys: K, kind: EntryKind) -> Result<()>
 where
     P: AsRef<Path>,
     K: AsRef<[String]>,
@@ -106,73 +155,74 @@ where
         return Err(PassrsError::NoPrivateKeyFound.into());
     }
 
-    let gpg_ids = verify_keys(keys)?;
-    let gpg_id_file = path.join(".gpg-id");
-    let gpg_id_sigfile = format!("{}.sig", gpg_id_file.display());
-    let mut file = fs::OpenOptions::new()
-        .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
-        .truncate(true)
-        .write(true)
-        .create(true)
-        .open(&gpg_id_file)?;
+    let gpg_ids = verify_keys(keys, kind)?;
+    let gpg_id_file = path.join(kind.recipients_file());
+
+    util::write_atomically(&gpg_id_file, gpg_ids.join("\n"))?;
 
-    file.write_all(gpg_ids.join("\n").as_bytes())?;
+    if kind == EntryKind::Gpg {
+        self::sign_gpg_id(&gpg_id_file)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a detached signature of `gpg_id_file` to `.gpg-id.sig` using the
+/// key(s) in `PASSWORD_STORE_SIGNING_KEY`, which is verified before anything
+/// gets encrypted to the listed keys. If no signing key is set, any stale
+/// signature is removed instead. Returns the signature's path if one was
+/// written.
+///
+/// Only `.gpg-id` files are signed; passage has no equivalent.
+fn sign_gpg_id(gpg_id_file: &Path) -> Result<Option<PathBuf>> {
+    let gpg_id_sigfile = PathBuf::from(format!("{}.sig", gpg_id_file.display()));
 
     if PASSWORD_STORE_SIGNING_KEY.is_empty() {
-        if PathBuf::from(&gpg_id_sigfile).exists() {
+        if gpg_id_sigfile.exists() {
             fs::remove_file(&gpg_id_sigfile)?;
         }
-    } else {
-        let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
-        let mut outbuf: Vec<u8> = Vec::new();
-        let mut file = fs::OpenOptions::new()
-            .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
-            .truncate(true)
-            .write(true)
-            .create(true)
-            .open(&gpg_id_sigfile)?;
-        let signing_keys: Vec<gpgme::Key> = PASSWORD_STORE_SIGNING_KEY
-            .iter()
-            .map(|k| ctx.get_key(k))
-            .filter_map(|k| k.ok())
-            .collect();
-
-        for key in signing_keys {
-            ctx.add_signer(&key)
-                .map_err(|e| format!("Failed to add key {:?} as signer: {}", key.id(), e))?;
-        }
 
-        ctx.sign_detached(gpg_ids.join("\n").as_bytes(), &mut outbuf)?;
-        file.write_all(&outbuf)?;
+        return Ok(None);
     }
 
-    Ok(())
+    let mut ctx = crypto::gpg_context()?;
+    let mut outbuf: Vec<u8> = Vec::new();
+    let signing_keys: Vec<gpgme::Key> = PASSWORD_STORE_SIGNING_KEY
+        .iter()
+        .map(|k| ctx.get_secret_key(k))
+        .filter_map(|k| k.ok())
+        .collect();
+
+    // Otherwise, gpg would sign with the default key, which can't be verified
+    if signing_keys.is_empty() {
+        return Err(PassrsError::NoSigningKeyFound.into());
+    }
+
+    for key in signing_keys {
+        ctx.add_signer(&key)
+            .map_err(|e| format!("Failed to add key {:?} as signer: {}", key.id(), e))?;
+    }
+
+    ctx.sign_detached(fs::read(gpg_id_file)?, &mut outbuf)?;
+
+    util::write_atomically(&gpg_id_sigfile, &outbuf)?;
+    util::verify_file(gpg_id_file)?;
+
+    Ok(Some(gpg_id_sigfile))
 }
 
-fn verify_keys<S>(gpg_keys: S) -> Result<Vec<String>>
+fn verify_keys<S>(gpg_keys: S, kind: EntryKind) -> Result<Vec<String>>
 where
     S: AsRef<[String]>,
 {
     let gpg_keys = gpg_keys.as_ref();
     let mut keys = Vec::new();
-    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+    let backend = crypto::backend_for(kind)?;
 
+    // Recipients only need a public key in our keyring; pass happily encrypts
+    // to keys we don't hold the secret half of
     for key in gpg_keys {
-        if let Ok(secret_key) = ctx.get_secret_key(key) {
-            let email = secret_key
-                .user_ids()
-                .next()
-                .ok_or("Secret key didn't have any user IDs")?
-                .email();
-            let user_id = match email {
-                Ok(email) => email.to_owned(),
-                _ => key.to_owned(),
-            };
-
-            keys.push(user_id);
-        } else {
-            continue;
-        }
+        keys.push(backend.user_id(key)?);
     }
 
     if keys.is_empty() {
@@ -219,14 +269,131 @@ where
     Ok((tree_id, sig, parents))
 }
 
-fn create_store<P, S>(path: P, gpg_keys: S) -> Result<()>
+/// Removes the recipients file of the substore at `path` (along with its
+/// signature and hidden-recipients marker), re-encrypts the substore's secrets
+/// for the recipients of its parent, and commits the result. If anything
+/// fails, the substore is left as it was.
+fn deinit_substore(path: &Path, kind: EntryKind) -> Result<()> {
+    if !util::path_exists(path)? {
+        return Err(PassrsError::PathDoesntExist(path.display().to_string()).into());
+    }
+
+    let recipients_file = util::find_recipients_file(path, kind)?;
+    let mut tx = Transaction::new();
+
+    self::track_recipients(&mut tx, path, kind)?;
+
+    for file in &[
+        recipients_file.clone(),
+        PathBuf::from(format!("{}.sig", recipients_file.display())),
+        path.join(HIDDEN_RECIPIENTS_FILE),
+    ] {
+        if file.exists() {
+            fs::remove_file(file)?;
+        }
+    }
+
+    // Now encrypts for the parent's recipients
+    util::stage_recrypt_dir(&mut tx, path, None, kind)?;
+    tx.commit(|| {
+        util::commit(
+            None::<&[PathBuf]>,
+            format!(
+                "Deinitialize {}",
+                &recipients_file.display().to_string()[*STORE_LEN..]
+            ),
+        )
+        .map_err(|e| format!("Failed to commit deinitializing substore: {}", e).into())
+    })
+}
+
+/// Creates or removes the marker that makes entries in the (sub)store at `path`
+/// hide their recipients. Returns the marker's path if it was created.
+fn update_hidden(path: &Path, hidden: bool) -> Result<Option<PathBuf>> {
+    let marker = path.join(HIDDEN_RECIPIENTS_FILE);
+
+    if hidden {
+        fs::OpenOptions::new()
+            .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
+            .write(true)
+            .create(true)
+            .open(&marker)?;
+
+        Ok(Some(marker))
+    } else {
+        if marker.exists() {
+            fs::remove_file(&marker)?;
+        }
+
+        Ok(None)
+    }
+}
+
+fn recipients_name(kind: EntryKind) -> &'static str {
+    match kind {
+        EntryKind::Gpg => "GPG ID",
+        EntryKind::Age => "age recipients",
+    }
+}
+
+/// Makes git diff and merge entries by their plaintext; see
+/// [`sync::setup_git_drivers`].
+///
+/// [`sync::setup_git_drivers`]: ../../sync/fn.setup_git_drivers.html
+const GITATTRIBUTES: &str = "*.gpg diff=gpg merge=passrs\n*.age diff=age merge=passrs\n";
+
+/// Installs the git drivers in the repository of an existing store, and adds
+/// whatever [`GITATTRIBUTES`] lines its `.gitattributes` lacks, committing it.
+/// Stores created by pass, or cloned from elsewhere, would otherwise never get
+/// them. Does nothing if the store isn't a git repository.
+///
+/// [`GITATTRIBUTES`]: constant.GITATTRIBUTES.html
+fn setup_git(store: &Path) -> Result<()> {
+    let repo = match Repository::open(store) {
+        Ok(repo) => repo,
+        Err(_) => return Ok(()),
+    };
+
+    sync::setup_git_drivers(&mut repo.config()?)?;
+
+    let gitattributes_path = store.join(".gitattributes");
+    let existing = fs::read_to_string(&gitattributes_path).unwrap_or_default();
+    let missing: String = GITATTRIBUTES
+        .lines()
+        .filter(|line| !existing.lines().any(|l| l.trim() == *line))
+        .map(|line| format!("{}\n", line))
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
+        .append(true)
+        .create(true)
+        .open(&gitattributes_path)?;
+
+    if !existing.is_empty() && !existing.ends_with('\n') {
+        file.write_all(b"\n")?;
+    }
+
+    file.write_all(missing.as_bytes())?;
+
+    util::commit(
+        Some([&gitattributes_path]),
+        "Configure git to diff and merge entries by their plaintext",
+    )
+}
+
+fn create_store<P, S>(path: P, gpg_keys: S, kind: EntryKind, hidden: bool) -> Result<()>
 where
     P: AsRef<Path>,
     S: AsRef<[String]>,
 {
     let path = path.as_ref();
     let gpg_keys = gpg_keys.as_ref();
-    let gpg_ids = self::verify_keys(gpg_keys)?;
+    let gpg_ids = self::verify_keys(gpg_keys, kind)?;
 
     if fs::metadata(&path).is_err() {
         fs::create_dir_all(&path)?;
@@ -237,14 +404,9 @@ where
     // which is intercepted... I don't like this and thus force the creation of
     // a git repository
     if let Ok(repo) = Repository::init(&path) {
-        let gpg_id_path = path.join(".gpg-id");
-        let mut file = fs::OpenOptions::new()
-            .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
-            .write(true)
-            .create(true)
-            .open(&gpg_id_path)?;
+        let gpg_id_path = path.join(kind.recipients_file());
 
-        file.write_all(gpg_ids.join("\n").as_bytes())?;
+        util::write_atomically(&gpg_id_path, gpg_ids.join("\n"))?;
 
         let gitattributes_path = path.join(".gitattributes");
         let mut file = fs::OpenOptions::new()
@@ -253,19 +415,21 @@ where
             .create(true)
             .open(&gitattributes_path)?;
 
-        file.write_all(b"*.gpg diff=gpg")?;
+        file.write_all(GITATTRIBUTES.as_bytes())?;
+
+        let mut files = vec![gpg_id_path.clone(), gitattributes_path];
+
+        if kind == EntryKind::Gpg {
+            files.extend(self::sign_gpg_id(&gpg_id_path)?);
+        }
+
+        files.extend(self::update_hidden(path, hidden)?);
 
         let mut config = repo.config()?;
 
-        // NOTE: These are the default GPG_OPTS as defined in `pass`. Since we
-        // don't call out to gpg, we can't easily support PASSWORD_STORE_GPG_OPTS.
-        config.set_str(
-            "diff.gpg.textconv",
-            "gpg2 --decrypt --quiet --yes --compress-algo=none --no-encrypt-to --batch --use-agent",
-        )?;
-        config.set_bool("diff.gpg.binary", true)?;
+        sync::setup_git_drivers(&mut config)?;
 
-        let (tree_id, sig, parents) = git_prep(&repo, &[gpg_id_path, gitattributes_path])?;
+        let (tree_id, sig, parents) = git_prep(&repo, &files)?;
         let parents: Vec<&Commit> = parents.iter().collect();
         let list = &gpg_keys.join(", ");
         let keys = if gpg_keys.len() > 1 {
@@ -277,7 +441,7 @@ where
         let ref_message = format!("commit (initial): {}", &commit_message);
 
         let commit = if config.get_bool("commit.gpgsign")? {
-            let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+            let mut ctx = crypto::gpg_context()?;
             let buf = repo.commit_create_buffer(
                 &sig,
                 &sig,
@@ -311,7 +475,13 @@ where
     Ok(())
 }
 
-fn create_substore<P, Q, S>(store: P, path: Q, gpg_keys: S) -> Result<()>
+fn create_substore<P, Q, S>(
+    store: P,
+    path: Q,
+    gpg_keys: S,
+    kind: EntryKind,
+    hidden: bool,
+) -> Result<()>
 where
     P: AsRef<Path>,
     Q: AsRef<Path>,
@@ -321,7 +491,7 @@ where
     let store = store.as_ref();
     let gpg_keys = gpg_keys.as_ref();
 
-    self::verify_keys(&gpg_keys)?;
+    self::verify_keys(&gpg_keys, kind)?;
 
     if fs::metadata(&path).is_err() {
         fs::create_dir_all(&path)?;
@@ -329,22 +499,26 @@ where
     }
 
     if let Ok(repo) = Repository::open(&store) {
-        let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+        let mut ctx = crypto::gpg_context()?;
 
-        let gpg_id_path = path.join(".gpg-id");
-        let mut file = fs::OpenOptions::new()
-            .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
-            .write(true)
-            .create(true)
-            .open(&gpg_id_path)?;
+        let gpg_id_path = path.join(kind.recipients_file());
+
+        util::write_atomically(&gpg_id_path, gpg_keys.join("\n"))?;
+
+        let mut files = vec![gpg_id_path.clone()];
+
+        if kind == EntryKind::Gpg {
+            files.extend(self::sign_gpg_id(&gpg_id_path)?);
+        }
 
-        file.write_all(gpg_keys.join("\n").as_bytes())?;
+        files.extend(self::update_hidden(path, hidden)?);
 
-        let (tree_id, sig, parents) = git_prep(&repo, [&gpg_id_path])?;
+        let (tree_id, sig, parents) = git_prep(&repo, &files)?;
         let parents: Vec<&Commit> = parents.iter().collect();
         let config = repo.config()?;
         let commit_message = format!(
-            "Set GPG ID to {} ({})",
+            "Set {} to {} ({})",
+            self::recipients_name(kind),
             gpg_keys.join(", "),
             &path.display().to_string()[*STORE_LEN..]
         );
@@ -0,0 +1,90 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use termion::color;
+use termion::style;
+
+use crate::consts::STORE_LEN;
+use crate::history;
+use crate::sync;
+use crate::util;
+use crate::Result;
+
+pub(crate) fn log(secret_name: Option<String>, patch: bool) -> Result<()> {
+    let path = match secret_name {
+        Some(secret_name) => Some(self::entry_path(&secret_name)?),
+        None => None,
+    };
+
+    for revision in history::log(path.as_ref())? {
+        write!(
+            io::stdout(),
+            "{yellow}{:.7}{reset} {}",
+            revision.id,
+            revision.summary,
+            yellow = color::Fg(color::Yellow),
+            reset = style::Reset
+        )?;
+
+        // Only mention the entry's path if it was renamed since
+        match &revision.path {
+            Some(entry) if Some(entry) != path.as_ref() => writeln!(
+                io::stdout(),
+                " ({})",
+                util::strip_extension(&entry.to_string_lossy())
+            )?,
+            _ => writeln!(io::stdout())?,
+        }
+
+        if patch && revision.path.is_some() {
+            let change = history::changes(&revision)?;
+
+            self::print_diff(
+                &change.old.unwrap_or_default(),
+                &change.new.unwrap_or_default(),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the path of the entry `secret_name` relative to the store. The entry
+/// doesn't have to exist anymore.
+pub(crate) fn entry_path(secret_name: &str) -> Result<PathBuf> {
+    let path = util::canonicalize_path(secret_name)?;
+
+    Ok(PathBuf::from(&path.to_string_lossy()[*STORE_LEN..]))
+}
+
+fn print_diff(old: &[u8], new: &[u8]) -> Result<()> {
+    let old = sync::lines(old);
+    let new = sync::lines(new);
+    let matching = sync::matching(&old, &new);
+    let mut stdout = io::stdout();
+    let (mut i, mut j) = (0, 0);
+
+    while i < old.len() || j < new.len() {
+        let (prefix, line, colour) = match matching.get(i) {
+            Some(Some(m)) if *m == j => {
+                i += 1;
+                j += 1;
+                (" ", new[j - 1], None)
+            }
+            Some(None) => {
+                i += 1;
+                ("-", old[i - 1], Some(color::Red.fg_str()))
+            }
+            _ => {
+                j += 1;
+                ("+", new[j - 1], Some(color::Green.fg_str()))
+            }
+        };
+
+        write!(stdout, "{}{}", colour.unwrap_or(""), prefix)?;
+        stdout.write_all(line.strip_suffix(b"\n").unwrap_or(line))?;
+        writeln!(stdout, "{}", style::Reset)?;
+    }
+
+    Ok(())
+}
@@ -1,10 +1,15 @@
+use std::io::{self, Write};
 use std::path::PathBuf;
 
-use crate::consts::STORE_STRING;
+use walkdir::WalkDir;
+
+use crate::cli::OutputFormat;
+use crate::consts::{STORE_LEN, STORE_STRING};
 use crate::tree;
+use crate::util;
 use crate::{PassrsError, Result};
 
-pub(crate) fn ls(path: Option<String>) -> Result<()> {
+pub(crate) fn ls(path: Option<String>, format: OutputFormat) -> Result<()> {
     let root = if let Some(path) = path {
         if path.contains(&*STORE_STRING) {
             path
@@ -15,17 +20,52 @@ pub(crate) fn ls(path: Option<String>) -> Result<()> {
         STORE_STRING.to_owned()
     };
 
-    if PathBuf::from(&root).exists() {
-        let tree = tree::tree(&root)?;
+    if !PathBuf::from(&root).exists() {
+        return Err(PassrsError::PathDoesntExist(root).into());
+    }
 
-        if tree.leaves.is_empty() {
-            return Ok(());
-        } else {
-            tree.display_tree()?;
-        }
+    if format == OutputFormat::Json {
+        let entries = self::entries(&root)?;
 
-        Ok(())
-    } else {
-        Err(PassrsError::PathDoesntExist(root).into())
+        writeln!(io::stdout(), "{}", serde_json::to_string(&entries)?)?;
+
+        return Ok(());
+    }
+
+    let tree = tree::tree(&root)?;
+
+    if !tree.leaves.is_empty() {
+        tree.display_tree()?;
     }
+
+    Ok(())
+}
+
+/// Returns the store-relative names of every entry under `root`, sorted.
+fn entries(root: &str) -> Result<Vec<String>> {
+    let mut entries = Vec::new();
+    let walker = WalkDir::new(root)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() == 0
+                || e.file_name()
+                    .to_str()
+                    .map(|name| !name.starts_with('.'))
+                    .unwrap_or(false)
+        });
+
+    for entry in walker {
+        let entry = entry?;
+        let path = entry
+            .path()
+            .to_str()
+            .ok_or("Entry did not contain a valid path")?;
+
+        if entry.file_type().is_file() && util::is_entry(path) {
+            entries.push(util::strip_extension(&path[*STORE_LEN..]).to_owned());
+        }
+    }
+
+    Ok(entries)
 }
@@ -1,7 +1,6 @@
 use std::fs;
-use std::os::unix::fs::OpenOptionsExt;
 
-use crate::consts::PASSWORD_STORE_UMASK;
+use crate::transaction::Transaction;
 use crate::util;
 use crate::{PassrsError, Result};
 
@@ -13,19 +12,33 @@ pub(crate) fn mv(source: String, dest: String, force: bool) -> Result<()> {
     };
     let mut dest_is_dir = false;
     let dest_path = if is_file {
-        if dest.ends_with('/') {
+        let dest = if dest.ends_with('/') {
             dest_is_dir = true;
             let name = source.rfind('/').unwrap_or(0);
             let oldname = &source[name..];
 
-            util::exact_path([&dest, oldname, ".gpg"].concat())?
+            [&dest, oldname].concat()
         } else {
-            util::exact_path([&dest, ".gpg"].concat())?
-        }
+            dest.clone()
+        };
+        let dest_dir = util::exact_path(&dest)?
+            .parent()
+            .ok_or("Destination had no parent directory")?
+            .to_path_buf();
+        // Entries take the kind of the substore they're moved into
+        let extension = util::closest_entry_kind(dest_dir).extension();
+
+        util::exact_path([&dest, extension].concat())?
     } else {
         util::exact_path(&dest)?
     };
 
+    if source_path == dest_path {
+        return Err(PassrsError::SourceIsDestination.into());
+    }
+
+    let mut tx = Transaction::new();
+
     if is_file {
         if !util::path_exists(&source_path)? {
             return Err(PassrsError::NotInStore(source).into());
@@ -34,13 +47,7 @@ pub(crate) fn mv(source: String, dest: String, force: bool) -> Result<()> {
         if !force && util::path_exists(&dest_path)? {
             let prompt = format!("An entry exists for {}. Overwrite it?", dest);
 
-            if util::prompt_yesno(prompt)? {
-                fs::OpenOptions::new()
-                    .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
-                    .write(true)
-                    .truncate(true)
-                    .open(&dest_path)?;
-            } else {
+            if !util::prompt_yesno(prompt)? {
                 return Err(PassrsError::UserAbort.into());
             }
         }
@@ -56,18 +63,9 @@ pub(crate) fn mv(source: String, dest: String, force: bool) -> Result<()> {
             format!("Rename {} to {}", source, dest)
         };
 
-        util::copy(&source_path, &dest_path)?;
-
-        if util::get_closest_gpg_id(&dest_path)? != util::get_closest_gpg_id(&source_path)? {
-            if dest_path.is_file() {
-                util::recrypt_file(&dest_path, None)?;
-            } else if dest_path.is_dir() {
-                util::recrypt_dir(&dest_path, None)?;
-            }
-        }
-
-        util::remove_dirs_to_file(&source_path)?;
-        util::commit(Some([&source_path, &dest_path]), commit_message)?;
+        util::stage_copy_entry(&mut tx, &source_path, &dest_path)?;
+        tx.remove(&source_path)?;
+        tx.commit(|| util::commit(Some([&source_path, &dest_path]), commit_message))?;
     } else {
         if !util::path_exists(&source_path)? {
             return Err(PassrsError::PathDoesntExist(source).into());
@@ -77,23 +75,20 @@ pub(crate) fn mv(source: String, dest: String, force: bool) -> Result<()> {
             let prompt = format!("An entry exists for {}. Overwrite it?", dest);
 
             if util::prompt_yesno(prompt)? {
-                fs::remove_dir_all(&dest_path)?;
+                tx.remove(&dest_path)?;
             } else {
                 return Err(PassrsError::UserAbort.into());
             }
         }
 
-        util::copy(&source_path, &dest_path)?;
-
-        if util::get_closest_gpg_id(&dest_path)? != util::get_closest_gpg_id(&source_path)? {
-            util::recrypt_dir(&dest_path, None)?;
-        }
-
-        fs::remove_dir_all(&source_path)?;
-        util::commit(
-            Some([&source_path, &dest_path]),
-            format!("Rename {} to {}", source, dest),
-        )?;
+        util::stage_copy_dir(&mut tx, &source_path, &dest_path, true)?;
+        tx.remove(&source_path)?;
+        tx.commit(|| {
+            util::commit(
+                Some([&source_path, &dest_path]),
+                format!("Rename {} to {}", source, dest),
+            )
+        })?;
     }
 
     Ok(())
@@ -1,8 +1,10 @@
 use std::io::{self, Write};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use serde_json::json;
 use termion::{color, style};
 
+use crate::cli::OutputFormat;
 use crate::consts::{PASSWORD_STORE_CLIP_TIME, STORE_LEN};
 use crate::otp::TOTPBuilder;
 use crate::ui::{self, UiResult};
@@ -11,39 +13,63 @@ use crate::{PassrsError, Result};
 
 use super::validate;
 
-pub(crate) fn code(secret_name: String, clip: bool) -> Result<()> {
-    let file = ui::display_matches_for_target(&secret_name)?;
-
-    match file {
-        UiResult::Success(file) => {
-            let lines = util::decrypt_file_into_strings(&file)?;
-            let file = file[*STORE_LEN..file.rfind(".gpg").unwrap()].to_owned();
-
-            for otp in lines {
-                if validate::validate(&otp).is_ok() {
-                    if clip {
-                        let code = self::generate_totp(&otp)?;
-
-                        clipboard::clip(&code, false)?;
-                        writeln!(io::stdout(),
-                                 "Copied token for {yellow}{}{reset} to the clipboard, which will clear in {} seconds.",
-                                 &file,
-                                 *PASSWORD_STORE_CLIP_TIME,
-                                 yellow = color::Fg(color::Yellow),
-                                 reset = style::Reset,
-                        )?;
-                    } else {
-                        self::display_code(&otp)?;
-                    }
-
-                    return Ok(());
-                }
+pub(crate) fn code(secret_name: String, clip: bool, format: OutputFormat) -> Result<()> {
+    // Whatever reads the JSON can't pick an entry from the list
+    let file = if format == OutputFormat::Json && !clip {
+        let mut matches = util::find_matches(&secret_name)?;
+
+        if matches.len() > 1 {
+            let matches = matches
+                .iter()
+                .map(|matched| util::strip_extension(&matched[*STORE_LEN..]).to_owned())
+                .collect();
+
+            return Err(PassrsError::MultipleMatches(secret_name, matches).into());
+        }
+
+        matches.remove(0)
+    } else {
+        match ui::display_matches_for_target(&secret_name)? {
+            UiResult::Success(file) => file,
+            _ => return Err(PassrsError::NoMatchesFound(secret_name).into()),
+        }
+    };
+    let lines = util::decrypt_file_into_strings(&file)?;
+    let file = util::strip_extension(&file[*STORE_LEN..]).to_owned();
+
+    for otp in lines {
+        if validate::validate(&otp).is_ok() {
+            if clip {
+                let code = self::generate_totp(&otp)?;
+
+                clipboard::clip(&code, false)?;
+                writeln!(io::stdout(),
+                         "Copied token for {yellow}{}{reset} to the clipboard, which will clear in {} seconds.",
+                         &file,
+                         *PASSWORD_STORE_CLIP_TIME,
+                         yellow = color::Fg(color::Yellow),
+                         reset = style::Reset,
+                )?;
+            } else if format == OutputFormat::Json {
+                let code = self::generate_totp(&otp)?;
+                let period = validate::get_period(&otp)?;
+                let out = json!({
+                    "path": file,
+                    "code": code,
+                    "period": period,
+                    "remaining": self::remaining(period)?,
+                });
+
+                writeln!(io::stdout(), "{}", out)?;
+            } else {
+                self::display_code(&otp)?;
             }
 
-            Err(PassrsError::NoUriFound(file).into())
+            return Ok(());
         }
-        _ => Err(PassrsError::NoMatchesFound(secret_name).into()),
     }
+
+    Err(PassrsError::NoUriFound(file).into())
 }
 
 pub(crate) fn generate_totp<S>(otp: S) -> Result<String>
@@ -67,6 +93,14 @@ where
     Ok(code)
 }
 
+/// Returns how many seconds are left until a code with the given `period`
+/// expires.
+fn remaining(period: u64) -> Result<u64> {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok(period - (time % period))
+}
+
 pub(crate) fn display_code<S>(otp: S) -> Result<()>
 where
     S: AsRef<str>,
@@ -74,8 +108,7 @@ where
     let otp = otp.as_ref();
     let code = self::generate_totp(&otp)?;
     let period = validate::get_period(&otp)?;
-    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-    let duration = period - (time % period);
+    let duration = self::remaining(period)?;
     let elapsed = (period - duration) as usize;
     let remaining = (duration % period) as usize;
 
@@ -1,7 +1,3 @@
-use std::fs;
-use std::os::unix::fs::OpenOptionsExt;
-
-use crate::consts::PASSWORD_STORE_UMASK;
 use crate::util::{self, EditMode};
 use crate::{Flags, PassrsError, Result};
 
@@ -23,13 +19,7 @@ pub(crate) fn insert(
     if !force && util::path_exists(&path)? {
         let prompt = format!("An entry exists for {}. Overwrite it?", secret_name);
 
-        if util::prompt_yesno(prompt)? {
-            fs::OpenOptions::new()
-                .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
-                .write(true)
-                .truncate(true)
-                .open(&path)?;
-        } else {
+        if !util::prompt_yesno(prompt)? {
             return Err(PassrsError::UserAbort.into());
         }
     }
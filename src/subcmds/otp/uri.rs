@@ -17,7 +17,7 @@ pub(crate) fn uri(secret_name: String, flags: Flags) -> Result<()> {
 
     if let UiResult::Success(file) = file {
         let lines = util::decrypt_file_into_strings(&file)?;
-        let file = file[*STORE_LEN..file.rfind(".gpg").unwrap()].to_owned();
+        let file = util::strip_extension(&file[*STORE_LEN..]).to_owned();
         let mut ret = Err(PassrsError::NoUriFound(file).into());
 
         for otp in lines {
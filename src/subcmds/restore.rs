@@ -0,0 +1,10 @@
+use crate::history;
+use crate::Result;
+
+use super::log;
+
+pub(crate) fn restore(secret_name: String, rev: String) -> Result<()> {
+    let path = log::entry_path(&secret_name)?;
+
+    history::restore(&rev, &path)
+}
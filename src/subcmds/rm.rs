@@ -1,8 +1,7 @@
 use std::fs;
-use std::os::unix::fs::OpenOptionsExt;
 use std::path::PathBuf;
 
-use crate::consts::PASSWORD_STORE_UMASK;
+use crate::transaction::Transaction;
 use crate::util;
 use crate::Flags;
 use crate::{PassrsError, Result};
@@ -15,37 +14,35 @@ pub(crate) fn rm(secret_name: String, flags: Flags) -> Result<()> {
     if !force && util::path_exists(&path)? {
         let prompt = format!("Are you sure you would like to delete {}?", secret_name);
 
-        if util::prompt_yesno(prompt)? {
-            if path.is_file() {
-                fs::OpenOptions::new()
-                    .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
-                    .write(true)
-                    .truncate(true)
-                    .open(&path)?;
-            }
-        } else {
+        if !util::prompt_yesno(prompt)? {
             return Err(PassrsError::UserAbort.into());
         }
     }
 
+    let mut tx = Transaction::new();
+
     match fs::metadata(&path) {
         Ok(meta) => {
             if meta.is_dir() {
                 if recursive {
-                    fs::remove_dir_all(&path)?;
-                    util::commit(
-                        None::<&[PathBuf]>,
-                        format!("Remove folder {} from store", secret_name),
-                    )?;
+                    tx.remove(&path)?;
+                    tx.commit(|| {
+                        util::commit(
+                            None::<&[PathBuf]>,
+                            format!("Remove folder {} from store", secret_name),
+                        )
+                    })?;
                 } else {
                     return Err(PassrsError::PathIsDir(path.display().to_string()).into());
                 }
             } else {
-                fs::remove_file(path)?;
-                util::commit(
-                    None::<&[PathBuf]>,
-                    format!("Remove entry {} from store", secret_name),
-                )?;
+                tx.remove(&path)?;
+                tx.commit(|| {
+                    util::commit(
+                        None::<&[PathBuf]>,
+                        format!("Remove entry {} from store", secret_name),
+                    )
+                })?;
             }
         }
         Err(_) => {
@@ -0,0 +1,58 @@
+use std::io::{self, Write};
+
+use termion::color;
+use termion::style;
+
+use crate::policy::Policy;
+use crate::rotate;
+use crate::util;
+use crate::{Flags, PassrsError, Result};
+
+use super::generate;
+
+pub(crate) fn rotate(
+    targets: Vec<String>,
+    length: Option<usize>,
+    policy: Policy,
+    keep_previous: bool,
+    flags: Flags,
+) -> Result<()> {
+    let entries = rotate::find_entries(&targets)?;
+
+    if !flags.force {
+        let prompt = format!("Generate new secrets for {} entries?", entries.len());
+
+        if !util::prompt_yesno(prompt)? {
+            return Err(PassrsError::UserAbort.into());
+        }
+    }
+
+    let rotations = rotate::rotate(&entries, keep_previous, |path| {
+        generate::new_secret(path, length, policy.clone(), flags.no_symbols)
+    })?;
+
+    writeln!(
+        io::stdout(),
+        "{bold}Rotated {} secrets. Update them wherever they're used:{reset}",
+        rotations.len(),
+        bold = style::Bold,
+        reset = style::Reset,
+    )?;
+
+    for rotation in rotations {
+        write!(
+            io::stdout(),
+            "  [ ] {yellow}{}{reset}",
+            rotation.name,
+            yellow = color::Fg(color::Yellow),
+            reset = style::Reset,
+        )?;
+
+        match rotation.url {
+            Some(url) => writeln!(io::stdout(), " ({})", url)?,
+            None => writeln!(io::stdout())?,
+        }
+    }
+
+    Ok(())
+}
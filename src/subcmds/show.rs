@@ -1,69 +1,69 @@
 use std::io::{self, Write};
+use std::str;
 
+use serde_json::json;
 use termion::color;
 use termion::style;
 
+use crate::cli::OutputFormat;
 use crate::clipboard;
-use crate::consts::{PASSWORD_STORE_CLIP_TIME, STORE_LEN};
+use crate::consts::{PASSWORD_STORE_CLIP_TIME, PASSWORD_STORE_DIR, STORE_LEN};
+use crate::entry::Entry;
+use crate::history;
 use crate::ui;
 use crate::ui::UiResult;
 use crate::util;
-use crate::Result;
+use crate::{PassrsError, Result};
 
-use super::edit;
+use super::{edit, log};
 
 #[allow(clippy::option_option)]
-pub(crate) fn show(secret_name: String, clip: Option<Option<usize>>) -> Result<()> {
+pub(crate) fn show(
+    secret_name: String,
+    clip: Option<Option<String>>,
+    field: Option<String>,
+    format: OutputFormat,
+    rev: Option<String>,
+) -> Result<()> {
+    if let Some(rev) = rev {
+        let path = log::entry_path(&secret_name)?;
+        let plain = history::plaintext_at(&rev, &path)?
+            .ok_or_else(|| format!("'{}' did not exist in {}", secret_name, rev))?;
+        let password = str::from_utf8(&plain)?
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect();
+        let file = PASSWORD_STORE_DIR.join(&path);
+
+        return self::show_entry(&file.to_string_lossy(), password, clip, field, format);
+    }
+
+    // Whatever reads the JSON can't pick an entry from the list
+    if format == OutputFormat::Json && clip.is_none() {
+        let matches = util::find_matches(&secret_name)?;
+
+        if matches.len() > 1 {
+            let matches = matches
+                .iter()
+                .map(|matched| util::strip_extension(&matched[*STORE_LEN..]).to_owned())
+                .collect();
+
+            return Err(PassrsError::MultipleMatches(secret_name, matches).into());
+        }
+
+        let password = util::decrypt_file_into_strings(&matches[0])?;
+
+        return self::show_entry(&matches[0], password, clip, field, format);
+    }
+
     match ui::display_matches_for_target(&secret_name)? {
         UiResult::Success(file) => {
             let password = util::decrypt_file_into_strings(&file)?;
 
-            match clip {
-                Some(clip) => {
-                    let file = &file[*STORE_LEN..file.rfind(".gpg").unwrap_or_else(|| file.len())];
-                    let contents = match clip {
-                        Some(line) => password
-                            .get(line.saturating_sub(1))
-                            .ok_or(format!("File at line {} was empty", line))?,
-                        None => password.first().ok_or("Vec was empty")?,
-                    };
-
-                    clipboard::clip(contents, false)?;
-                    writeln!(
-                        io::stdout(),
-                        "Copied {yellow}{}{reset} to the clipboard, which will clear in {} seconds.",
-                        file,
-                        *PASSWORD_STORE_CLIP_TIME,
-                        yellow = color::Fg(color::Yellow),
-                        reset = style::Reset,
-                    )?;
-                }
-                _ => {
-                    if termion::is_tty(&io::stdout()) {
-                        let file =
-                            &file[*STORE_LEN..file.rfind(".gpg").unwrap_or_else(|| file.len())];
-
-                        writeln!(
-                            io::stdout(),
-                            "Contents of {yellow}{}{reset}",
-                            file,
-                            yellow = color::Fg(color::Yellow),
-                            reset = style::Reset
-                        )?;
-
-                        for line in &password {
-                            writeln!(io::stdout(), "{}", line)?;
-                        }
-                    } else {
-                        // if stdout is not a tty, it is being piped, so only
-                        // print the first line
-                        write!(io::stdout(), "{}", &password[0])?;
-                    }
-                }
-            }
+            self::show_entry(&file, password, clip, field, format)?;
         }
         UiResult::CopiedToClipboard(file) => {
-            let file = &file[*STORE_LEN..file.rfind(".gpg").unwrap_or_else(|| file.len())];
+            let file = util::strip_extension(&file[*STORE_LEN..]);
 
             writeln!(
                 io::stdout(),
@@ -75,7 +75,7 @@ pub(crate) fn show(secret_name: String, clip: Option<Option<usize>>) -> Result<(
             )?;
         }
         UiResult::SpawnEditor(file) => {
-            let file = &file[..file.rfind(".gpg").unwrap_or_else(|| file.len())];
+            let file = util::strip_extension(&file);
 
             edit::edit(&file)?;
         }
@@ -83,3 +83,106 @@ pub(crate) fn show(secret_name: String, clip: Option<Option<usize>>) -> Result<(
 
     Ok(())
 }
+
+#[allow(clippy::option_option)]
+fn show_entry(
+    file: &str,
+    password: Vec<String>,
+    clip: Option<Option<String>>,
+    field: Option<String>,
+    format: OutputFormat,
+) -> Result<()> {
+    let entry = Entry::parse(password.join("\n"));
+
+    match (clip, field) {
+        (Some(clip), field) => {
+            let file = util::strip_extension(&file[*STORE_LEN..]);
+            let contents = match clip.or(field) {
+                // Entries aren't always ordered the same way, so
+                // anything that isn't a line number is a field name
+                Some(target) => match target.parse::<usize>() {
+                    Ok(line) => password
+                        .get(line.saturating_sub(1))
+                        .ok_or(format!("File at line {} was empty", line))?,
+                    Err(_) => entry
+                        .field(&target)
+                        .ok_or(PassrsError::NoFieldFound(target))?,
+                },
+                None => password.first().ok_or("Vec was empty")?,
+            };
+
+            clipboard::clip(contents, false)?;
+            writeln!(
+                io::stdout(),
+                "Copied {yellow}{}{reset} to the clipboard, which will clear in {} seconds.",
+                file,
+                *PASSWORD_STORE_CLIP_TIME,
+                yellow = color::Fg(color::Yellow),
+                reset = style::Reset,
+            )?;
+        }
+        (None, field) if format == OutputFormat::Json => {
+            let path = util::strip_extension(&file[*STORE_LEN..]);
+            let out = match field {
+                Some(field) => {
+                    let value = entry
+                        .field(&field)
+                        .ok_or_else(|| PassrsError::NoFieldFound(field.clone()))?;
+
+                    json!({ "path": path, "key": field, "value": value })
+                }
+                None => {
+                    let fields: Vec<_> = entry
+                        .fields
+                        .iter()
+                        .map(|(key, value)| json!({ "key": key, "value": value }))
+                        .collect();
+
+                    json!({
+                        "path": path,
+                        "password": entry.password,
+                        "fields": fields,
+                        "notes": entry.notes,
+                        "otp": entry.otp,
+                    })
+                }
+            };
+
+            writeln!(io::stdout(), "{}", out)?;
+        }
+        (None, Some(field)) => {
+            let value = entry
+                .field(&field)
+                .ok_or(PassrsError::NoFieldFound(field))?;
+
+            if termion::is_tty(&io::stdout()) {
+                writeln!(io::stdout(), "{}", value)?;
+            } else {
+                write!(io::stdout(), "{}", value)?;
+            }
+        }
+        (None, None) => {
+            if termion::is_tty(&io::stdout()) {
+                let file = util::strip_extension(&file[*STORE_LEN..]);
+
+                writeln!(
+                    io::stdout(),
+                    "Contents of {yellow}{}{reset}",
+                    file,
+                    yellow = color::Fg(color::Yellow),
+                    reset = style::Reset
+                )?;
+
+                for line in &password {
+                    writeln!(io::stdout(), "{}", line)?;
+                }
+            } else {
+                // if stdout is not a tty, it is being piped, so only
+                // print the first line
+                write!(io::stdout(), "{}", &password[0])?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::process::Command;
+
+use crate::consts::EDITOR;
+use crate::subcmds::edit;
+use crate::sync::{self, Merge, SyncOutcome, CONFLICT_START};
+use crate::util;
+use crate::{PassrsError, Result};
+
+pub(crate) fn sync(remote: String, push: bool) -> Result<()> {
+    let outcome = sync::sync(&remote, push, self::resolve)?;
+
+    match outcome {
+        SyncOutcome::UpToDate => writeln!(io::stdout(), "Already up to date with {}", remote)?,
+        SyncOutcome::FastForwarded => writeln!(io::stdout(), "Fast-forwarded to {}", remote)?,
+        SyncOutcome::Merged(entries) => writeln!(
+            io::stdout(),
+            "Merged changes from {} ({} entries changed on both sides)",
+            remote,
+            entries
+        )?,
+    }
+
+    Ok(())
+}
+
+/// Takes the merge of an entry if it was clean, and otherwise lets the user
+/// resolve the conflicts in their editor.
+fn resolve(entry: &str, merge: &Merge) -> Result<Vec<u8>> {
+    let name = util::strip_extension(entry);
+
+    if merge.conflicts == 0 {
+        writeln!(io::stdout(), "Merged {} automatically", name)?;
+
+        return Ok(merge.text.clone());
+    }
+
+    let prompt = format!(
+        "{} was changed on both sides ({} conflicts). Resolve them with {}?",
+        name, merge.conflicts, *EDITOR
+    );
+
+    if !util::prompt_yesno(prompt)? {
+        return Err(PassrsError::UserAbort.into());
+    }
+
+    let temp_path = edit::temp_file(name)?;
+    let temp_dir = Path::new(&temp_path)
+        .parent()
+        .ok_or("Path did not contain a parent")?
+        .to_path_buf();
+
+    util::create_dirs_to_file(&temp_path)?;
+
+    let edited = (|| -> Result<Vec<u8>> {
+        fs::OpenOptions::new()
+            .mode(0o600)
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?
+            .write_all(&merge.text)?;
+        Command::new(&*EDITOR).arg(&temp_path).status()?;
+
+        Ok(fs::read(&temp_path)?)
+    })();
+
+    let _ = fs::remove_file(&temp_path);
+    let _ = fs::remove_dir(&temp_dir);
+    let edited = edited?;
+
+    if edited.is_empty() {
+        return Err(PassrsError::UserAbort.into());
+    }
+
+    if edited
+        .split(|&b| b == b'\n')
+        .any(|line| line == CONFLICT_START.as_bytes())
+    {
+        return Err(format!("{} still contains conflict markers", name).into());
+    }
+
+    Ok(edited)
+}
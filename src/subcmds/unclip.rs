@@ -39,14 +39,17 @@ pub(crate) fn unclip(timeout: u32, force: bool) -> Result<()> {
         }
     }
 
-    let password_bytes = clipboard::paste()?;
-    let password = str::from_utf8(&password_bytes)?;
-    let password_hash =
-        HEXLOWER.encode(digest::digest(&digest::SHA256, password.as_bytes()).as_ref());
-
-    if !(password_hash == *PASSRS_UNCLIP_HASH || force) {
-        clipboard::clear()?;
-        return Err(PassrsError::HashMismatch.into());
+    // Backends that can't read the clipboard can't check it either
+    if clipboard::backend()?.can_paste() {
+        let password_bytes = clipboard::paste()?;
+        let password = str::from_utf8(&password_bytes)?;
+        let password_hash =
+            HEXLOWER.encode(digest::digest(&digest::SHA256, password.as_bytes()).as_ref());
+
+        if !(password_hash == *PASSRS_UNCLIP_HASH || force) {
+            clipboard::clear()?;
+            return Err(PassrsError::HashMismatch.into());
+        }
     }
 
     thread::sleep(time::Duration::from_secs(timeout as u64));
@@ -0,0 +1,456 @@
+//! Git synchronization
+//!
+//! # sync
+//!
+//! This module houses `passrs sync`, which fetches the current branch from a
+//! remote, fast-forwards or merges it, and pushes the result back, all through
+//! git2. Credentials come from ssh-agent or from git's credential helpers.
+//!
+//! Entries changed on both sides can't be merged by git, as it only sees their
+//! ciphertext. Instead, the ancestor and both sides of a conflicting entry are
+//! decrypted and merged line by line (see [`merge3`]), and the caller decides
+//! what the merged entry should contain.
+//!
+//! [`merge3`]: fn.merge3.html
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+
+use git2::build::CheckoutBuilder;
+use git2::{
+    Config, Cred, CredentialType, FetchOptions, IndexEntry, PushOptions, RemoteCallbacks,
+    Repository, StatusOptions,
+};
+
+use crate::consts::PASSWORD_STORE_DIR;
+use crate::crypto;
+use crate::lock;
+use crate::util::{self, EntryKind};
+use crate::Result;
+
+/// Marks the start of a conflicting chunk, followed by the local lines.
+pub const CONFLICT_START: &str = "<<<<<<< local";
+/// Separates the local lines of a conflicting chunk from the ancestor's.
+pub const CONFLICT_BASE: &str = "||||||| base";
+/// Separates the ancestor's lines of a conflicting chunk from the remote ones.
+pub const CONFLICT_SEPARATOR: &str = "=======";
+/// Marks the end of a conflicting chunk.
+pub const CONFLICT_END: &str = ">>>>>>> remote";
+
+/// What happened to the local branch while syncing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncOutcome {
+    /// The remote had nothing new.
+    UpToDate,
+    /// The local branch was behind, and now points to the remote branch.
+    FastForwarded,
+    /// Both sides had new commits, which were merged. Contains the number of
+    /// entries that had to be merged by decrypting them.
+    Merged(usize),
+}
+
+/// The result of a three-way merge of plaintext.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merge {
+    /// The merged lines, with conflicting chunks surrounded by
+    /// [`CONFLICT_START`] and [`CONFLICT_END`].
+    ///
+    /// [`CONFLICT_START`]: constant.CONFLICT_START.html
+    /// [`CONFLICT_END`]: constant.CONFLICT_END.html
+    pub text: Vec<u8>,
+    /// The number of conflicting chunks.
+    pub conflicts: usize,
+}
+
+/// Syncs the store's current branch with the branch of the same name on
+/// `remote_name`, then pushes it. `resolve` is called with the path of every
+/// entry that was changed on both sides, relative to the store, and the merge
+/// of its plaintext; it returns what the entry should contain.
+///
+/// Nothing is changed locally unless every entry could be merged.
+pub fn sync<F>(remote_name: &str, push: bool, mut resolve: F) -> Result<SyncOutcome>
+where
+    F: FnMut(&str, &Merge) -> Result<Vec<u8>>,
+{
+    let _lock = lock::acquire()?;
+    let repo = Repository::open(&*PASSWORD_STORE_DIR)?;
+    let mut config = repo.config()?;
+
+    // A clone has the store's `.gitattributes`, but not the drivers it names
+    self::setup_git_drivers(&mut config)?;
+    self::ensure_clean(&repo)?;
+
+    let head = repo.head()?;
+    let branch = head
+        .shorthand()
+        .ok_or("Current branch name was not valid UTF-8")?
+        .to_owned();
+    let local_ref = format!("refs/heads/{}", branch);
+    let tracking_ref = format!("refs/remotes/{}/{}", remote_name, branch);
+    let mut remote = repo.find_remote(remote_name)?;
+    let mut fetch_options = FetchOptions::new();
+
+    fetch_options.remote_callbacks(self::callbacks(&config));
+    remote.fetch(
+        &[&format!("+{}:{}", local_ref, tracking_ref)],
+        Some(&mut fetch_options),
+        None,
+    )?;
+
+    let outcome = match repo.find_reference(&tracking_ref) {
+        // The remote doesn't have this branch yet
+        Err(_) => SyncOutcome::UpToDate,
+        Ok(tracking) => {
+            let theirs = repo.reference_to_annotated_commit(&tracking)?;
+            let (analysis, _) = repo.merge_analysis(&[&theirs])?;
+            let remote_commit = repo.find_commit(theirs.id())?;
+
+            if analysis.is_up_to_date() {
+                SyncOutcome::UpToDate
+            } else if analysis.is_fast_forward() {
+                repo.checkout_tree(
+                    remote_commit.as_object(),
+                    Some(CheckoutBuilder::new().safe()),
+                )?;
+                repo.reference(
+                    &local_ref,
+                    remote_commit.id(),
+                    true,
+                    &format!("passrs sync: fast-forward to {}", tracking_ref),
+                )?;
+
+                SyncOutcome::FastForwarded
+            } else {
+                let local_commit = head.peel_to_commit()?;
+                let mut index = repo.merge_commits(&local_commit, &remote_commit, None)?;
+                let mut merged = 0;
+
+                if index.has_conflicts() {
+                    let conflicts = index.conflicts()?.collect::<Result<Vec<_>, _>>()?;
+
+                    for conflict in conflicts {
+                        let (ours, theirs) = match (conflict.our, conflict.their) {
+                            (Some(ours), Some(theirs)) => (ours, theirs),
+                            (ours, theirs) => {
+                                let entry = ours.or(theirs).ok_or("Conflict had no sides")?;
+
+                                return Err(self::unmergeable(&entry).into());
+                            }
+                        };
+                        let path = String::from_utf8(ours.path.clone())?;
+
+                        if !util::is_entry(&path) {
+                            return Err(self::unmergeable(&ours).into());
+                        }
+
+                        let backend = crypto::backend_for(EntryKind::from_path(&path))?;
+                        let decrypt = |entry: &IndexEntry| -> Result<Vec<u8>> {
+                            backend.decrypt(repo.find_blob(entry.id)?.content())
+                        };
+                        let base = match &conflict.ancestor {
+                            Some(ancestor) => decrypt(ancestor)?,
+                            None => Vec::new(),
+                        };
+                        let merge = self::merge3(&base, &decrypt(&ours)?, &decrypt(&theirs)?);
+                        let plaintext = resolve(&path, &merge)?;
+                        let ciphertext = util::encrypt_bytes_for(
+                            PASSWORD_STORE_DIR.join(&path),
+                            None,
+                            &plaintext,
+                        )?;
+                        let mut entry = ours;
+
+                        // Stage 0, i.e. no longer conflicting
+                        entry.flags &= !0x3000;
+                        entry.id = repo.blob(&ciphertext)?;
+                        entry.file_size = ciphertext.len() as u32;
+                        index.remove_path(Path::new(&path))?;
+                        index.add(&entry)?;
+                        merged += 1;
+                    }
+                }
+
+                let tree = repo.find_tree(index.write_tree_to(&repo)?)?;
+                let sig = repo.signature()?;
+
+                repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().safe()))?;
+                repo.commit(
+                    Some("HEAD"),
+                    &sig,
+                    &sig,
+                    &format!("Merge {} into {}", tracking_ref, branch),
+                    &tree,
+                    &[&local_commit, &remote_commit],
+                )?;
+
+                SyncOutcome::Merged(merged)
+            }
+        }
+    };
+
+    let local = repo.refname_to_id(&local_ref)?;
+
+    if push && repo.refname_to_id(&tracking_ref).ok() != Some(local) {
+        let rejected = RefCell::new(None);
+        let mut callbacks = self::callbacks(&config);
+
+        callbacks.push_update_reference(|refname, status| {
+            if let Some(status) = status {
+                *rejected.borrow_mut() = Some(format!("{}: {}", refname, status));
+            }
+
+            Ok(())
+        });
+
+        let mut push_options = PushOptions::new();
+
+        push_options.remote_callbacks(callbacks);
+        remote.push(
+            &[&format!("{}:{}", local_ref, local_ref)],
+            Some(&mut push_options),
+        )?;
+        drop(push_options);
+
+        if let Some(rejected) = rejected.into_inner() {
+            return Err(format!("Remote rejected the push of {}", rejected).into());
+        }
+
+        repo.reference(&tracking_ref, local, true, "passrs sync: push")?;
+    }
+
+    Ok(outcome)
+}
+
+/// Configures `passrs git-diff-driver` as the `textconv` of entries, so that
+/// `git log -p` shows decrypted diffs, and `passrs git-merge-driver` as their
+/// merge driver. Unlike pass, which uses `gpg2` directly, this honours
+/// `PASSWORD_STORE_GPG_OPTS` and works for age entries too.
+///
+/// Git only uses them for paths that `.gitattributes` assigns them to, which
+/// `passrs init` takes care of.
+pub fn setup_git_drivers(config: &mut Config) -> Result<()> {
+    for driver in &["gpg", "age"] {
+        config.set_str(
+            &format!("diff.{}.textconv", driver),
+            "passrs git-diff-driver",
+        )?;
+        config.set_bool(&format!("diff.{}.binary", driver), true)?;
+    }
+
+    config.set_str("merge.passrs.name", "passrs entry merge driver")?;
+    config.set_str("merge.passrs.driver", "passrs git-merge-driver %O %A %B %P")?;
+
+    Ok(())
+}
+
+/// Merges the encrypted entries in the files `base`, `ours` and `theirs` like
+/// [`sync`] does, then encrypts the result for the entry at `path` (relative
+/// to the store) and writes it to `ours`. An empty file is treated as an empty
+/// entry. Returns the number of conflicting chunks left in the result.
+///
+/// This is what `passrs git-merge-driver` does when git asks it to merge an
+/// entry.
+///
+/// [`sync`]: fn.sync.html
+pub fn merge_entry_files<P, Q, R>(base: P, ours: Q, theirs: R, path: &str) -> Result<usize>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    R: AsRef<Path>,
+{
+    let ours = ours.as_ref();
+    // The files git hands us don't keep the entry's extension
+    let backend = crypto::backend_for(EntryKind::from_path(path))?;
+    let decrypt = |file: &Path| -> Result<Vec<u8>> {
+        let ciphertext = fs::read(file)?;
+
+        if ciphertext.is_empty() {
+            Ok(Vec::new())
+        } else {
+            backend.decrypt(&ciphertext)
+        }
+    };
+    let merge = self::merge3(
+        &decrypt(base.as_ref())?,
+        &decrypt(ours)?,
+        &decrypt(theirs.as_ref())?,
+    );
+    let ciphertext = util::encrypt_bytes_for(PASSWORD_STORE_DIR.join(path), None, &merge.text)?;
+
+    util::write_atomically(ours, ciphertext)?;
+
+    Ok(merge.conflicts)
+}
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs` line by
+/// line. Chunks that were changed differently on both sides are kept in full,
+/// surrounded by conflict markers.
+pub fn merge3(base: &[u8], ours: &[u8], theirs: &[u8]) -> Merge {
+    let base = self::lines(base);
+    let ours = self::lines(ours);
+    let theirs = self::lines(theirs);
+    let to_ours = self::matching(&base, &ours);
+    let to_theirs = self::matching(&base, &theirs);
+    let mut merge = Merge {
+        text: Vec::new(),
+        conflicts: 0,
+    };
+    let (mut i, mut j, mut k) = (0, 0, 0);
+
+    loop {
+        // The next line of base that is unchanged on both sides
+        let stable = (i..base.len()).find_map(|b| match (to_ours[b], to_theirs[b]) {
+            (Some(o), Some(t)) if o >= j && t >= k => Some((b, o, t)),
+            _ => None,
+        });
+
+        match stable {
+            Some((b, o, t)) if (b, o, t) == (i, j, k) => {
+                merge.text.extend(base[i]);
+                i += 1;
+                j += 1;
+                k += 1;
+            }
+            Some((b, o, t)) => {
+                self::merge_chunk(&mut merge, &base[i..b], &ours[j..o], &theirs[k..t]);
+                i = b;
+                j = o;
+                k = t;
+            }
+            None => {
+                self::merge_chunk(&mut merge, &base[i..], &ours[j..], &theirs[k..]);
+                break;
+            }
+        }
+    }
+
+    merge
+}
+
+fn merge_chunk(merge: &mut Merge, base: &[&[u8]], ours: &[&[u8]], theirs: &[&[u8]]) {
+    if ours == base || ours == theirs {
+        merge.text.extend(theirs.concat());
+    } else if theirs == base {
+        merge.text.extend(ours.concat());
+    } else {
+        let mut push_side = |marker: &str, lines: &[&[u8]]| {
+            merge.text.extend(marker.as_bytes());
+            merge.text.push(b'\n');
+
+            for line in lines {
+                merge.text.extend(*line);
+            }
+
+            if !merge.text.ends_with(b"\n") {
+                merge.text.push(b'\n');
+            }
+        };
+
+        push_side(CONFLICT_START, ours);
+        push_side(CONFLICT_BASE, base);
+        push_side(CONFLICT_SEPARATOR, theirs);
+        merge.text.extend(CONFLICT_END.as_bytes());
+        merge.text.push(b'\n');
+        merge.conflicts += 1;
+    }
+}
+
+/// Splits `text` into lines, keeping their line endings.
+pub(crate) fn lines(text: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+
+    for (i, &b) in text.iter().enumerate() {
+        if b == b'\n' {
+            lines.push(&text[start..=i]);
+            start = i + 1;
+        }
+    }
+
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+
+    lines
+}
+
+/// Finds the longest common subsequence of `a` and `b`, returning for every
+/// line in `a` the index of the line in `b` it was matched with, if any.
+pub(crate) fn matching(a: &[&[u8]], b: &[&[u8]]) -> Vec<Option<usize>> {
+    // lcs[i][j] is the length of the LCS of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut matches = vec![None; a.len()];
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            matches[i] = Some(j);
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    matches
+}
+
+fn unmergeable(entry: &IndexEntry) -> String {
+    format!(
+        "Can't merge '{}' automatically; resolve the conflict with `passrs git`",
+        String::from_utf8_lossy(&entry.path)
+    )
+}
+
+/// Refuses to sync with uncommitted changes, which a merge could clobber.
+fn ensure_clean(repo: &Repository) -> Result<()> {
+    let mut status_opts = StatusOptions::new();
+
+    status_opts.include_untracked(false);
+
+    if repo.statuses(Some(&mut status_opts))?.is_empty() {
+        Ok(())
+    } else {
+        Err("The store has uncommitted changes; commit them before syncing".into())
+    }
+}
+
+/// Authenticates with ssh-agent for SSH remotes, and with git's credential
+/// helpers otherwise.
+fn callbacks(config: &Config) -> RemoteCallbacks<'_> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut attempts = 0;
+
+    callbacks.credentials(move |url, username, allowed| {
+        // libgit2 keeps asking as long as we provide credentials
+        attempts += 1;
+
+        if attempts > 3 {
+            return Err(git2::Error::from_str("Authentication failed"));
+        }
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            Cred::credential_helper(config, url, username)
+        } else {
+            Cred::default()
+        }
+    });
+
+    callbacks
+}
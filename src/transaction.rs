@@ -0,0 +1,408 @@
+//! All-or-nothing store operations
+//!
+//! # transaction
+//!
+//! This module houses [`Transaction`], which makes operations that touch many
+//! files in the store (re-encrypting a substore, or moving, copying or removing
+//! a folder) all-or-nothing. New contents are staged in temporary files next to
+//! their destination and only swapped in once every one of them was written.
+//! If anything fails after that -- including committing to the store's git
+//! repository -- every file, the git index and `HEAD` are restored.
+//!
+//! [`Transaction`]: struct.Transaction.html
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use git2::{Oid, Repository};
+use walkdir::WalkDir;
+
+use crate::consts::{PASSWORD_STORE_DIR, PASSWORD_STORE_UMASK};
+use crate::util;
+use crate::{PassrsError, Result};
+
+/// Keeps the names of staged files unique within this process.
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+enum Op {
+    /// Replace (or create) `target` with the file staged at `staged`.
+    Write { target: PathBuf, staged: PathBuf },
+    /// Remove the file at `target`.
+    Remove { target: PathBuf },
+}
+
+/// What has to be undone for an operation that was applied.
+struct Applied {
+    target: PathBuf,
+    /// Where the file previously at `target` was moved to, if there was one.
+    backup: Option<PathBuf>,
+    /// Whether `target` now holds a staged file.
+    written: bool,
+}
+
+/// A file the caller modifies directly, with its contents and permissions
+/// from before that.
+struct Snapshot {
+    path: PathBuf,
+    previous: Option<(Vec<u8>, u32)>,
+}
+
+/// The state of the store's git repository from before a transaction.
+struct GitState {
+    head: Option<(String, Oid)>,
+    index: Oid,
+}
+
+/// A set of changes to the store that is applied all at once, or not at all.
+/// Dropping a `Transaction` without committing it discards every staged change
+/// and restores every tracked file.
+#[derive(Default)]
+pub struct Transaction {
+    ops: Vec<Op>,
+    snapshots: Vec<Snapshot>,
+    created_dirs: Vec<PathBuf>,
+    done: bool,
+}
+
+impl Transaction {
+    pub fn new() -> Transaction {
+        Transaction::default()
+    }
+
+    /// Stages `contents` to be written to `path`. The file's permissions
+    /// respect [`PASSWORD_STORE_UMASK`].
+    ///
+    /// [`PASSWORD_STORE_UMASK`]: ../consts/static.PASSWORD_STORE_UMASK.html
+    pub fn write<P, V>(&mut self, path: P, contents: V) -> Result<()>
+    where
+        P: AsRef<Path>,
+        V: AsRef<[u8]>,
+    {
+        self.stage(
+            path.as_ref(),
+            contents.as_ref(),
+            0o666 - (0o666 & *PASSWORD_STORE_UMASK),
+        )
+    }
+
+    /// Stages a copy of the file at `source` to `dest`, keeping its
+    /// permissions.
+    pub fn copy<P, Q>(&mut self, source: P, dest: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let source = source.as_ref();
+        let mode = fs::metadata(source)?.permissions().mode() & 0o7777;
+
+        self.stage(dest.as_ref(), &fs::read(source)?, mode)
+    }
+
+    /// Stages the removal of `path`. Folders are removed along with everything
+    /// in them.
+    pub fn remove<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        if fs::symlink_metadata(path)?.is_dir() {
+            for entry in WalkDir::new(path).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+                let entry = entry?;
+
+                if !entry.file_type().is_dir() {
+                    self.ops.push(Op::Remove {
+                        target: entry.into_path(),
+                    });
+                }
+            }
+        } else {
+            self.ops.push(Op::Remove {
+                target: path.to_path_buf(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Remembers the current contents of `path`, which the caller is about to
+    /// modify directly, so that they can be restored if the transaction fails.
+    /// This is meant for files like `.gpg-id`, which have to be in place
+    /// before the rest of the transaction can be staged.
+    pub fn track<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let previous = match fs::read(path) {
+            Ok(contents) => Some((contents, fs::metadata(path)?.permissions().mode() & 0o7777)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        self.snapshots.push(Snapshot {
+            path: path.to_path_buf(),
+            previous,
+        });
+
+        Ok(())
+    }
+
+    /// Swaps in every staged change, then runs `then` (usually `util::commit`).
+    /// If either fails, everything is rolled back, including the git index
+    /// and `HEAD`, and the error is returned.
+    pub fn commit<F>(mut self, then: F) -> Result<()>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let git = self::git_state()?;
+        let mut applied = Vec::new();
+        let ret = self.apply(&mut applied).and_then(|_| then());
+
+        match ret {
+            Ok(()) => {
+                self.finish(applied);
+                self.done = true;
+
+                Ok(())
+            }
+            Err(e) => {
+                self::rollback(applied);
+
+                if let Some(git) = git {
+                    // The original error is more interesting
+                    let _ = self::restore_git(&git);
+                }
+
+                // Dropping restores the tracked files
+                Err(e)
+            }
+        }
+    }
+
+    fn stage(&mut self, target: &Path, contents: &[u8], mode: u32) -> Result<()> {
+        let dir = match target.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let name = target
+            .file_name()
+            .ok_or_else(|| format!("'{}' is not a file", target.display()))?;
+        let mut missing = Vec::new();
+
+        for ancestor in dir.ancestors().filter(|a| !a.as_os_str().is_empty()) {
+            if ancestor.exists() {
+                break;
+            }
+
+            missing.push(ancestor.to_path_buf());
+        }
+
+        util::create_dirs_to_file(target)?;
+        self.created_dirs.extend(missing.into_iter().rev());
+
+        // Hidden, so that it doesn't show up in the store if we crash
+        let staged = dir.join(format!(
+            ".{}.staged-{}-{}",
+            name.to_string_lossy(),
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let mut file = fs::OpenOptions::new()
+            .mode(mode)
+            .write(true)
+            .create_new(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&staged)?;
+
+        self.ops.push(Op::Write {
+            target: target.to_path_buf(),
+            staged: staged.clone(),
+        });
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        Ok(())
+    }
+
+    fn apply(&self, applied: &mut Vec<Applied>) -> Result<()> {
+        for op in &self.ops {
+            match op {
+                Op::Write { target, staged } => {
+                    let backup = self::move_aside(target, applied)?;
+
+                    fs::rename(staged, target)?;
+                    applied.push(Applied {
+                        target: target.clone(),
+                        backup,
+                        written: true,
+                    });
+                }
+                Op::Remove { target } => {
+                    let backup = self::move_aside(target, applied)?;
+
+                    applied.push(Applied {
+                        target: target.clone(),
+                        backup,
+                        written: false,
+                    });
+                }
+            }
+        }
+
+        let mut dirs: Vec<&Path> = applied.iter().filter_map(|a| a.target.parent()).collect();
+
+        dirs.sort();
+        dirs.dedup();
+
+        for dir in dirs.into_iter().filter(|dir| dir.exists()) {
+            fs::File::open(dir)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&self, applied: Vec<Applied>) {
+        // Everything was committed; failing to clean up doesn't change that
+        for a in applied {
+            if let Some(backup) = a.backup {
+                let _ = fs::remove_file(backup);
+            }
+
+            if !a.written {
+                self::remove_empty_dirs(&a.target);
+            }
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        for op in &self.ops {
+            if let Op::Write { staged, .. } = op {
+                let _ = fs::remove_file(staged);
+            }
+        }
+
+        for snapshot in self.snapshots.iter().rev() {
+            let _ = match &snapshot.previous {
+                Some((contents, mode)) => {
+                    util::write_atomically_with_mode(&snapshot.path, contents, *mode)
+                }
+                None if snapshot.path.exists() => {
+                    fs::remove_file(&snapshot.path).map_err(Into::into)
+                }
+                None => Ok(()),
+            };
+        }
+
+        for dir in self.created_dirs.iter().rev() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
+/// Moves the file at `target` out of the way, if there is one, returning where
+/// it was moved to.
+fn move_aside(target: &Path, applied: &[Applied]) -> Result<Option<PathBuf>> {
+    let meta = match fs::symlink_metadata(target) {
+        Ok(meta) => meta,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    if meta.is_dir() {
+        return Err(PassrsError::PathIsDir(target.display().to_string()).into());
+    }
+
+    let name = target
+        .file_name()
+        .ok_or_else(|| format!("'{}' is not a file", target.display()))?;
+    let backup = target.with_file_name(format!(
+        ".{}.backup-{}-{}",
+        name.to_string_lossy(),
+        std::process::id(),
+        applied.len()
+    ));
+
+    fs::rename(target, &backup)?;
+
+    Ok(Some(backup))
+}
+
+fn rollback(applied: Vec<Applied>) {
+    // Nothing better to do than trying to restore as much as possible
+    for a in applied.into_iter().rev() {
+        if a.written {
+            let _ = fs::remove_file(&a.target);
+        }
+
+        if let Some(backup) = a.backup {
+            let _ = fs::rename(backup, &a.target);
+        }
+    }
+}
+
+/// Removes the now-empty folders a removed file was in, up to the store.
+fn remove_empty_dirs(path: &Path) {
+    let mut path = path;
+
+    while let Some(dir) = path.parent() {
+        if dir == *PASSWORD_STORE_DIR || dir.as_os_str().is_empty() || fs::remove_dir(dir).is_err()
+        {
+            break;
+        }
+
+        path = dir;
+    }
+}
+
+fn git_state() -> Result<Option<GitState>> {
+    let repo = match Repository::open(&*PASSWORD_STORE_DIR) {
+        Ok(repo) => repo,
+        Err(_) => return Ok(None),
+    };
+    let head = repo.head().ok().and_then(|head| {
+        let name = head.name()?.to_owned();
+
+        Some((name, head.target()?))
+    });
+    let index = repo.index()?.write_tree()?;
+
+    Ok(Some(GitState { head, index }))
+}
+
+fn restore_git(git: &GitState) -> Result<()> {
+    let repo = Repository::open(&*PASSWORD_STORE_DIR)?;
+
+    match &git.head {
+        Some((name, oid)) => {
+            let mut head = repo.find_reference(name)?;
+
+            if head.target() != Some(*oid) {
+                head.set_target(*oid, "passrs: roll back failed transaction")?;
+            }
+        }
+        None => {
+            // The first commit of the store was made
+            if let Ok(mut head) = repo.head() {
+                head.delete()?;
+            }
+        }
+    }
+
+    let mut index = repo.index()?;
+
+    index.read_tree(&repo.find_tree(git.index)?)?;
+    index.write()?;
+
+    Ok(())
+}
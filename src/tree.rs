@@ -32,6 +32,7 @@ use std::path::{Path, PathBuf};
 use termion::{color, style};
 
 use crate::consts::PASSWORD_STORE_DIR;
+use crate::util;
 use crate::Result;
 
 const EDGE: &str = "├── ";
@@ -127,9 +128,8 @@ impl Tree {
                         reset = style::Reset
                     )?;
                 } else {
-                    // if the leaf ends with .gpg, don't show that
-                    let leaf_name =
-                        &leaf_name[..leaf_name.rfind(".gpg").unwrap_or_else(|| leaf_name.len())];
+                    // if the leaf ends with .gpg or .age, don't show that
+                    let leaf_name = util::strip_extension(leaf_name);
 
                     writeln!(io::stdout(), "{}{}", CORNER, leaf_name)?;
                 }
@@ -144,9 +144,8 @@ impl Tree {
                     reset = style::Reset
                 )?;
             } else {
-                // if the leaf ends with .gpg, don't show that
-                let leaf_name =
-                    &leaf_name[..leaf_name.rfind(".gpg").unwrap_or_else(|| leaf_name.len())];
+                // if the leaf ends with .gpg or .age, don't show that
+                let leaf_name = util::strip_extension(leaf_name);
 
                 writeln!(io::stdout(), "{}{}", EDGE, leaf_name)?;
             }
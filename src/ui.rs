@@ -57,7 +57,7 @@ impl Ui {
             // extension, so chop those parts off
             .map(|entry| {
                 // Don't show PASSWORD_STORE_DIR or .gpg in UI
-                entry[*STORE_LEN..entry.rfind(".gpg").unwrap_or_else(|| entry.len())].to_owned()
+                util::strip_extension(&entry[*STORE_LEN..]).to_owned()
             })
             .collect();
 
@@ -5,6 +5,7 @@
 //! This module houses most of the meat and potatoes of `passrs`. Any generic,
 //! helpful function used in more than one place finds its home here.
 
+use std::collections::HashSet;
 use std::fs;
 use std::io::{self, BufRead, BufReader, Read, Write};
 use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
@@ -12,15 +13,21 @@ use std::path::{Path, PathBuf};
 use std::str;
 
 use git2::{Commit, Repository};
-use gpgme::{Context, Data, Protocol};
+use gpgme::Context;
 use ring::rand;
 use termion::input::TermRead;
 use walkdir::WalkDir;
 
+use crate::agent;
 use crate::consts::{
-    GPG_ID_FILE, HOME, PASSWORD_STORE_DIR, PASSWORD_STORE_KEY, PASSWORD_STORE_UMASK, STORE_LEN,
-    STORE_STRING,
+    AGE_RECIPIENTS_FILE, GPG_ID_FILE, GPG_OPTS_FILE, HIDDEN_RECIPIENTS_FILE, HOME,
+    PASSWORD_STORE_DIR, PASSWORD_STORE_GPG_OPTS, PASSWORD_STORE_KEY, PASSWORD_STORE_SIGNING_KEY,
+    PASSWORD_STORE_UMASK, STORE_LEN, STORE_STRING,
 };
+use crate::crypto::{self, GpgOptions};
+use crate::entry::Entry;
+use crate::lock;
+use crate::transaction::Transaction;
 use crate::{PassrsError, Result};
 
 /// Helper function to return the path to the specified entry. Paths may be an
@@ -38,19 +45,24 @@ where
 
     self::check_sneaky_paths(&path)?;
 
-    let enc_path = [&path, ".gpg"].concat();
-    let path = if fs::metadata(&enc_path).is_ok() {
-        enc_path
+    let gpg_path = [&path, EntryKind::Gpg.extension()].concat();
+    let age_path = [&path, EntryKind::Age.extension()].concat();
+    let path = if fs::metadata(&gpg_path).is_ok() {
+        gpg_path
+    } else if fs::metadata(&age_path).is_ok() {
+        age_path
     } else if let Ok(meta) = fs::metadata(&path) {
         if meta.is_dir() || path.ends_with('/') {
             path
         } else {
-            path + ".gpg"
+            let extension = self::closest_entry_kind(&path).extension();
+            path + extension
         }
     } else if path.ends_with('/') {
         path
     } else {
-        path + ".gpg"
+        let extension = self::closest_entry_kind(&path).extension();
+        path + extension
     };
 
     Ok(PathBuf::from(path))
@@ -75,10 +87,10 @@ where
 }
 
 /// Pretty self explanatory. If neither the specified store directory or
-/// `.gpg-id` file exist, the store doesn't exist.
+/// `.gpg-id` (or `.age-recipients`) file exist, the store doesn't exist.
 pub fn verify_store_exists() -> Result<()> {
     let store_meta = fs::metadata(&*PASSWORD_STORE_DIR);
-    let id_meta = fs::metadata(&*GPG_ID_FILE);
+    let id_meta = fs::metadata(&*GPG_ID_FILE).or_else(|_| fs::metadata(&*AGE_RECIPIENTS_FILE));
 
     if store_meta.is_err() || id_meta.is_err() {
         return Err(PassrsError::StoreDoesntExist.into());
@@ -149,13 +161,13 @@ where
             .to_str()
             .ok_or("Path couldn't be converted to str")?;
 
-        if path.ends_with(".gpg")
-            && (filename == &target || target == &filename[..filename.len() - 4])
+        if self::is_entry(path)
+            && (filename == &target || target == self::strip_extension(filename))
         {
             return Ok(vec![path.to_owned()]);
         }
 
-        if path.ends_with(".gpg")
+        if self::is_entry(path)
             && is_file
             && (path[*STORE_LEN..].contains(target)
                 || path[*STORE_LEN..].to_ascii_lowercase().contains(target))
@@ -179,40 +191,52 @@ pub fn decrypt_file_into_strings<P>(path: P) -> Result<Vec<String>>
 where
     P: AsRef<Path>,
 {
-    let path = path.as_ref();
-    let mut bytes = Vec::new();
-    let mut file = fs::File::open(path)?;
-
-    file.read_to_end(&mut bytes)?;
-
-    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
-    let mut cipher = Data::from_bytes(bytes)?;
-    let mut plain = Vec::new();
-
-    ctx.decrypt(&mut cipher, &mut plain)?;
-
+    let plain = self::decrypt_file_into_bytes(path)?;
     let plain = str::from_utf8(&plain)?;
     let out = plain.lines().map(ToOwned::to_owned).collect();
 
     Ok(out)
 }
 
-/// Decrypts the specified file into a `Vec<u8>`.
+/// Decrypt the specified file into an [`Entry`], split into its password,
+/// `key: value` fields, notes and OTP URI. This will return an `Err` if the
+/// plaintext is not encoded in valid UTF8.
+///
+/// [`Entry`]: ../entry/struct.Entry.html
+pub fn decrypt_file_into_entry<P>(path: P) -> Result<Entry>
+where
+    P: AsRef<Path>,
+{
+    let plain = self::decrypt_file_into_bytes(path)?;
+    let plain = str::from_utf8(&plain)?;
+
+    Ok(Entry::parse(plain))
+}
+
+/// Decrypts the specified file into a `Vec<u8>`. If a `passrs agent` is
+/// running, it is asked for the plaintext first; if that fails for any reason,
+/// the file is decrypted by this process instead.
 pub fn decrypt_file_into_bytes<P>(path: P) -> Result<Vec<u8>>
 where
     P: AsRef<Path>,
 {
     let path = path.as_ref();
+
+    if let Ok(Some(plain)) = agent::request(path) {
+        return Ok(plain);
+    }
+
+    self::decrypt_file_locally(path)
+}
+
+/// Decrypts the specified file into a `Vec<u8>` without consulting the agent.
+pub(crate) fn decrypt_file_locally(path: &Path) -> Result<Vec<u8>> {
     let mut bytes = Vec::new();
     let mut file = fs::File::open(path)?;
 
     file.read_to_end(&mut bytes)?;
 
-    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
-    let mut cipher = Data::from_bytes(bytes)?;
-    let mut plain = Vec::new();
-
-    ctx.decrypt(&mut cipher, &mut plain)?;
+    let plain = crypto::backend_for(EntryKind::from_path(path))?.decrypt(&bytes)?;
 
     Ok(plain)
 }
@@ -227,10 +251,71 @@ pub enum EditMode {
     Append,
 }
 
-/// Encrypts a slice of bytes with [`PASSWORD_STORE_KEY`] or the key(s) listed
-/// in `.gpg-id`. Callers must verify that [`PASSWORD_STORE_DIR`] exists and is
-/// initialized using `verify_store_exists`. If `editmode` is `Append`, append
-/// the bytes; otherwise, overwrite the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What an entry is encrypted with, as determined by its extension.
+pub enum EntryKind {
+    /// Encrypted with OpenPGP to the key(s) listed in `.gpg-id`.
+    Gpg,
+    /// Encrypted with age to the recipient(s) listed in `.age-recipients`.
+    Age,
+}
+
+impl EntryKind {
+    /// Entries ending in `.age` are age entries; everything else is assumed to
+    /// be encrypted with OpenPGP.
+    pub fn from_path<P>(path: P) -> EntryKind
+    where
+        P: AsRef<Path>,
+    {
+        match path.as_ref().extension() {
+            Some(ext) if ext == "age" => EntryKind::Age,
+            _ => EntryKind::Gpg,
+        }
+    }
+
+    /// The extension (including the leading `.`) of entries of this kind.
+    pub fn extension(self) -> &'static str {
+        match self {
+            EntryKind::Gpg => ".gpg",
+            EntryKind::Age => ".age",
+        }
+    }
+
+    /// The name of the file listing the recipients of entries of this kind.
+    pub fn recipients_file(self) -> &'static str {
+        match self {
+            EntryKind::Gpg => ".gpg-id",
+            EntryKind::Age => ".age-recipients",
+        }
+    }
+}
+
+/// Returns `true` if `path` looks like an entry, i.e. it ends in `.gpg` or
+/// `.age`.
+pub fn is_entry<S>(path: S) -> bool
+where
+    S: AsRef<str>,
+{
+    let path = path.as_ref();
+
+    path.ends_with(EntryKind::Gpg.extension()) || path.ends_with(EntryKind::Age.extension())
+}
+
+/// Strips the `.gpg` or `.age` extension from `path`, if it has one.
+pub fn strip_extension(path: &str) -> &str {
+    if self::is_entry(path) {
+        &path[..path.len() - 4]
+    } else {
+        path
+    }
+}
+
+/// Encrypts a slice of bytes with [`PASSWORD_STORE_KEY`] and the key(s) listed
+/// in the closest `.gpg-id`, or, for `.age` entries, the recipient(s) listed in
+/// the closest `.age-recipients`. Callers must verify that
+/// [`PASSWORD_STORE_DIR`] exists and is initialized using
+/// `verify_store_exists`. If `editmode` is `Append`, append the bytes;
+/// otherwise, overwrite the file.
 ///
 /// [`PASSWORD_STORE_KEY`]: ../consts/static.PASSWORD_STORE_KEY.html
 /// [`PASSWORD_STORE_DIR`]: ../consts/static.PASSWORD_STORE_DIR.html
@@ -244,59 +329,133 @@ where
 
     self::create_dirs_to_file(&path)?;
 
-    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
-    let id_file = match self::find_gpg_id(
-        path.parent()
-            .ok_or(format!("Path '{}' didn't have a parent", path.display()))?,
-    ) {
-        Ok(gpgid) => fs::OpenOptions::new().read(true).open(&gpgid)?,
-        Err(_) => fs::OpenOptions::new().read(true).open(&*GPG_ID_FILE)?,
-    };
-    let reader = BufReader::new(&id_file);
-    let mut keys = Vec::new();
+    let kind = EntryKind::from_path(path);
+    let backend = crypto::backend_for(kind)?;
+    let keys = self::get_recipients(path, kind)?;
 
-    for line in reader.lines() {
-        keys.push(line?);
-    }
-
-    keys.extend(PASSWORD_STORE_KEY.clone());
-
-    let encryption_keys: Vec<gpgme::Key> = keys
-        .iter()
-        .map(|k| ctx.get_secret_key(k))
-        .filter_map(|k| k.ok())
-        .collect();
-
-    if encryption_keys.is_empty() {
+    if keys.iter().all(|k| k.trim().is_empty()) {
         return Err(PassrsError::NoSigningKeyFound.into());
     } else {
         let mut to_be_encrypted: Vec<u8> = Vec::new();
 
         if editmode == EditMode::Append {
-            let mut to_be_decrypted = Data::load(path.display().to_string())?;
-
-            ctx.decrypt(&mut to_be_decrypted, &mut to_be_encrypted)?;
+            to_be_encrypted = self::decrypt_file_into_bytes(path)?;
             to_be_encrypted.push(b'\n');
         }
 
         to_be_encrypted.extend(to_encrypt);
 
-        let mut encrypted_contents: Vec<u8> = Vec::new();
+        let encrypted_contents = self::encrypt_for(path, backend, &keys, &to_be_encrypted)?;
+
+        self::write_atomically(path, &encrypted_contents)?;
+    }
+
+    Ok(())
+}
+
+/// Replaces the contents of `path` with `contents`, such that a crash at any
+/// point leaves either the old or the new contents behind: the contents are
+/// written to a temporary file in the same directory, which is synced to disk
+/// and renamed over `path`, after which the directory itself is synced. The
+/// file's permissions respect [`PASSWORD_STORE_UMASK`].
+///
+/// This is why `insert`, `generate` and `otp insert` don't remove an entry
+/// they overwrite: it stays in place until the new one is ready, so a failed
+/// encryption leaves it untouched. `cp` and `mv` get the same guarantee from a
+/// [`Transaction`].
+///
+/// [`PASSWORD_STORE_UMASK`]: ../consts/static.PASSWORD_STORE_UMASK.html
+/// [`Transaction`]: ../transaction/struct.Transaction.html
+pub fn write_atomically<P, V>(path: P, contents: V) -> Result<()>
+where
+    P: AsRef<Path>,
+    V: AsRef<[u8]>,
+{
+    self::write_atomically_with_mode(
+        path.as_ref(),
+        contents.as_ref(),
+        0o666 - (0o666 & *PASSWORD_STORE_UMASK),
+    )
+}
+
+pub(crate) fn write_atomically_with_mode(path: &Path, contents: &[u8], mode: u32) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("'{}' is not a file", path.display()))?;
+    // Hidden, so that it doesn't show up in the store if we crash
+    let tmp = dir.join(format!(
+        ".{}.tmp-{}",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    let write = || -> Result<()> {
         let mut file = fs::OpenOptions::new()
-            .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
+            .mode(mode)
             .write(true)
             .create(true)
-            .open(&path)?;
+            .truncate(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&tmp)?;
 
-        ctx.encrypt(
-            &encryption_keys,
-            &mut to_be_encrypted,
-            &mut encrypted_contents,
-        )?;
-        file.write_all(&encrypted_contents)?;
+        // The mode passed to open is only used for new files
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        fs::File::open(dir)?.sync_all()?;
+
+        Ok(())
+    };
+
+    let ret = write();
+
+    if ret.is_err() {
+        let _ = fs::remove_file(&tmp);
     }
 
-    Ok(())
+    ret
+}
+
+/// Encrypts `plaintext` for the entry at `path`, hiding its recipients and
+/// using the gpg options of the (sub)store it belongs to if it asks for it.
+fn encrypt_for(
+    path: &Path,
+    backend: &dyn crypto::CryptoBackend,
+    keys: &[String],
+    plaintext: &[u8],
+) -> Result<Vec<u8>> {
+    let hidden = self::hides_recipients(path);
+
+    match self::store_gpg_options(path)? {
+        Some(options) => backend.encrypt_with_options(keys, plaintext, &options, hidden),
+        None if hidden => backend.encrypt_hidden(keys, plaintext),
+        None => backend.encrypt(keys, plaintext),
+    }
+}
+
+/// Resolves every key in `keys` to a public key in the keyring, so that we can
+/// encrypt to recipients whose secret key we don't hold (e.g. teammates sharing
+/// a substore). Returns an error naming the first key that couldn't be resolved
+/// or that can't be used for encryption. Blank lines are ignored.
+pub fn get_encryption_keys<K>(ctx: &mut Context, keys: K) -> Result<Vec<gpgme::Key>>
+where
+    K: AsRef<[String]>,
+{
+    let keys = keys.as_ref();
+    let mut encryption_keys = Vec::new();
+
+    for key in keys.iter().map(|k| k.trim()).filter(|k| !k.is_empty()) {
+        match ctx.get_key(key) {
+            Ok(public_key) if public_key.can_encrypt() => encryption_keys.push(public_key),
+            _ => return Err(PassrsError::NoPublicKeyFound(key.to_owned()).into()),
+        }
+    }
+
+    Ok(encryption_keys)
 }
 
 /// A light wrapper around [`fs::create_dir_all`] that creates all directories
@@ -415,6 +574,16 @@ where
 /// Find a `.gpg-id` file in the specified path and return it if found;
 /// otherwise, return an error.
 pub fn find_gpg_id<P>(path: P) -> Result<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    self::find_recipients_file(path, EntryKind::Gpg)
+}
+
+/// Find the file listing the recipients for entries of `kind` (`.gpg-id` or
+/// `.age-recipients`) in the specified path and return it if found; otherwise,
+/// return an error.
+pub fn find_recipients_file<P>(path: P, kind: EntryKind) -> Result<PathBuf>
 where
     P: AsRef<Path>,
 {
@@ -429,12 +598,126 @@ where
             .map(|e| !e.starts_with(".git"))
             .unwrap_or(false);
 
-        if show && file_name == ".gpg-id" {
+        if show && file_name == kind.recipients_file() {
             return Ok(path);
         }
     }
 
-    Err(PassrsError::NoGpgIdFile(path.display().to_string()).into())
+    match kind {
+        EntryKind::Gpg => Err(PassrsError::NoGpgIdFile(path.display().to_string()).into()),
+        EntryKind::Age => Err(PassrsError::NoAgeRecipientsFile(path.display().to_string()).into()),
+    }
+}
+
+/// Returns whether entries at `path` are encrypted without revealing the key IDs
+/// of their recipients, i.e. whether the closest `.gpg-id` has a
+/// [`HIDDEN_RECIPIENTS_FILE`] next to it. This never applies to age entries, as
+/// age doesn't reveal its recipients to begin with.
+///
+/// [`HIDDEN_RECIPIENTS_FILE`]: ../consts/constant.HIDDEN_RECIPIENTS_FILE.html
+pub fn hides_recipients<P>(path: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    if EntryKind::from_path(path) == EntryKind::Age {
+        return false;
+    }
+
+    self::get_closest_gpg_id(path)
+        .map(|gpg_id| gpg_id.with_file_name(HIDDEN_RECIPIENTS_FILE).exists())
+        .unwrap_or(false)
+}
+
+/// Returns the gpg options for entries at `path` if the closest `.gpg-id` has a
+/// [`GPG_OPTS_FILE`] next to it: [`PASSWORD_STORE_GPG_OPTS`] followed by the
+/// options in that file, separated by whitespace. Blank lines and `#` comments
+/// are ignored. This never applies to age entries.
+///
+/// Like the `.gpg-id`, the file must have a valid signature if
+/// `PASSWORD_STORE_SIGNING_KEY` is set (see [`verify_file`]), as it could add
+/// hidden recipients.
+///
+/// [`GPG_OPTS_FILE`]: ../consts/constant.GPG_OPTS_FILE.html
+/// [`PASSWORD_STORE_GPG_OPTS`]: ../consts/static.PASSWORD_STORE_GPG_OPTS.html
+/// [`verify_file`]: fn.verify_file.html
+pub fn store_gpg_options<P>(path: P) -> Result<Option<GpgOptions>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    if EntryKind::from_path(path) == EntryKind::Age {
+        return Ok(None);
+    }
+
+    let opts_file = match self::get_closest_gpg_id(path) {
+        Ok(gpg_id) => gpg_id.with_file_name(GPG_OPTS_FILE),
+        Err(_) => return Ok(None),
+    };
+
+    if !opts_file.exists() {
+        return Ok(None);
+    }
+
+    self::verify_file(&opts_file)?;
+
+    let contents = fs::read_to_string(&opts_file)?;
+    let mut opts = PASSWORD_STORE_GPG_OPTS.clone();
+
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+
+        opts.extend(line.split_whitespace().map(ToOwned::to_owned));
+    }
+
+    let options = GpgOptions::parse(&opts)
+        .map_err(|e| format!("Invalid gpg options in {}: {}", opts_file.display(), e))?;
+
+    Ok(Some(options))
+}
+
+/// Returns the recipients that the entry at `path` should be encrypted to: the
+/// key(s) in the closest `.gpg-id` plus [`PASSWORD_STORE_KEY`] for OpenPGP
+/// entries, or the recipient(s) in the closest `.age-recipients` for age
+/// entries. Blank lines and `#` comments are ignored.
+///
+/// If `PASSWORD_STORE_SIGNING_KEY` is set, the `.gpg-id` must have a valid
+/// signature (see [`verify_file`]), so that a tampered `.gpg-id` can't sneak in
+/// another recipient.
+///
+/// [`PASSWORD_STORE_KEY`]: ../consts/static.PASSWORD_STORE_KEY.html
+/// [`verify_file`]: fn.verify_file.html
+pub fn get_recipients<P>(path: P, kind: EntryKind) -> Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let recipients_file = self::get_closest_recipients_file(path, kind)?;
+
+    if kind == EntryKind::Gpg {
+        self::verify_file(&recipients_file)?;
+    }
+
+    let file = fs::OpenOptions::new().read(true).open(&recipients_file)?;
+    let reader = BufReader::new(&file);
+    let mut keys = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let key = line.split('#').next().unwrap_or_default().trim();
+
+        if !key.is_empty() {
+            keys.push(key.to_owned());
+        }
+    }
+
+    if kind == EntryKind::Gpg {
+        keys.extend(PASSWORD_STORE_KEY.clone());
+    }
+
+    Ok(keys)
 }
 
 /// Commit all modified files using `commit_message` as the message, if the
@@ -449,6 +732,7 @@ where
     P: AsRef<Path>,
 {
     let commit_message = commit_message.as_ref();
+    let _lock = lock::acquire()?;
 
     // NOTE: similarly implemented in subcmds/init.rs
     if let Ok(repo) = Repository::open(&*PASSWORD_STORE_DIR) {
@@ -500,7 +784,7 @@ where
         let statuses = repo.statuses(Some(&mut status_opts))?;
         let parents: Vec<&Commit> = parents.iter().collect();
         let commit = if config.get_bool("commit.gpgsign")? {
-            let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+            let mut ctx = crypto::gpg_context()?;
             let buf = repo.commit_create_buffer(
                 &sig,
                 &sig,
@@ -637,41 +921,60 @@ where
     Ok(())
 }
 
-/// Provided a `Vec<u8>` of characters and a length, randomly generate a
-/// password.
+/// Provided a set of characters and a length, randomly generate a password of
+/// `len` characters.
 ///
-/// This uses the [ring] crate to generate an array of a multiple of 64 bytes
-/// for use as random indices.
+/// Every character in `set` is equally likely to be picked: duplicates are
+/// ignored, and indices come from [`random_below`], which uses the [ring]
+/// crate's RNG with rejection sampling.
 ///
+/// [`random_below`]: fn.random_below.html
 /// [ring]: https://docs.rs/ring
-pub fn generate_chars_from_set<V>(set: V, len: usize) -> Result<Vec<u8>>
+pub fn generate_chars_from_set<V>(set: V, len: usize) -> Result<String>
 where
-    V: AsRef<[u8]>,
+    V: AsRef<[char]>,
 {
-    let set = set.as_ref();
-    let rng = rand::SystemRandom::new();
-    let mut secret_bytes: Vec<u8> = Vec::with_capacity(len);
-    let mut random: Vec<u8> = Vec::new();
+    let mut seen = HashSet::new();
+    let set: Vec<char> = set
+        .as_ref()
+        .iter()
+        .copied()
+        .filter(|&c| seen.insert(c))
+        .collect();
+    let mut secret = String::with_capacity(len);
 
-    for _ in 0..=(len / 64) {
-        let rand: [u8; 64] = rand::generate(&rng)
-            .expect("failed to generate random array")
-            .expose();
+    if set.is_empty() {
+        return Err("The character set to generate secrets from is empty".into());
+    }
 
-        random.extend(rand.iter());
+    for _ in 0..len {
+        secret.push(set[self::random_below(set.len())?]);
     }
 
-    for &rand in random.iter() {
-        secret_bytes.push(set[rand as usize % set.len()]);
+    Ok(secret)
+}
 
-        if secret_bytes.len() == len {
-            break;
-        }
-    }
+/// Returns a uniformly distributed random number in `0..bound`.
+///
+/// Random numbers that would favour the lower part of the range (because
+/// `bound` doesn't evenly divide `u32::MAX + 1`) are rejected and drawn again.
+pub fn random_below(bound: usize) -> Result<usize> {
+    assert!(bound > 0 && bound as u64 <= 1 << 32, "bound out of range");
 
-    assert_eq!(secret_bytes.len(), len);
+    let rng = rand::SystemRandom::new();
+    let bound = bound as u64;
+    let zone = (1 << 32) - (1 << 32) % bound;
 
-    Ok(secret_bytes)
+    loop {
+        let random: [u8; 4] = rand::generate(&rng)
+            .map_err(|_| "failed to generate random number")?
+            .expose();
+        let random = u64::from(u32::from_le_bytes(random));
+
+        if random < zone {
+            return Ok((random % bound) as usize);
+        }
+    }
 }
 
 /// Helper function to prompt the user for a secret to be inserted or appended
@@ -765,14 +1068,30 @@ where
     }
 }
 
-/// Helper function to recrypt a directory that handles the case where a
-/// subdirectory has a `.gpg-id` (which causes it to break out of the loop, thus
-/// ignoring any directory with a `.gpg-id` except for the root,
-/// [`PASSWORD_STORE_DIR`]). If no `keys` are specified, use the key(s) in the
-/// closest `.gpg-id`.
+/// Helper function to recrypt the entries of `kind` in a directory, skipping
+/// any subdirectory that has its own recipients file (`.gpg-id` or
+/// `.age-recipients`, depending on `kind`), as those belong to another
+/// substore. If no `keys` are specified, use the recipient(s) in the closest
+/// recipients file. Either every entry is recrypted, or none are.
+pub fn recrypt_dir<P>(path: P, keys: Option<&[String]>, kind: EntryKind) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut tx = Transaction::new();
+
+    self::stage_recrypt_dir(&mut tx, path, keys, kind)?;
+    tx.commit(|| Ok(()))
+}
+
+/// Like [`recrypt_dir`], but only stages the recrypted entries in `tx`.
 ///
-/// [`PASSWORD_STORE_DIR`]: ../consts/static.PASSWORD_STORE_DIR.html
-pub fn recrypt_dir<P>(path: P, keys: Option<&[String]>) -> Result<()>
+/// [`recrypt_dir`]: fn.recrypt_dir.html
+pub fn stage_recrypt_dir<P>(
+    tx: &mut Transaction,
+    path: P,
+    keys: Option<&[String]>,
+    kind: EntryKind,
+) -> Result<()>
 where
     P: AsRef<Path>,
 {
@@ -780,108 +1099,228 @@ where
     let keys = if let Some(keys) = keys {
         Vec::from(keys)
     } else {
-        let gpgid = self::get_closest_gpg_id(&path)?;
-        let mut keys = Vec::new();
-        let mut file = fs::OpenOptions::new().read(true).open(&gpgid)?;
-
-        file.read_to_end(&mut keys)?;
-
-        let keys = str::from_utf8(&keys)?;
-
-        keys.lines().map(ToOwned::to_owned).collect()
+        self::get_recipients(path, kind)?
     };
 
     if keys.is_empty() {
         return Err(PassrsError::NoPrivateKeyFound.into());
     }
 
+    self::stage_recrypt_dir_impl(tx, path, &keys, kind)
+}
+
+fn stage_recrypt_dir_impl(
+    tx: &mut Transaction,
+    path: &Path,
+    keys: &[String],
+    kind: EntryKind,
+) -> Result<()> {
     for entry in fs::read_dir(&path)? {
         let entry = entry?;
         let path = entry.path();
-        let file_name = path.file_name();
         let show = entry
             .file_name()
             .to_str()
             .map(|e| !e.starts_with(".git"))
             .unwrap_or(false);
 
-        if show {
-            if file_name == Some(".gpg-id".as_ref()) {
-                if path == *GPG_ID_FILE {
-                    continue;
-                } else {
-                    break;
-                }
-            }
+        if !show {
+            continue;
+        }
 
-            if path.is_file() && path.extension() == Some("gpg".as_ref()) {
-                self::recrypt_file(path, Some(&keys))?;
-            } else if path.is_dir() {
-                self::recrypt_dir(path, Some(&keys))?;
-            }
+        if path.is_file()
+            && EntryKind::from_path(&path) == kind
+            && self::is_entry(path.to_string_lossy())
+        {
+            tx.write(&path, self::recrypt_bytes(&path, &path, Some(keys))?)?;
+        } else if path.is_dir() && self::find_recipients_file(&path, kind).is_err() {
+            self::stage_recrypt_dir_impl(tx, &path, keys, kind)?;
         }
     }
 
     Ok(())
 }
 
-/// Recrypts an individual file at `path` with `keys` (or [`PASSWORD_STORE_KEY`]
-/// if no keys are specified).
-///
-/// [`PASSWORD_STORE_DIR`]: ../consts/static.PASSWORD_STORE_DIR.html
+/// Recrypts an individual file at `path` with `keys` (or the recipient(s) in
+/// the closest recipients file if no keys are specified).
 pub fn recrypt_file<S>(path: S, keys: Option<&[String]>) -> Result<()>
 where
     S: AsRef<Path>,
 {
     let path = path.as_ref();
-    let keys = if let Some(keys) = keys {
-        Vec::from(keys)
-    } else {
-        let gpgid = self::get_closest_gpg_id(&path)?;
-        let mut keys = Vec::new();
-        let mut file = fs::OpenOptions::new().read(true).open(&gpgid)?;
 
-        file.read_to_end(&mut keys)?;
+    self::write_atomically(path, self::recrypt_bytes(path, path, keys)?)
+}
 
-        let keys = str::from_utf8(&keys)?;
+/// Decrypts the entry at `source` and encrypts it again for the entry at
+/// `dest` with `keys` (or the recipient(s) in the recipients file closest to
+/// `dest` if no keys are specified), without writing anything.
+pub fn recrypt_bytes<P, Q>(source: P, dest: Q, keys: Option<&[String]>) -> Result<Vec<u8>>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let decrypted_contents = self::decrypt_file_into_bytes(source)?;
 
-        keys.lines().map(ToOwned::to_owned).collect()
+    self::encrypt_bytes_for(dest, keys, &decrypted_contents)
+}
+
+/// Encrypts `plaintext` for the entry at `path` with `keys` (or the
+/// recipient(s) in the recipients file closest to `path` if no keys are
+/// specified), without writing anything.
+pub fn encrypt_bytes_for<P>(path: P, keys: Option<&[String]>, plaintext: &[u8]) -> Result<Vec<u8>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let kind = EntryKind::from_path(path);
+    let keys = if let Some(keys) = keys {
+        Vec::from(keys)
+    } else {
+        self::get_recipients(path, kind)?
     };
 
     if keys.is_empty() {
         return Err(PassrsError::NoPrivateKeyFound.into());
     }
 
-    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
-    let keys: Vec<gpgme::Key> = keys
-        .iter()
-        .map(|k| ctx.get_secret_key(k))
-        .filter_map(|k| k.ok())
-        .collect();
+    let backend = crypto::backend_for(kind)?;
 
-    if keys.is_empty() {
-        return Err(PassrsError::NoPrivateKeyFound.into());
+    self::encrypt_for(path, backend, &keys, plaintext)
+}
+
+/// Stages a copy of the entry `source` at `dest` in `tx`. It is recrypted for
+/// `dest` if that is an entry of another kind (see [`closest_entry_kind`]) or
+/// has other recipients than `source`; otherwise it is copied as is.
+///
+/// [`closest_entry_kind`]: fn.closest_entry_kind.html
+pub fn stage_copy_entry<P, Q>(tx: &mut Transaction, source: P, dest: Q) -> Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let source = source.as_ref();
+    let dest = dest.as_ref();
+    let kind = EntryKind::from_path(dest);
+
+    if kind != EntryKind::from_path(source)
+        || self::get_closest_recipients_file(dest, kind)?
+            != self::get_closest_recipients_file(source, kind)?
+    {
+        tx.write(dest, self::recrypt_bytes(source, dest, None)?)
+    } else {
+        tx.copy(source, dest)
+    }
+}
+
+/// Stages copies of everything in the folder `source` to the folder `dest` in
+/// `tx`. If `recrypt` is set, entries are recrypted for `dest` wherever its
+/// recipients differ from those of `source`, and converted (including their
+/// extension) to the kind of entries `dest` holds (see [`closest_entry_kind`]);
+/// folders with their own recipients file are substores and keep their
+/// recipients and kind.
+///
+/// [`closest_entry_kind`]: fn.closest_entry_kind.html
+pub fn stage_copy_dir<P, Q>(tx: &mut Transaction, source: P, dest: Q, recrypt: bool) -> Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let source = source.as_ref();
+    let dest = dest.as_ref();
+
+    if dest.starts_with(source) {
+        return Err(PassrsError::SourceIsDestination.into());
     }
 
-    let mut encrypted_contents = Data::load(path.display().to_string())?;
-    let mut decrypted_contents = Vec::new();
+    let is_substore = self::find_recipients_file(source, EntryKind::Gpg).is_ok()
+        || self::find_recipients_file(source, EntryKind::Age).is_ok();
+    let convert = if recrypt && !is_substore {
+        let kind = self::closest_entry_kind(dest);
 
-    ctx.decrypt(&mut encrypted_contents, &mut decrypted_contents)?;
+        Some((kind, self::get_recipients(dest, kind)?))
+    } else {
+        None
+    };
+    let mut keys = Vec::new();
 
-    let mut file = fs::OpenOptions::new()
-        .mode(0o666 - (0o666 & *PASSWORD_STORE_UMASK))
-        .write(true)
-        .open(&path)?;
-    let mut encrypted_contents = Vec::new();
+    for &kind in &[EntryKind::Gpg, EntryKind::Age] {
+        let source_recipients = self::get_closest_recipients_file(source, kind).ok();
+        let dest_recipients = self::get_closest_recipients_file(dest, kind).ok();
+        // Entries of the other kind are converted instead
+        let recrypt = recrypt
+            && !matches!(&convert, Some((k, _)) if *k != kind)
+            && dest_recipients.is_some()
+            && dest_recipients != source_recipients
+            && self::find_recipients_file(source, kind).is_err();
+
+        if recrypt {
+            keys.push((kind, self::get_recipients(dest, kind)?));
+        }
+    }
 
-    ctx.encrypt(&keys, &decrypted_contents, &mut encrypted_contents)?;
-    file.write_all(&encrypted_contents)?;
+    self::stage_copy_dir_impl(tx, source, dest, &keys, convert.as_ref())
+}
+
+fn stage_copy_dir_impl(
+    tx: &mut Transaction,
+    source: &Path,
+    dest: &Path,
+    keys: &[(EntryKind, Vec<String>)],
+    convert: Option<&(EntryKind, Vec<String>)>,
+) -> Result<()> {
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let path = entry.path();
+        let target = dest.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            let keys: Vec<_> = keys
+                .iter()
+                .filter(|(kind, _)| self::find_recipients_file(&path, *kind).is_err())
+                .cloned()
+                .collect();
+            let convert = convert.filter(|_| {
+                self::find_recipients_file(&path, EntryKind::Gpg).is_err()
+                    && self::find_recipients_file(&path, EntryKind::Age).is_err()
+            });
+
+            self::stage_copy_dir_impl(tx, &path, &target, &keys, convert)?;
+            continue;
+        }
+
+        let kind = EntryKind::from_path(&path);
+        let recrypt_keys = keys.iter().find(|(k, _)| *k == kind).map(|(_, keys)| keys);
+
+        match (convert, recrypt_keys) {
+            (Some((dest_kind, keys)), _)
+                if *dest_kind != kind && self::is_entry(path.to_string_lossy()) =>
+            {
+                let target = target.with_extension(&dest_kind.extension()[1..]);
+
+                tx.write(&target, self::recrypt_bytes(&path, &target, Some(keys))?)?
+            }
+            (_, Some(keys)) if self::is_entry(path.to_string_lossy()) => {
+                tx.write(&target, self::recrypt_bytes(&path, &target, Some(keys))?)?
+            }
+            _ => tx.copy(&path, &target)?,
+        }
+    }
 
     Ok(())
 }
 
 /// Helper function to find the `.gpg-id` closest to the specified path.
 pub fn get_closest_gpg_id<P>(path: P) -> Result<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    self::get_closest_recipients_file(path, EntryKind::Gpg)
+}
+
+/// Helper function to find the recipients file (`.gpg-id` or
+/// `.age-recipients`) for entries of `kind` closest to the specified path.
+pub fn get_closest_recipients_file<P>(path: P, kind: EntryKind) -> Result<PathBuf>
 where
     P: AsRef<Path>,
 {
@@ -893,13 +1332,49 @@ where
     };
 
     if path == *PASSWORD_STORE_DIR {
-        return Ok(GPG_ID_FILE.clone());
+        return match kind {
+            EntryKind::Gpg => Ok(GPG_ID_FILE.clone()),
+            EntryKind::Age if AGE_RECIPIENTS_FILE.exists() => Ok(AGE_RECIPIENTS_FILE.clone()),
+            EntryKind::Age => Err(PassrsError::NoAgeRecipientsFile(STORE_STRING.clone()).into()),
+        };
     }
 
-    match self::find_gpg_id(&path) {
-        Ok(gpgid) => Ok(gpgid),
-        Err(_) => self::get_closest_gpg_id(path.parent().ok_or("path's parent doesn't exist")?),
+    match self::find_recipients_file(&path, kind) {
+        Ok(file) => Ok(file),
+        Err(_) => self::get_closest_recipients_file(
+            path.parent().ok_or("path's parent doesn't exist")?,
+            kind,
+        ),
+    }
+}
+
+/// Determines whether new entries at `path` should be encrypted with OpenPGP
+/// or age: whichever recipients file is closest wins, with `.age-recipients`
+/// taking precedence over a `.gpg-id` in the same directory.
+pub fn closest_entry_kind<P>(path: P) -> EntryKind
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let mut dir = if path.is_dir() {
+        Some(path)
+    } else {
+        path.parent()
+    };
+
+    while let Some(path) = dir {
+        if self::find_recipients_file(path, EntryKind::Age).is_ok() {
+            return EntryKind::Age;
+        }
+
+        if self::find_recipients_file(path, EntryKind::Gpg).is_ok() || path == *PASSWORD_STORE_DIR {
+            break;
+        }
+
+        dir = path.parent();
     }
+
+    EntryKind::Gpg
 }
 
 /// Some subcommands require user interaction, which in turn requires stdout is
@@ -912,6 +1387,56 @@ pub fn ensure_stdout_is_tty() -> Result<()> {
     }
 }
 
+/// Verifies that `path` has a detached signature at `path.sig`, made by one of
+/// the keys in `PASSWORD_STORE_SIGNING_KEY`. Like `pass`, only full
+/// fingerprints are considered, and nothing is verified if no signing key was
+/// set.
+pub fn verify_file<P>(path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    if PASSWORD_STORE_SIGNING_KEY.is_empty() {
+        return Ok(());
+    }
+
+    let sigfile = PathBuf::from(format!("{}.sig", path.display()));
+
+    if !sigfile.exists() {
+        return Err(PassrsError::NoSignatureFound(path.display().to_string()).into());
+    }
+
+    let mut ctx = crypto::gpg_context()?;
+    let result = ctx.verify_detached(fs::read(&sigfile)?, fs::read(path)?)?;
+    let mut fingerprints = Vec::new();
+
+    for sig in result.signatures().filter(|sig| sig.status().is_ok()) {
+        if let Ok(fpr) = sig.fingerprint() {
+            fingerprints.push(fpr.to_owned());
+
+            // Signatures made by a subkey count for the primary key as well
+            if let Ok(primary) = ctx
+                .get_key(fpr)
+                .map(|key| key.fingerprint().map(str::to_owned))
+            {
+                fingerprints.extend(primary.ok());
+            }
+        }
+    }
+
+    let valid = PASSWORD_STORE_SIGNING_KEY
+        .iter()
+        .filter(|key| key.len() == 40 && key.bytes().all(|b| b.is_ascii_hexdigit()))
+        .any(|key| fingerprints.iter().any(|fpr| fpr.eq_ignore_ascii_case(key)));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(PassrsError::InvalidSignature(path.display().to_string()).into())
+    }
+}
+
 /// Copies `source` to `dest` recursively.
 pub fn copy<P, Q>(source: &P, dest: &Q) -> Result<()>
 where
@@ -962,7 +1487,11 @@ where
             self::create_dirs_to_file(&dest)?;
         }
 
-        fs::copy(source, dest)?;
+        self::write_atomically_with_mode(
+            dest,
+            &fs::read(source)?,
+            meta.permissions().mode() & 0o7777,
+        )?;
     } else if meta.is_dir() {
         if let Some(root) = root {
             if root == id {
@@ -0,0 +1,62 @@
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use passrs::agent;
+use passrs::consts;
+use passrs::util::{self, EditMode};
+
+use common::{Store, TempDir};
+
+fn wait_for_socket() {
+    let start = Instant::now();
+
+    while !consts::PASSRS_AGENT_SOCKET.exists() {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+// The agent's socket is shared, so everything is tested in sequence
+#[test]
+fn agent() {
+    common::fake_test_setup(Store::Empty, &[]);
+
+    let dir = TempDir::in_store("agent");
+    let entry = dir.join("entry.gpg");
+
+    fs::write(dir.join(".gpg-id"), "passrs@testuser").unwrap();
+    util::encrypt_bytes_into_file("secret", &entry, EditMode::Clobber).unwrap();
+
+    assert!(agent::request(&entry).unwrap().is_none());
+    assert!(!agent::stop().unwrap());
+
+    let server = thread::spawn(|| agent::serve(Duration::from_secs(60)));
+
+    wait_for_socket();
+    assert!(agent::serve(Duration::from_secs(60)).is_err());
+    assert_eq!(agent::request(&entry).unwrap().unwrap(), b"secret");
+    assert_eq!(util::decrypt_file_into_bytes(&entry).unwrap(), b"secret");
+
+    // Modified entries aren't served from the cache
+    util::encrypt_bytes_into_file("new secret", &entry, EditMode::Clobber).unwrap();
+    assert_eq!(agent::request(&entry).unwrap().unwrap(), b"new secret");
+
+    // Only entries in the store are handed out
+    assert!(agent::request("Cargo.toml").is_err());
+    assert!(agent::request(dir.join(".gpg-id")).is_err());
+
+    assert!(agent::stop().unwrap());
+    server.join().unwrap().unwrap();
+    assert!(!consts::PASSRS_AGENT_SOCKET.exists());
+    assert!(agent::request(&entry).unwrap().is_none());
+
+    // Without any requests, the agent exits by itself
+    let server = thread::spawn(|| agent::serve(Duration::from_millis(200)));
+
+    wait_for_socket();
+    server.join().unwrap().unwrap();
+    assert!(!consts::PASSRS_AGENT_SOCKET.exists());
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+use data_encoding::HEXLOWER;
+use passrs::clipboard::{self, ClipboardBackend, CommandBackend, FileBackend};
+use ring::digest;
+
+use common::TempDir;
+
+#[test]
+fn file_backend() {
+    let dir = TempDir::new("file");
+    let file = dir.join("clipboard");
+    let backend = FileBackend::new(&file);
+
+    assert_eq!(backend.paste().unwrap(), b"");
+
+    backend.copy(b"hunter2").unwrap();
+
+    assert_eq!(backend.paste().unwrap(), b"hunter2");
+    assert_eq!(
+        fs::metadata(&file).unwrap().permissions().mode() & 0o777,
+        0o600
+    );
+
+    backend.clear().unwrap();
+
+    assert_eq!(backend.paste().unwrap(), b"");
+
+    // The process-wide clipboard
+    clipboard::set_backend(Box::new(FileBackend::new(&file))).unwrap();
+    clipboard::backend().unwrap().copy(b"hunter2").unwrap();
+
+    assert_eq!(clipboard::paste().unwrap(), b"hunter2");
+
+    clipboard::clear().unwrap();
+
+    assert_eq!(clipboard::paste().unwrap(), b"");
+}
+
+#[test]
+fn command_backend() {
+    let dir = TempDir::new("command");
+    let file = dir.join("clipboard");
+    let backend = CommandBackend {
+        copy: format!("cat > {}", file.display()),
+        paste: Some(format!("cat {}", file.display())),
+        clear: None,
+    };
+
+    backend.copy(b"hunter2").unwrap();
+
+    assert_eq!(fs::read(&file).unwrap(), b"hunter2");
+    assert_eq!(backend.paste().unwrap(), b"hunter2");
+
+    backend.clear().unwrap();
+
+    assert_eq!(fs::read(&file).unwrap(), b"");
+
+    let backend = CommandBackend {
+        copy: String::from("exit 1"),
+        paste: None,
+        clear: None,
+    };
+
+    assert!(backend.copy(b"hunter2").is_err());
+    assert!(backend.paste().is_err());
+    assert!(!backend.can_paste());
+}
+
+#[test]
+fn osc52_sequence() {
+    assert_eq!(
+        clipboard::osc52_sequence(b"hunter2", false),
+        b"\x1b]52;c;aHVudGVyMg==\x07".to_vec()
+    );
+    assert_eq!(
+        clipboard::osc52_sequence(b"hunter2", true),
+        b"\x1bPtmux;\x1b\x1b]52;c;aHVudGVyMg==\x07\x1b\\".to_vec()
+    );
+    assert!(!clipboard::backend_named("osc52").unwrap().can_paste());
+}
+
+#[test]
+fn select_backend() {
+    for var in &[
+        "PASSRS_CLIPBOARD_COPY",
+        "WAYLAND_DISPLAY",
+        "DISPLAY",
+        "TMUX",
+        "SSH_TTY",
+        "SSH_CONNECTION",
+    ] {
+        env::remove_var(var);
+    }
+
+    assert_eq!(clipboard::detect(), None);
+
+    env::set_var("SSH_CONNECTION", "10.0.0.1 22 10.0.0.2 22");
+    assert_eq!(clipboard::detect(), Some("osc52"));
+
+    env::set_var("TMUX", "/tmp/tmux-1000/default,1234,0");
+    assert_eq!(clipboard::detect(), Some("tmux"));
+
+    env::set_var("WAYLAND_DISPLAY", "wayland-0");
+    assert_eq!(clipboard::detect(), Some("wayland"));
+
+    env::set_var("PASSRS_CLIPBOARD_COPY", "cat > /dev/null");
+    assert_eq!(clipboard::detect(), Some("command"));
+
+    assert!(clipboard::backend_named("command").is_ok());
+    assert!(clipboard::backend_named("pigeon").is_err());
+}
+
+#[test]
+fn unclip_clears_clipboard() {
+    let dir = TempDir::new("unclip");
+    let file = dir.join("clipboard");
+    let hash = HEXLOWER.encode(digest::digest(&digest::SHA256, b"hunter2").as_ref());
+
+    fs::create_dir_all(dir.join("store")).unwrap();
+    fs::write(dir.join("store/.gpg-id"), "passrs@testuser").unwrap();
+    fs::write(&file, "hunter2").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_passrs"))
+        .args(["unclip", "0"])
+        .env("PASSWORD_STORE_DIR", dir.join("store"))
+        .env("PASSRS_CLIPBOARD_BACKEND", "file")
+        .env("PASSRS_CLIPBOARD_FILE", &file)
+        .env("PASSRS_UNCLIP_HASH", hash)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(fs::read(&file).unwrap(), b"");
+}
@@ -0,0 +1,281 @@
+// Not every test binary uses every helper
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str;
+use std::sync::Once;
+
+use git2::{Repository, Signature};
+use once_cell::sync::Lazy;
+use passrs::consts;
+use passrs::crypto::{self, CryptoBackend, GpgOptions};
+use passrs::error::{PassrsError, Result};
+
+/// The temporary directory this test binary works in.
+static TEST_DIR: Lazy<PathBuf> = Lazy::new(|| {
+    let dir = env::temp_dir().join(format!("passrs-test-{}", process::id()));
+
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+});
+
+/// What the store of a test binary starts out as.
+pub enum Store {
+    /// A copy of `tests/test_repo`.
+    TestRepo,
+    /// Nothing; the store doesn't exist until a test creates it.
+    Empty,
+}
+
+/// Sets up the environment shared by every test in this binary: passrs is
+/// pointed at a private store in a temporary directory, so that tests can
+/// modify it without touching `tests/test_repo` or the stores of other test
+/// binaries, and at an agent socket next to it. `vars` are set along with it.
+///
+/// Only the first call has any effect, so tests running in parallel never see
+/// a half-configured environment.
+pub fn test_setup(store: Store, vars: &[(&str, &str)]) {
+    static SETUP: Once = Once::new();
+
+    SETUP.call_once(|| {
+        let dir = &*TEST_DIR;
+
+        env::set_var("RUST_BACKTRACE", "1");
+        env::set_var("PASSWORD_STORE_DIR", dir.join("store"));
+        env::set_var("GNUPGHOME", "./tests/gnupg");
+        env::set_var("PASSRS_AGENT_SOCKET", dir.join("agent.sock"));
+        env::remove_var("PASSWORD_STORE_SIGNING_KEY");
+
+        for (key, value) in vars {
+            env::set_var(key, value);
+        }
+
+        if let Store::TestRepo = store {
+            copy_dir(Path::new("./tests/test_repo"), &dir.join("store"));
+        }
+    });
+}
+
+/// Like [`test_setup`], but encrypts with [`FakeBackend`] instead of gpg.
+pub fn fake_test_setup(store: Store, vars: &[(&str, &str)]) {
+    test_setup(store, vars);
+
+    // Only the first call actually sets it
+    let _ = crypto::set_backend(Box::new(FakeBackend));
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+
+    for dirent in fs::read_dir(from).unwrap() {
+        let dirent = dirent.unwrap();
+        let path = dirent.path();
+        let dest = to.join(dirent.file_name());
+
+        if path.is_dir() {
+            copy_dir(&path, &dest);
+        } else {
+            fs::copy(&path, &dest).unwrap();
+        }
+    }
+}
+
+/// Unwraps the error of `result`, which must be a [`PassrsError`].
+pub fn passrs_error<T>(result: Result<T>) -> PassrsError
+where
+    T: std::fmt::Debug,
+{
+    *result.unwrap_err().downcast::<PassrsError>().unwrap()
+}
+
+/// An empty directory for a single test, removed again when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates `name` in the temporary directory of this test binary, outside
+    /// of the store.
+    pub fn new(name: &str) -> TempDir {
+        TempDir::create(TEST_DIR.join(name))
+    }
+
+    /// Creates `name` in the store. It is hidden (its name starts with a dot),
+    /// so that it doesn't show up when listing or searching the store from
+    /// other tests.
+    pub fn in_store(name: &str) -> TempDir {
+        TempDir::create(consts::PASSWORD_STORE_DIR.join(format!(".{}", name)))
+    }
+
+    fn create(dir: PathBuf) -> TempDir {
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        TempDir(dir)
+    }
+
+    /// The name of this directory, relative to the store if it is in it.
+    pub fn name(&self) -> String {
+        self.0.file_name().unwrap().to_string_lossy().into_owned()
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Configures the author of commits in `repo`, and that they aren't signed.
+pub fn configure(repo: &Repository) {
+    let mut config = repo.config().unwrap();
+
+    config.set_str("user.name", "passrs").unwrap();
+    config.set_str("user.email", "passrs@testuser").unwrap();
+    config.set_bool("commit.gpgsign", false).unwrap();
+}
+
+/// Commits everything in the working directory of `repo`, including removals.
+pub fn commit_all(repo: &Repository, message: &str) {
+    let mut index = repo.index().unwrap();
+
+    index
+        .add_all(["."], git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    index.update_all(["."], None).unwrap();
+    index.write().unwrap();
+
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = Signature::now("passrs", "passrs@testuser").unwrap();
+    let parent = repo.head().ok().map(|h| h.peel_to_commit().unwrap());
+    let parents: Vec<_> = parent.iter().collect();
+
+    repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+        .unwrap();
+}
+
+/// Writes `plaintext` as an entry at `name` in the working directory of `repo`
+/// (see [`write_entry`]) and commits it.
+pub fn commit_entry(repo: &Repository, name: &str, plaintext: &str) {
+    write_entry(repo.workdir().unwrap().join(name), plaintext);
+    commit_all(repo, name);
+}
+
+/// What [`FakeBackend`] encrypts `plaintext` to for passrs@testuser.
+pub fn fake_entry(plaintext: &str) -> String {
+    fake_entry_for(&["passrs@testuser"], plaintext)
+}
+
+/// What [`FakeBackend`] encrypts `plaintext` to for `recipients`.
+pub fn fake_entry_for(recipients: &[&str], plaintext: &str) -> String {
+    let recipients: Vec<String> = recipients.iter().map(|&r| r.to_owned()).collect();
+    let cipher = FakeBackend
+        .encrypt(&recipients, plaintext.as_bytes())
+        .unwrap();
+
+    String::from_utf8(cipher).unwrap()
+}
+
+/// Writes `plaintext` as an entry encrypted by [`FakeBackend`] for
+/// passrs@testuser, creating the folders leading up to it.
+pub fn write_entry<P>(path: P, plaintext: &str)
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, fake_entry(plaintext)).unwrap();
+}
+
+/// An in-memory backend that "encrypts" by prefixing the plaintext with the
+/// list of recipients, so tests don't need gpg at all.
+pub struct FakeBackend;
+
+const KNOWN_RECIPIENTS: &[&str] = &["passrs@testuser", "passrs@teammate"];
+
+/// Plaintext that can't be encrypted, so tests can make operations fail midway.
+const INJECTED_FAILURE: &[u8] = b"inject failure";
+
+impl CryptoBackend for FakeBackend {
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let ciphertext = str::from_utf8(ciphertext)?;
+        let newline = ciphertext.find('\n').ok_or("missing header")?;
+
+        if !ciphertext.starts_with("fake:") {
+            return Err("not encrypted by FakeBackend".into());
+        }
+
+        Ok(ciphertext.as_bytes()[newline + 1..].to_vec())
+    }
+
+    fn encrypt(&self, recipients: &[String], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let recipients: Vec<&str> = recipients
+            .iter()
+            .map(|r| r.trim())
+            .filter(|r| !r.is_empty())
+            .collect();
+
+        if let Some(unknown) = recipients.iter().find(|r| !KNOWN_RECIPIENTS.contains(r)) {
+            return Err(format!("unknown recipient {}", unknown).into());
+        }
+
+        if plaintext == INJECTED_FAILURE {
+            return Err("injected failure".into());
+        }
+
+        let mut cipher = format!("fake:{}\n", recipients.join(",")).into_bytes();
+
+        cipher.extend(plaintext);
+
+        Ok(cipher)
+    }
+
+    fn encrypt_hidden(&self, recipients: &[String], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut cipher = self.encrypt(recipients, plaintext)?;
+        let newline = cipher.iter().position(|&b| b == b'\n').unwrap();
+
+        cipher.splice(..newline, b"fake:<hidden>".iter().cloned());
+
+        Ok(cipher)
+    }
+
+    fn encrypt_with_options(
+        &self,
+        recipients: &[String],
+        plaintext: &[u8],
+        options: &GpgOptions,
+        hidden: bool,
+    ) -> Result<Vec<u8>> {
+        let recipients = [recipients, &options.hidden_recipients].concat();
+
+        // Like gpg, hidden recipients imply hiding every recipient
+        if hidden || options.throw_keyids || !options.hidden_recipients.is_empty() {
+            self.encrypt_hidden(&recipients, plaintext)
+        } else {
+            self.encrypt(&recipients, plaintext)
+        }
+    }
+
+    fn user_id(&self, recipient: &str) -> Result<String> {
+        Ok(recipient.to_owned())
+    }
+}
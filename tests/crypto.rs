@@ -0,0 +1,276 @@
+mod common;
+
+use std::fs;
+
+use passrs::consts;
+use passrs::crypto;
+use passrs::util::{self, EditMode};
+
+use common::{FakeBackend, Store, TempDir};
+
+#[test]
+fn set_backend() {
+    common::fake_test_setup(Store::Empty, &[]);
+
+    assert!(crypto::set_backend(Box::new(FakeBackend)).is_err());
+    assert!(crypto::backend().unwrap().encrypt(&[], b"").is_ok());
+}
+
+#[test]
+fn roundtrip() {
+    common::fake_test_setup(Store::Empty, &[]);
+
+    let dir = TempDir::in_store("crypto");
+    let entry = dir.join("entry.gpg");
+
+    fs::write(dir.join(".gpg-id"), "passrs@testuser").unwrap();
+
+    util::encrypt_bytes_into_file("secret", &entry, EditMode::Clobber).unwrap();
+    util::encrypt_bytes_into_file("user: passrs", &entry, EditMode::Append).unwrap();
+
+    let raw = fs::read_to_string(&entry).unwrap();
+
+    assert_eq!(raw, common::fake_entry("secret\nuser: passrs"));
+    assert_eq!(
+        util::decrypt_file_into_strings(&entry).unwrap(),
+        vec![String::from("secret"), String::from("user: passrs")]
+    );
+
+    let keys = [
+        String::from("passrs@testuser"),
+        String::from("passrs@teammate"),
+    ];
+
+    util::recrypt_file(&entry, Some(&keys)).unwrap();
+
+    let raw = fs::read_to_string(&entry).unwrap();
+
+    assert_eq!(
+        raw,
+        common::fake_entry_for(
+            &["passrs@testuser", "passrs@teammate"],
+            "secret\nuser: passrs"
+        )
+    );
+    assert_eq!(
+        util::decrypt_file_into_bytes(&entry).unwrap(),
+        b"secret\nuser: passrs"
+    );
+
+    fs::write(dir.join(".gpg-id"), "passrs@nobody").unwrap();
+    assert!(util::encrypt_bytes_into_file("new", &entry, EditMode::Clobber).is_err());
+}
+
+#[test]
+fn hidden_recipients() {
+    common::fake_test_setup(Store::Empty, &[]);
+
+    let dir = TempDir::in_store("crypto-hidden");
+    let entry = dir.join("sub").join("entry.gpg");
+
+    fs::write(dir.join(".gpg-id"), "passrs@testuser").unwrap();
+    fs::write(dir.join(consts::HIDDEN_RECIPIENTS_FILE), "").unwrap();
+
+    assert!(util::hides_recipients(&entry));
+    assert!(!util::hides_recipients(dir.join("entry.age")));
+
+    util::encrypt_bytes_into_file("secret", &entry, EditMode::Clobber).unwrap();
+
+    assert!(fs::read_to_string(&entry)
+        .unwrap()
+        .starts_with("fake:<hidden>\n"));
+    assert_eq!(util::decrypt_file_into_bytes(&entry).unwrap(), b"secret");
+
+    fs::remove_file(dir.join(consts::HIDDEN_RECIPIENTS_FILE)).unwrap();
+    util::recrypt_file(&entry, None).unwrap();
+
+    assert!(!util::hides_recipients(&entry));
+    assert_eq!(
+        fs::read_to_string(&entry).unwrap(),
+        common::fake_entry("secret")
+    );
+}
+
+#[test]
+#[cfg(feature = "age")]
+fn age_roundtrip() {
+    use age::secrecy::ExposeSecret;
+    use passrs::transaction::Transaction;
+
+    common::fake_test_setup(Store::Empty, &[]);
+
+    let dir = TempDir::in_store("crypto-age");
+    let entry = dir.join("entry.age");
+    let identity = age::x25519::Identity::generate();
+
+    fs::write(dir.join("identities"), identity.to_string().expose_secret()).unwrap();
+    fs::write(
+        dir.join(".age-recipients"),
+        identity.to_public().to_string(),
+    )
+    .unwrap();
+    std::env::set_var("PASSRS_AGE_IDENTITIES", dir.join("identities"));
+
+    util::encrypt_bytes_into_file("secret", &entry, EditMode::Clobber).unwrap();
+
+    assert!(fs::read(&entry)
+        .unwrap()
+        .starts_with(b"age-encryption.org/v1"));
+    assert_eq!(util::decrypt_file_into_bytes(&entry).unwrap(), b"secret");
+
+    // Folders moved into an age substore are converted to age
+    let gpg_dir = TempDir::in_store("crypto-age-mv");
+    let mut tx = Transaction::new();
+
+    fs::write(gpg_dir.join(".gpg-id"), "passrs@testuser").unwrap();
+    util::encrypt_bytes_into_file("moved", gpg_dir.join("folder/entry.gpg"), EditMode::Clobber)
+        .unwrap();
+    util::stage_copy_dir(&mut tx, gpg_dir.join("folder"), dir.join("folder"), true).unwrap();
+    tx.commit(|| Ok(())).unwrap();
+
+    assert!(!dir.join("folder/entry.gpg").exists());
+    assert_eq!(
+        util::decrypt_file_into_bytes(dir.join("folder/entry.age")).unwrap(),
+        b"moved"
+    );
+
+    // So are entries copied into it, while the original stays as it was
+    let source = gpg_dir.join("folder/entry.gpg");
+    let dest = dir.join("copied");
+    let dest = dest.with_extension(&util::closest_entry_kind(&dest).extension()[1..]);
+    let mut tx = Transaction::new();
+
+    util::stage_copy_entry(&mut tx, &source, &dest).unwrap();
+    tx.commit(|| Ok(())).unwrap();
+
+    assert_eq!(dest, dir.join("copied.age"));
+    assert_eq!(util::decrypt_file_into_bytes(&dest).unwrap(), b"moved");
+    assert_eq!(
+        fs::read_to_string(&source).unwrap(),
+        common::fake_entry("moved")
+    );
+
+    fs::write(dir.join(".age-recipients"), "passrs@testuser").unwrap();
+    assert!(util::encrypt_bytes_into_file("new", &entry, EditMode::Clobber).is_err());
+}
+
+#[test]
+fn gpg_options() {
+    use crypto::GpgOptions;
+    use gpgme::{EncryptFlags, PinentryMode};
+
+    let defaults = GpgOptions::parse::<&str>(&[]).unwrap();
+
+    assert_eq!(defaults, GpgOptions::default());
+    assert_eq!(
+        defaults.encrypt_flags(),
+        EncryptFlags::NO_ENCRYPT_TO | EncryptFlags::NO_COMPRESS
+    );
+
+    let options = GpgOptions::parse(&[
+        "--armor",
+        "--trust-model=always",
+        "-R",
+        "0xDEADBEEF",
+        "--compress-algo",
+        "zlib",
+        "--pinentry-mode",
+        "loopback",
+        "--homedir=./tests/gnupg",
+        "--quiet",
+    ])
+    .unwrap();
+
+    assert_eq!(
+        options,
+        GpgOptions {
+            armor: true,
+            trust_model: Some(String::from("always")),
+            throw_keyids: false,
+            hidden_recipients: vec![String::from("0xDEADBEEF")],
+            compress: true,
+            pinentry_mode: Some(PinentryMode::Loopback),
+            homedir: Some(String::from("./tests/gnupg")),
+        }
+    );
+    assert_eq!(
+        options.encrypt_flags(),
+        EncryptFlags::NO_ENCRYPT_TO | EncryptFlags::ALWAYS_TRUST | EncryptFlags::THROW_KEYIDS
+    );
+
+    assert!(GpgOptions::parse(&["--throw-keyids"])
+        .unwrap()
+        .encrypt_flags()
+        .contains(EncryptFlags::THROW_KEYIDS));
+    assert!(!GpgOptions::parse(&["-z", "0"]).unwrap().compress);
+
+    // Only `always` skips gpg's trust checks; other models are passed on as is
+    let direct = GpgOptions::parse(&["--trust-model", "direct"]).unwrap();
+
+    assert_eq!(direct.trust_model.as_deref(), Some("direct"));
+    assert!(!direct.encrypt_flags().contains(EncryptFlags::ALWAYS_TRUST));
+    assert!(!GpgOptions::parse(&["--trust-model=tofu"])
+        .unwrap()
+        .encrypt_flags()
+        .contains(EncryptFlags::ALWAYS_TRUST));
+    assert!(GpgOptions::parse(&["--always-trust"])
+        .unwrap()
+        .encrypt_flags()
+        .contains(EncryptFlags::ALWAYS_TRUST));
+
+    assert!(GpgOptions::parse(&["--homedir"]).is_err());
+    assert!(GpgOptions::parse(&["--pinentry-mode", "sometimes"]).is_err());
+    assert!(GpgOptions::parse(&["--trust-model", "nonsense"]).is_err());
+    assert!(GpgOptions::parse(&["--default-key", "0xDEADBEEF"]).is_err());
+}
+
+#[test]
+fn store_gpg_options() {
+    common::fake_test_setup(Store::Empty, &[]);
+
+    let dir = TempDir::in_store("crypto-gpg-opts");
+    let sub = dir.join("sub");
+    let plain = TempDir::in_store("crypto-no-gpg-opts");
+
+    fs::create_dir_all(&sub).unwrap();
+    fs::write(dir.join(".gpg-id"), "passrs@testuser").unwrap();
+    fs::write(plain.join(".gpg-id"), "passrs@testuser").unwrap();
+    fs::write(
+        dir.join(".gpg-opts"),
+        "--armor # for mail\n\n--hidden-recipient passrs@teammate\n",
+    )
+    .unwrap();
+
+    // Entries in folders without a `.gpg-id` use their substore's options
+    let options = util::store_gpg_options(sub.join("entry.gpg"))
+        .unwrap()
+        .unwrap();
+
+    assert!(options.armor);
+    assert_eq!(options.hidden_recipients, vec!["passrs@teammate"]);
+    assert!(util::store_gpg_options(plain.join("entry.gpg"))
+        .unwrap()
+        .is_none());
+    assert!(util::store_gpg_options(sub.join("entry.age"))
+        .unwrap()
+        .is_none());
+
+    util::encrypt_bytes_into_file("secret", sub.join("entry.gpg"), EditMode::Clobber).unwrap();
+    util::encrypt_bytes_into_file("secret", plain.join("entry.gpg"), EditMode::Clobber).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(sub.join("entry.gpg")).unwrap(),
+        "fake:<hidden>\nsecret"
+    );
+    assert_eq!(
+        fs::read_to_string(plain.join("entry.gpg")).unwrap(),
+        common::fake_entry("secret")
+    );
+
+    fs::write(dir.join(".gpg-opts"), "--default-key 0xDEADBEEF").unwrap();
+
+    assert!(util::store_gpg_options(sub.join("entry.gpg")).is_err());
+    assert!(
+        util::encrypt_bytes_into_file("new", sub.join("entry.gpg"), EditMode::Clobber).is_err()
+    );
+}
@@ -0,0 +1,71 @@
+use passrs::entry::Entry;
+
+#[test]
+fn parse() {
+    let entry = Entry::parse(
+        "hunter2\n\
+         user: passrs@example.com\n\
+         URL: https://example.com/login\n\
+         security question: what is your quest?\n\
+         https://example.com/recovery\n\
+         otpauth://totp/example?secret=JBSWY3DPEHPK3PXP\n\
+         user:   alt@example.com  \n\
+         just some notes",
+    );
+
+    assert_eq!(entry.password, "hunter2");
+    assert_eq!(
+        entry.fields,
+        vec![
+            (String::from("user"), String::from("passrs@example.com")),
+            (
+                String::from("URL"),
+                String::from("https://example.com/login")
+            ),
+            (
+                String::from("security question"),
+                String::from("what is your quest?")
+            ),
+            (String::from("user"), String::from("alt@example.com")),
+        ]
+    );
+    assert_eq!(
+        entry.notes,
+        vec![
+            String::from("https://example.com/recovery"),
+            String::from("just some notes")
+        ]
+    );
+    assert_eq!(
+        entry.otp.as_deref(),
+        Some("otpauth://totp/example?secret=JBSWY3DPEHPK3PXP")
+    );
+}
+
+#[test]
+fn field() {
+    let entry = Entry::parse("hunter2\nuser: a\nurl: example.com\nUser: b");
+
+    assert_eq!(entry.field("user"), Some("a"));
+    assert_eq!(entry.field("URL"), Some("example.com"));
+    assert_eq!(entry.field("password"), None);
+    assert_eq!(
+        entry.fields_named("USER").collect::<Vec<_>>(),
+        vec!["a", "b"]
+    );
+}
+
+#[test]
+fn password_only() {
+    let entry = Entry::parse("user: not a field");
+
+    assert_eq!(entry.password, "user: not a field");
+    assert!(entry.fields.is_empty());
+    assert!(entry.notes.is_empty());
+    assert_eq!(entry.otp, None);
+    assert_eq!(Entry::parse(""), Entry::default());
+
+    let entry = Entry::parse("otpauth://totp/example?secret=JBSWY3DPEHPK3PXP");
+
+    assert_eq!(entry.otp.as_deref(), Some(entry.password.as_str()));
+}
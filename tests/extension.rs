@@ -0,0 +1,166 @@
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use passrs::consts;
+
+use common::{Store, TempDir};
+
+/// Creates an extension dir containing `hello.bash`.
+fn extension_dir(name: &str) -> TempDir {
+    self::extension_dir_with(
+        name,
+        "echo \"$PROGRAM $COMMAND $PREFIX $EXTENSIONS\" \"$@\"\n\
+         [ \"$1\" = fail ] && exit 3\n\
+         exit 0\n",
+    )
+}
+
+/// Creates an extension dir containing `hello.bash` with `script` in it.
+fn extension_dir_with(name: &str, script: &str) -> TempDir {
+    common::test_setup(Store::TestRepo, &[]);
+
+    let dir = TempDir::new(name);
+    let extension = dir.join("hello.bash");
+
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+    fs::write(&extension, script).unwrap();
+    fs::set_permissions(&extension, fs::Permissions::from_mode(0o700)).unwrap();
+
+    dir
+}
+
+fn passrs(dir: &Path, envs: &[(&str, &str)], args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_passrs"))
+        .args(args)
+        .env("PASSWORD_STORE_EXTENSIONS_DIR", dir)
+        .env("PASSRS_SYSTEM_EXTENSION_DIR", "./tests/nonexistent")
+        .env_remove("PASSWORD_STORE_ENABLE_EXTENSIONS")
+        .envs(envs.iter().cloned())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn run_extension() {
+    let dir = extension_dir("run");
+    let enabled = [("PASSWORD_STORE_ENABLE_EXTENSIONS", "true")];
+
+    let output = passrs(&dir, &enabled, &["hello", "world", "--flag"]);
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        format!(
+            "passrs hello {} {} world --flag",
+            consts::PASSWORD_STORE_DIR.display(),
+            dir.display()
+        )
+    );
+
+    // The extension's exit code is passed on
+    let output = passrs(&dir, &enabled, &["hello", "fail"]);
+
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn helpers() {
+    let dir = extension_dir_with(
+        "helpers",
+        "set_gpg_recipients \"$1\"\n\
+         echo \"${GPG_RECIPIENT_ARGS[@]}\" \"${#GPG_OPTS[@]}\"\n\
+         cmd_version\n\
+         check_sneaky_paths \"$1\"\n\
+         echo unreachable\n",
+    );
+    let enabled = [
+        ("PASSWORD_STORE_ENABLE_EXTENSIONS", "true"),
+        ("PASSWORD_STORE_GPG_OPTS", ""),
+    ];
+
+    let output = passrs(&dir, &enabled, &["hello", "../a"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.starts_with("-r passrs@testuser 4\n"));
+    assert!(stdout.contains(env!("CARGO_PKG_VERSION")));
+    assert!(!stdout.contains("unreachable"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("sneaky path"));
+}
+
+#[test]
+fn user_extensions_first() {
+    let user = extension_dir_with("user-first", "echo user\n");
+    let system = extension_dir_with("system-first", "echo system\n");
+    let run = |envs: &[(&str, &str)]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_passrs"))
+            .arg("hello")
+            .env("PASSWORD_STORE_EXTENSIONS_DIR", &*user)
+            .env("PASSRS_SYSTEM_EXTENSION_DIR", &*system)
+            .env_remove("PASSWORD_STORE_ENABLE_EXTENSIONS")
+            .envs(envs.iter().cloned())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8_lossy(&output.stdout).trim().to_owned()
+    };
+
+    assert_eq!(run(&[("PASSWORD_STORE_ENABLE_EXTENSIONS", "true")]), "user");
+    assert_eq!(run(&[]), "system");
+}
+
+#[test]
+fn extensions_disabled() {
+    let dir = extension_dir("disabled");
+
+    assert!(!passrs(&dir, &[], &["hello"]).status.success());
+    assert!(!passrs(
+        &dir,
+        &[("PASSWORD_STORE_ENABLE_EXTENSIONS", "1")],
+        &["hello"]
+    )
+    .status
+    .success());
+    assert!(!passrs(
+        &dir,
+        &[("PASSWORD_STORE_ENABLE_EXTENSIONS", "true")],
+        &["nonexistent"]
+    )
+    .status
+    .success());
+}
+
+#[test]
+fn world_writable_extension() {
+    let dir = extension_dir("writable");
+    let enabled = [("PASSWORD_STORE_ENABLE_EXTENSIONS", "true")];
+
+    fs::set_permissions(dir.join("hello.bash"), fs::Permissions::from_mode(0o777)).unwrap();
+    assert!(!passrs(&dir, &enabled, &["hello"]).status.success());
+
+    fs::set_permissions(dir.join("hello.bash"), fs::Permissions::from_mode(0o700)).unwrap();
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+    assert!(!passrs(&dir, &enabled, &["hello"]).status.success());
+}
+
+#[test]
+fn unsigned_extension() {
+    let dir = extension_dir("unsigned");
+    let enabled = [
+        ("PASSWORD_STORE_ENABLE_EXTENSIONS", "true"),
+        (
+            "PASSWORD_STORE_SIGNING_KEY",
+            "0123456789ABCDEF0123456789ABCDEF01234567",
+        ),
+    ];
+
+    let output = passrs(&dir, &enabled, &["hello"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("does not exist"));
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use passrs::consts;
+use passrs::sync;
+
+use common::{Store, TempDir};
+
+#[test]
+fn merge_entry_files() {
+    common::fake_test_setup(Store::TestRepo, &[]);
+
+    let dir = TempDir::in_store("merge-driver");
+    let entry = format!("{}/entry.gpg", dir.name());
+    // Like the temporary files git passes to merge drivers
+    let base = dir.join(".merge_file_base");
+    let ours = dir.join(".merge_file_ours");
+    let theirs = dir.join(".merge_file_theirs");
+
+    fs::write(dir.join(".gpg-id"), "passrs@teammate").unwrap();
+    common::write_entry(&base, "password\nuser: me\n");
+    common::write_entry(&ours, "new password\nuser: me\n");
+    common::write_entry(&theirs, "password\nuser: myself\n");
+
+    assert_eq!(
+        sync::merge_entry_files(&base, &ours, &theirs, &entry).unwrap(),
+        0
+    );
+    // Encrypted for the entry's recipients
+    assert_eq!(
+        fs::read_to_string(&ours).unwrap(),
+        common::fake_entry_for(&["passrs@teammate"], "new password\nuser: myself\n")
+    );
+
+    // Added on both sides
+    fs::write(&base, "").unwrap();
+    common::write_entry(&theirs, "other password\n");
+
+    assert_eq!(
+        sync::merge_entry_files(&base, &ours, &theirs, &entry).unwrap(),
+        1
+    );
+    assert!(fs::read_to_string(&ours)
+        .unwrap()
+        .contains(sync::CONFLICT_START));
+}
+
+#[test]
+#[ignore] // Don't spawn gpg-agent
+fn diff_driver() {
+    common::test_setup(Store::TestRepo, &[]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_passrs"))
+        .arg("git-diff-driver")
+        .arg(consts::PASSWORD_STORE_DIR.join("a.gpg"))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!output.stdout.is_empty());
+    assert!(!output.stdout.starts_with(b"-----BEGIN PGP"));
+}
@@ -0,0 +1,294 @@
+mod common;
+
+use std::fs;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use passrs::agent;
+use passrs::consts;
+use passrs::crypto::{self, CryptoBackend};
+use passrs::error::Result;
+use passrs::util::{self, EditMode};
+
+use common::{FakeBackend, Store, TempDir};
+
+/// The same secrets as `tests/test_repo`, which is encrypted with gpg.
+const ENTRIES: &[(&str, &str)] = &[
+    ("A", r#"-N){'a"V;7N'i!TyM"@st:A%"#),
+    ("Xgh", r#"[cCVvJJG3E6d-N$cC~5p#}G5"#),
+    ("a", r#"eHy;CDpa&4]Sf1g*rx1Zlrig"#),
+    ("b", r#"~)-~/xK-)R@2n,>Ke:u|g]x#"#),
+    ("c", r#"l4zA$~f"h1$IZ``XaUbe#KIr"#),
+    ("d", r#"yq{u9by/kxG+\t$@b]-=noCk"#),
+    ("e", r#"2$t1?\UMs"OgT^YD%MlJhnDF"#),
+    ("f", "a\nb\nc\nd\ne\nf"),
+    ("xkl", r#"dNe'>A/qb<N`OSQ8"XOwVrG~"#),
+];
+
+/// Plaintext containing this is counted by [`CountingBackend`].
+const COUNTED: &[u8] = b"counted";
+
+static DECRYPTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts how often entries containing [`COUNTED`] are decrypted.
+struct CountingBackend;
+
+impl CryptoBackend for CountingBackend {
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let plaintext = FakeBackend.decrypt(ciphertext)?;
+
+        if plaintext.windows(COUNTED.len()).any(|w| w == COUNTED) {
+            DECRYPTIONS.fetch_add(1, Ordering::SeqCst);
+        }
+
+        Ok(plaintext)
+    }
+
+    fn encrypt(&self, recipients: &[String], plaintext: &[u8]) -> Result<Vec<u8>> {
+        FakeBackend.encrypt(recipients, plaintext)
+    }
+
+    fn user_id(&self, recipient: &str) -> Result<String> {
+        FakeBackend.user_id(recipient)
+    }
+}
+
+// The `passrs grep` processes can't use another backend, so they decrypt
+// through an agent running in this test binary instead
+fn setup() {
+    static AGENT: Once = Once::new();
+
+    AGENT.call_once(|| {
+        crypto::set_backend(Box::new(CountingBackend)).unwrap();
+        common::test_setup(Store::Empty, &[]);
+
+        let store = &*consts::PASSWORD_STORE_DIR;
+
+        fs::create_dir_all(store).unwrap();
+        fs::write(store.join(".gpg-id"), "passrs@testuser").unwrap();
+
+        for (name, contents) in ENTRIES {
+            let path = store.join(format!("{}.gpg", name));
+
+            util::encrypt_bytes_into_file(contents, path, EditMode::Clobber).unwrap();
+        }
+
+        thread::spawn(|| agent::serve(Duration::from_secs(3600)));
+
+        let start = Instant::now();
+
+        while !consts::PASSRS_AGENT_SOCKET.exists() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+    });
+}
+
+fn grep(args: &[&str], grepoptions: &str) -> Output {
+    setup();
+
+    Command::new(env!("CARGO_BIN_EXE_passrs"))
+        .arg("grep")
+        .args(args)
+        .env("GREPOPTIONS", grepoptions)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{:?}", output);
+
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn invalid_grepoptions() {
+    let output = grep(&["a"], "--bogus");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("GREPOPTIONS"));
+}
+
+#[test]
+fn invalid_regex() {
+    assert!(!grep(&["[cCVv"], "").status.success());
+    assert!(!grep(&["-c", "-l", "a"], "").status.success());
+}
+
+#[test]
+fn subfolder_doesnt_exist() {
+    assert!(!grep(&["a", "nonexistent"], "").status.success());
+}
+
+#[test]
+fn ignore_case() {
+    assert_eq!(stdout(&grep(&["EHY"], "")), "");
+    assert!(stdout(&grep(&["-i", "EHY"], "")).contains("1:eHy;CDpa&4]Sf1g*rx1Zlrig"));
+}
+
+#[test]
+fn fixed_strings() {
+    let out = stdout(&grep(&["-F", "[cCVv"], ""));
+
+    assert!(out.contains("Xgh"));
+    assert!(out.contains("1:[cCVvJJG3E6d-N$cC~5p#}G5"));
+}
+
+#[test]
+fn word_regexp() {
+    assert!(stdout(&grep(&["-w", "b"], "")).contains("2:b"));
+    // "9by" in d.gpg isn't a whole word
+    assert!(stdout(&grep(&["9b"], "")).contains("yq{u9by"));
+    assert_eq!(stdout(&grep(&["-w", "9b"], "")), "");
+}
+
+#[test]
+fn invert_match() {
+    let out = stdout(&grep(&["-v", "-c", "^[a-c]$"], ""));
+
+    assert!(out.contains("f:3"));
+}
+
+#[test]
+fn context() {
+    let out = stdout(&grep(&["-A", "1", "^c$"], ""));
+
+    assert!(out.contains("3:c\n4-d\n"));
+    assert!(!out.contains("2-b"));
+
+    let out = stdout(&grep(&["-C", "1", "^c$"], ""));
+
+    assert!(out.contains("2-b\n3:c\n4-d\n"));
+
+    let out = stdout(&grep(&["-B", "2", "^c$"], ""));
+
+    assert!(out.contains("1-a\n2-b\n3:c\n"));
+    assert!(!out.contains("4-d"));
+}
+
+#[test]
+fn count() {
+    let out = stdout(&grep(&["-c", "^[a-f]$"], ""));
+
+    assert!(out.contains("f:6"));
+    // Secrets without any matches aren't listed
+    assert_eq!(out.lines().count(), 1);
+}
+
+#[test]
+fn files_with_matches() {
+    let out = stdout(&grep(&["-l", "x"], ""));
+
+    // a.gpg ("rx1"), b.gpg ("xK") and d.gpg ("kxG"); the rest only have an
+    // uppercase X, if any
+    assert!(out.lines().any(|l| l.ends_with('a')));
+    assert!(out.lines().any(|l| l.ends_with('b')));
+    assert!(out.lines().any(|l| l.ends_with('d')));
+    assert!(!out.contains(':'));
+    assert_eq!(out.lines().count(), 3);
+}
+
+#[test]
+fn grepoptions() {
+    assert!(stdout(&grep(&["EHY"], "-i")).contains("1:eHy;CDpa&4]Sf1g*rx1Zlrig"));
+    // Options on the command line win over $GREPOPTIONS
+    assert!(stdout(&grep(&["-l", "^[a-f]$"], "-c"))
+        .lines()
+        .all(|l| !l.contains(':')));
+}
+
+#[test]
+fn subfolder_and_fields() {
+    setup();
+
+    let dir = TempDir::in_store("grep");
+    let name = dir.name();
+
+    fs::create_dir_all(dir.join("Internet")).unwrap();
+    fs::create_dir_all(dir.join(".hidden")).unwrap();
+    fs::write(dir.join(".gpg-id"), "passrs@testuser").unwrap();
+    util::encrypt_bytes_into_file(
+        "user: hunter2\nuser: passrs@example.com\nurl: example.com\nnotes: ask passrs",
+        dir.join("Internet/example.gpg"),
+        EditMode::Clobber,
+    )
+    .unwrap();
+    util::encrypt_bytes_into_file(
+        "passrs\nuser: passrs@example.org",
+        dir.join("other.gpg"),
+        EditMode::Clobber,
+    )
+    .unwrap();
+    util::encrypt_bytes_into_file("passrs", dir.join(".hidden/secret.gpg"), EditMode::Clobber)
+        .unwrap();
+
+    let out = stdout(&grep(&["passrs", &format!("{}/Internet", name)], ""));
+
+    assert!(out.contains("example"));
+    assert!(!out.contains("other"));
+
+    let out = stdout(&grep(&["passrs", &name], ""));
+
+    assert!(out.contains("other"));
+    // Hidden folders aren't searched
+    assert!(!out.contains("secret"));
+
+    let out = stdout(&grep(&["--field", "user", "passrs", &name], ""));
+
+    // The first line is the password, not a field
+    assert!(!out.contains("hunter2"));
+    // Line numbers are those of the whole entry
+    assert!(out.contains("2:user: passrs@example.com"));
+    assert!(out.contains("2:user: passrs@example.org"));
+    assert!(!out.contains("ask passrs"));
+
+    let out = stdout(&grep(
+        &["--field", "URL", "--field", "notes", "example", &name],
+        "",
+    ));
+
+    assert!(out.contains("3:url: example.com"));
+    assert!(!out.contains("passrs@example.com"));
+
+    // Context isn't limited to the fields
+    let out = stdout(&grep(&["--field", "url", "-B", "1", "example", &name], ""));
+
+    assert!(out.contains("2-user: passrs@example.com\n3:url: example.com"));
+    assert!(!out.contains("passrs@example.org"));
+
+    // Nor is an inverted match, though the password is still never shown
+    let out = stdout(&grep(&["--field", "user", "-v", "passrs", &name], ""));
+
+    assert!(!out.contains("hunter2"));
+    assert!(!out.contains("url"));
+}
+
+#[test]
+fn cache_across_greps() {
+    setup();
+
+    let dir = TempDir::in_store("grep-cache");
+    let name = dir.name();
+
+    fs::write(dir.join(".gpg-id"), "passrs@testuser").unwrap();
+    util::encrypt_bytes_into_file("counted", dir.join("one.gpg"), EditMode::Clobber).unwrap();
+    util::encrypt_bytes_into_file("counted", dir.join("two.gpg"), EditMode::Clobber).unwrap();
+
+    let grep = || {
+        Command::new(env!("CARGO_BIN_EXE_passrs"))
+            .args(["grep", "-c", "counted", &name])
+            .env("PASSRS_GREP_CACHE_TTL", "60")
+            .output()
+            .unwrap()
+    };
+
+    assert_eq!(stdout(&grep()).lines().count(), 2);
+    assert_eq!(DECRYPTIONS.load(Ordering::SeqCst), 2);
+
+    // The second grep is a separate process, but reuses the agent's plaintext
+    assert_eq!(stdout(&grep()).lines().count(), 2);
+    assert_eq!(DECRYPTIONS.load(Ordering::SeqCst), 2);
+}
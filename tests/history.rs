@@ -0,0 +1,72 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use git2::Repository;
+use passrs::consts;
+use passrs::history;
+
+use common::Store;
+
+#[test]
+fn entry_history() {
+    common::fake_test_setup(Store::Empty, &[]);
+
+    let store = &*consts::PASSWORD_STORE_DIR;
+    let repo = Repository::init(store).unwrap();
+
+    common::configure(&repo);
+    fs::write(store.join(".gpg-id"), "passrs@testuser").unwrap();
+    common::write_entry(store.join("a.gpg"), "first\n");
+    common::commit_all(&repo, "Add a");
+    common::write_entry(store.join("other.gpg"), "other\n");
+    common::commit_all(&repo, "Add other");
+    fs::rename(store.join("a.gpg"), store.join("b.gpg")).unwrap();
+    common::commit_all(&repo, "Rename a to b");
+    common::write_entry(store.join("b.gpg"), "second\n");
+    common::commit_all(&repo, "Edit b");
+
+    // The history of b follows it through the rename, skipping other
+    let log = history::log(Some("b.gpg")).unwrap();
+    let summaries: Vec<_> = log.iter().map(|r| r.summary.as_str()).collect();
+
+    assert_eq!(summaries, ["Edit b", "Rename a to b", "Add a"]);
+    assert_eq!(log[1].path, Some(PathBuf::from("b.gpg")));
+    assert_eq!(log[1].old_path, Some(PathBuf::from("a.gpg")));
+    assert_eq!(log[2].path, Some(PathBuf::from("a.gpg")));
+    assert_eq!(log[2].old_path, None);
+    assert_eq!(history::log(None::<&str>).unwrap().len(), 4);
+
+    let change = history::changes(&log[0]).unwrap();
+
+    assert_eq!(change.old, Some(b"first\n".to_vec()));
+    assert_eq!(change.new, Some(b"second\n".to_vec()));
+
+    // Old versions can be decrypted by their old name
+    assert_eq!(
+        history::plaintext_at("HEAD~3", "a.gpg").unwrap(),
+        Some(b"first\n".to_vec())
+    );
+    assert_eq!(history::plaintext_at("HEAD~3", "b.gpg").unwrap(), None);
+
+    // Restoring re-encrypts for the current recipients and commits
+    fs::write(store.join(".gpg-id"), "passrs@teammate").unwrap();
+    common::commit_all(&repo, "Add teammate");
+    history::restore("HEAD~4", "a.gpg").unwrap();
+
+    assert_eq!(
+        fs::read_to_string(store.join("a.gpg")).unwrap(),
+        common::fake_entry_for(&["passrs@teammate"], "first\n")
+    );
+    assert_eq!(
+        repo.head()
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .summary()
+            .unwrap(),
+        "Restore a from HEAD~4"
+    );
+    assert!(history::restore("HEAD", "missing.gpg").is_err());
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use git2::Repository;
+use gpgme::{Context, Protocol};
+use passrs::consts;
+
+use common::{Store, TempDir};
+
+fn passrs(store: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_passrs"))
+        .args(args)
+        .env("PASSWORD_STORE_DIR", store)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn deinit_root() {
+    common::test_setup(Store::TestRepo, &[]);
+
+    let store = &*consts::PASSWORD_STORE_DIR;
+    let output = passrs(store, &["init", ""]);
+
+    assert!(!output.status.success());
+    assert!(store.join(".gpg-id").exists());
+}
+
+#[test]
+fn deinit_nonexistent_substore() {
+    common::test_setup(Store::TestRepo, &[]);
+
+    let store = &*consts::PASSWORD_STORE_DIR;
+
+    assert!(!passrs(store, &["init", "--path", "nonexistent", ""])
+        .status
+        .success());
+    // `a` is a secret, not a substore
+    assert!(!passrs(store, &["init", "--path", "a", ""]).status.success());
+}
+
+#[test]
+#[ignore] // Don't spawn gpg-agent
+fn deinit_substore() {
+    common::test_setup(Store::TestRepo, &[]);
+
+    let store = TempDir::new("deinit-store");
+    let sub = store.join("sub");
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp).unwrap();
+    let key = ctx.get_key("passrs@testuser").unwrap();
+    let mut cipher = Vec::new();
+
+    fs::create_dir_all(&sub).unwrap();
+    fs::write(store.join(".gpg-id"), "passrs@testuser").unwrap();
+    fs::write(sub.join(".gpg-id"), "passrs@testuser").unwrap();
+    fs::write(sub.join(".gpg-id.hidden"), "").unwrap();
+    ctx.encrypt(&[key], "secret", &mut cipher).unwrap();
+    fs::write(sub.join("entry.gpg"), cipher).unwrap();
+
+    let output = passrs(&store, &["init", "--path", "sub", ""]);
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!sub.join(".gpg-id").exists());
+    assert!(!sub.join(".gpg-id.hidden").exists());
+
+    let output = passrs(&store, &["show", "sub/entry"]);
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "secret");
+
+    // There's nothing left to deinitialize
+    assert!(!passrs(&store, &["init", "--path", "sub", ""])
+        .status
+        .success());
+}
+
+#[test]
+fn init_existing_store_sets_up_git() {
+    common::test_setup(Store::TestRepo, &[]);
+
+    let store = TempDir::new("init-existing-store");
+    let repo = Repository::init(&store).unwrap();
+
+    common::configure(&repo);
+
+    // What `pass git init` leaves behind
+    fs::write(store.join(".gpg-id"), "passrs@testuser\n").unwrap();
+    fs::write(store.join(".gitattributes"), "*.gpg diff=gpg\n").unwrap();
+    common::commit_all(&repo, "pass");
+
+    let output = passrs(&store, &["init", "passrs@testuser"]);
+
+    assert!(output.status.success(), "{:?}", output);
+
+    let config = repo.config().unwrap().snapshot().unwrap();
+
+    assert_eq!(
+        config.get_str("diff.gpg.textconv").unwrap(),
+        "passrs git-diff-driver"
+    );
+    assert_eq!(
+        config.get_str("merge.passrs.driver").unwrap(),
+        "passrs git-merge-driver %O %A %B %P"
+    );
+    assert_eq!(
+        fs::read_to_string(store.join(".gitattributes")).unwrap(),
+        "*.gpg diff=gpg\n*.gpg diff=gpg merge=passrs\n*.age diff=age merge=passrs\n"
+    );
+    assert!(repo.statuses(None).unwrap().is_empty());
+
+    // Nothing is left to add the second time around
+    let head = repo.head().unwrap().target();
+
+    assert!(passrs(&store, &["init", "passrs@testuser"])
+        .status
+        .success());
+    assert_eq!(repo.head().unwrap().target(), head);
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::fs;
+use std::os::unix::io::AsRawFd;
+
+use passrs::consts;
+use passrs::lock;
+
+use common::Store;
+
+#[test]
+fn store_lock() {
+    common::test_setup(Store::Empty, &[("PASSRS_LOCK_TIMEOUT", "0")]);
+
+    let store = &*consts::PASSWORD_STORE_DIR;
+
+    // There's nothing to lock yet
+    drop(lock::acquire().unwrap());
+    assert!(!store.exists());
+
+    fs::create_dir_all(store).unwrap();
+
+    {
+        let _outer = lock::acquire().unwrap();
+        let _inner = lock::acquire().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(lock::lock_path()).unwrap(),
+            std::process::id().to_string()
+        );
+    }
+
+    // Another process holds the lock
+    let other = fs::OpenOptions::new()
+        .write(true)
+        .open(lock::lock_path())
+        .unwrap();
+
+    assert_eq!(
+        unsafe { libc::flock(other.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) },
+        0
+    );
+    fs::write(lock::lock_path(), "12345").unwrap();
+
+    let err = lock::acquire().err().unwrap();
+
+    assert!(err.to_string().contains("PID 12345"), "{}", err);
+
+    drop(other);
+    drop(lock::acquire().unwrap());
+
+    fs::remove_dir_all(store).unwrap();
+}
@@ -0,0 +1,71 @@
+#![cfg(feature = "openpgp")]
+
+mod common;
+
+use passrs::crypto::{CryptoBackend, OpenPgpBackend};
+use passrs::error::PassrsError;
+
+use common::Store;
+
+const TESTUSER: &str = "4B0D9BBAC5C8329C035B125CF6EF0D39C5F84192";
+const IMPOSTOR: &str = "3E405B142614FB3D323C3046669EE5D8D9637A9C";
+
+#[test]
+fn find_cert() {
+    // Passrs Testuser <passrs@testuser>, Passrs Teammate <passrs@teammate> and
+    // Passrs Impostor <passrs@testuser>
+    common::test_setup(
+        Store::Empty,
+        &[("PASSRS_OPENPGP_CERTS", "./tests/openpgp-certs.asc")],
+    );
+
+    let backend = OpenPgpBackend::new().unwrap();
+    let user_id = |recipient: &str| backend.user_id(recipient);
+
+    for recipient in &[
+        "passrs@teammate",
+        "<passrs@teammate>",
+        "Passrs Teammate <passrs@teammate>",
+        "F55572CE4BCAF0789D2FCDEAF3A112D98FD4EE7A",
+        "0xF3A112D98FD4EE7A",
+        "8FD4EE7A",
+    ] {
+        assert_eq!(
+            user_id(recipient).unwrap(),
+            "passrs@teammate",
+            "{}",
+            recipient
+        );
+    }
+
+    // Keys can still be told apart by their user ID, fingerprint or subkeys
+    assert_eq!(
+        user_id("Passrs Testuser <passrs@testuser>").unwrap(),
+        "passrs@testuser"
+    );
+    assert_eq!(user_id(TESTUSER).unwrap(), "passrs@testuser");
+    assert_eq!(user_id("7D36CF249CCA9513").unwrap(), "passrs@testuser");
+
+    // Parts of user IDs or fingerprints used to match whichever came first
+    for recipient in &["passrs@te", "Passrs", "testuser", "39C5F84192"] {
+        match common::passrs_error(user_id(recipient)) {
+            PassrsError::NoPublicKeyFound(s) => assert_eq!(&s, recipient),
+            other => panic!("{}: {:?}", recipient, other),
+        }
+    }
+
+    match common::passrs_error(user_id("passrs@testuser")) {
+        PassrsError::AmbiguousRecipient(s, keys) => {
+            assert_eq!(s, "passrs@testuser");
+            assert_eq!(keys, [TESTUSER, IMPOSTOR]);
+        }
+        other => panic!("{:?}", other),
+    }
+
+    assert!(backend
+        .encrypt(&[String::from("passrs@testuser")], b"secret")
+        .is_err());
+    assert!(backend
+        .encrypt(&[String::from(TESTUSER)], b"secret")
+        .is_ok());
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::process::{Command, Output};
+
+use common::Store;
+
+fn passrs(args: &[&str]) -> Output {
+    common::test_setup(Store::TestRepo, &[]);
+
+    Command::new(env!("CARGO_BIN_EXE_passrs"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{:?}", output);
+
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn ls_json() {
+    let expected = r#"["A","Xgh","a","b","c","d","e","f","xkl"]"#;
+
+    assert_eq!(stdout(&passrs(&["ls", "--json"])).trim(), expected);
+    assert_eq!(
+        stdout(&passrs(&["ls", "--format", "json"])).trim(),
+        expected
+    );
+    assert!(!passrs(&["ls", "--json", "--format", "plain"])
+        .status
+        .success());
+}
+
+#[test]
+fn find_json() {
+    assert_eq!(
+        stdout(&passrs(&["find", "x", "--json"])).trim(),
+        r#"["Xgh","xkl"]"#
+    );
+    assert!(!passrs(&["find", "z", "--json"]).status.success());
+}
+
+#[test]
+fn show_json_multiple_matches() {
+    let output = passrs(&["show", "x", "--json"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    // Rather than asking which one to show
+    assert!(!output.status.success());
+    assert!(stderr.contains("Xgh, xkl"), "{}", stderr);
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+#[cfg(feature = "otp")]
+fn otp_code_json_multiple_matches() {
+    let output = passrs(&["otp", "code", "x", "--json"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(stderr.contains("Xgh, xkl"), "{}", stderr);
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+#[ignore] // Don't spawn gpg-agent
+fn show_json() {
+    assert_eq!(
+        stdout(&passrs(&["show", "f", "--json"])).trim(),
+        r#"{"fields":[],"notes":["b","c","d","e","f"],"otp":null,"password":"a","path":"f"}"#
+    );
+    assert!(!passrs(&["show", "f", "--json", "--field", "user"])
+        .status
+        .success());
+}
@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+
+use passrs::passphrase::{self, PassphraseOptions, Wordlist};
+
+const WORDLIST: &str = "11\tabacus\n12\tbanjo\n13\tcactus\n14\tdaisy\n\n15\telbow\n16\tfable\n";
+
+#[test]
+fn parse_wordlist() {
+    assert_eq!(Wordlist::parse(WORDLIST).unwrap().len(), 6);
+    // Dice rolls are optional
+    assert_eq!(Wordlist::parse("abacus\nbanjo\n").unwrap().len(), 2);
+
+    assert!(Wordlist::parse("abacus\nbanjo\nabacus\n").is_err());
+    assert!(Wordlist::parse("abacus\n").is_err());
+    assert!(Wordlist::parse("abacus banjo cactus\n").is_err());
+}
+
+#[test]
+fn generate_passphrase() {
+    let wordlist = Wordlist::parse(WORDLIST).unwrap();
+    let words: HashSet<_> = WORDLIST
+        .split_whitespace()
+        .filter(|w| !w.starts_with('1'))
+        .collect();
+    let options = PassphraseOptions {
+        words: 5,
+        separator: String::from("."),
+        ..Default::default()
+    };
+    let passphrase = passphrase::generate(&wordlist, &options).unwrap();
+    let generated: Vec<_> = passphrase.text.split('.').collect();
+
+    assert_eq!(generated.len(), 5);
+    assert!(generated.iter().all(|w| words.contains(w)));
+    assert!((passphrase.entropy - 5.0 * 6f64.log2()).abs() < 1e-9);
+
+    let options = PassphraseOptions {
+        words: 4,
+        capitalize: true,
+        digit: true,
+        ..Default::default()
+    };
+    let passphrase = passphrase::generate(&wordlist, &options).unwrap();
+    let generated: Vec<_> = passphrase.text.split('-').collect();
+
+    assert_eq!(generated.len(), 4);
+    assert!(generated.iter().all(|w| w.starts_with(char::is_uppercase)));
+    assert_eq!(
+        generated
+            .iter()
+            .filter(|w| w.ends_with(|c: char| c.is_ascii_digit()))
+            .count(),
+        1
+    );
+    assert!((passphrase.entropy - (4.0 * 6f64.log2() + 10f64.log2() + 2.0)).abs() < 1e-9);
+
+    let options = PassphraseOptions {
+        words: 0,
+        ..Default::default()
+    };
+
+    assert!(passphrase::generate(&wordlist, &options).is_err());
+}
+
+#[test]
+fn eff_wordlist_entropy() {
+    // 6 words from the EFF long wordlist are about 77.5 bits
+    let entropy = passphrase::entropy(7776, &PassphraseOptions::default());
+
+    assert!((entropy - 77.55).abs() < 0.01);
+}
+
+#[test]
+fn bundled_wordlist() {
+    assert_eq!(Wordlist::bundled().unwrap().len(), 7776);
+}
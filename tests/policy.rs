@@ -0,0 +1,148 @@
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+
+use passrs::consts;
+use passrs::policy::Policy;
+
+use common::{Store, TempDir};
+
+fn chars(set: &[u8]) -> Vec<char> {
+    set.iter().copied().map(char::from).collect()
+}
+
+#[test]
+fn parse_policy() {
+    let policy = Policy::parse(
+        "# Example's rules\nlength = 16\nmin-digits = 2\n\nexclude-ambiguous = true\nsymbols = !@#\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        policy,
+        Policy {
+            length: Some(16),
+            min_digits: Some(2),
+            exclude_ambiguous: Some(true),
+            symbols: Some(vec!['!', '@', '#']),
+            ..Default::default()
+        }
+    );
+
+    assert!(Policy::parse("min-digits = two").is_err());
+    assert!(Policy::parse("min-digits").is_err());
+    assert!(Policy::parse("max-length = 16").is_err());
+}
+
+#[test]
+fn policy_from_substore() {
+    common::test_setup(Store::TestRepo, &[]);
+
+    let dir = TempDir::in_store("policy");
+
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(
+        dir.join(consts::POLICY_FILE),
+        "min-upper = 3\nmax-repeat = 1\nexclude-ambiguous = true\n",
+    )
+    .unwrap();
+
+    let policy = Policy::for_entry(dir.join("sub/entry.gpg")).unwrap();
+
+    assert_eq!(policy.min_upper, Some(3));
+
+    // Flags override the file
+    let policy = policy.with_overrides(Policy {
+        min_upper: Some(1),
+        ..Default::default()
+    });
+
+    assert_eq!(policy.min_upper, Some(1));
+    assert_eq!(policy.max_repeat, Some(1));
+    assert_eq!(policy.exclude_ambiguous, Some(true));
+
+    // Including when they turn an option off
+    let policy = policy.with_overrides(Policy {
+        exclude_ambiguous: Some(false),
+        ..Default::default()
+    });
+
+    assert_eq!(policy.exclude_ambiguous, Some(false));
+    assert_eq!(policy.allowed_chars(['0', 'a']), vec!['0', 'a']);
+    assert_eq!(
+        Policy::for_entry(consts::PASSWORD_STORE_DIR.join("a.gpg")).unwrap(),
+        Policy::default()
+    );
+}
+
+#[test]
+fn generate_meets_policy() {
+    let policy = Policy {
+        min_digits: Some(3),
+        min_upper: Some(2),
+        min_special: Some(2),
+        exclude_ambiguous: Some(true),
+        max_repeat: Some(1),
+        symbols: Some(vec!['!', '@']),
+        ..Default::default()
+    };
+    let set = chars(
+        &[
+            consts::DIGITS,
+            consts::ALPHA_LOWER,
+            consts::ALPHA_UPPER,
+            consts::SPECIAL,
+        ]
+        .concat(),
+    );
+
+    for _ in 0..200 {
+        let password = policy.generate(&set, 12).unwrap();
+
+        assert_eq!(password.len(), 12);
+        assert!(policy.accepts(&password));
+        assert!(password.bytes().all(|c| !consts::AMBIGUOUS.contains(&c)
+            && (!consts::SPECIAL.contains(&c) || b"!@".contains(&c))));
+    }
+
+    // Requirements that can't be met
+    assert!(policy.generate(&set, 6).is_err());
+    assert!(policy.generate(chars(consts::ALPHA_LOWER), 12).is_err());
+
+    // Characters outside the ASCII classes are allowed, but don't count
+    let policy = Policy {
+        min_special: Some(1),
+        ..Default::default()
+    };
+    let password = policy.generate(['é', '€', '!'], 8).unwrap();
+
+    assert_eq!(password.chars().count(), 8);
+    assert!(password.contains('!'));
+    assert!(!policy.accepts("é€é€é€é€"));
+}
+
+#[test]
+fn generate_is_unbiased() {
+    // Of the passwords from "0a" with at least one digit, "00", "0a" and "a0"
+    // should be equally likely
+    let policy = Policy {
+        min_digits: Some(1),
+        ..Default::default()
+    };
+    let mut counts = HashMap::new();
+
+    for _ in 0..3000 {
+        *counts
+            .entry(policy.generate(['0', 'a'], 2).unwrap())
+            .or_insert(0) += 1;
+    }
+
+    assert_eq!(counts.len(), 3);
+    // Each is expected 1000 times, with a standard deviation of about 26
+    assert!(
+        counts.values().all(|&n| n > 850 && n < 1150),
+        "{:?}",
+        counts
+    );
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use git2::Repository;
+use passrs::consts;
+use passrs::rotate::{self, Rotation};
+
+use common::Store;
+
+fn read_entry(store: &Path, name: &str) -> String {
+    fs::read_to_string(store.join(name)).unwrap()
+}
+
+#[test]
+fn rotated() {
+    let plaintext = "old\nuser: me\nprevious: older\nnotes\n";
+
+    assert_eq!(
+        rotate::rotated(plaintext, "new", false),
+        "new\nuser: me\nprevious: older\nnotes\n"
+    );
+    assert_eq!(
+        rotate::rotated(plaintext, "new", true),
+        "new\nprevious: old\nuser: me\nnotes\n"
+    );
+    assert_eq!(rotate::rotated("old", "new", false), "new");
+}
+
+#[test]
+fn rotate_entries() {
+    common::fake_test_setup(Store::Empty, &[]);
+
+    let store = &*consts::PASSWORD_STORE_DIR;
+
+    fs::create_dir_all(store).unwrap();
+
+    let repo = Repository::init(store).unwrap();
+
+    fs::write(store.join(".gpg-id"), "passrs@teammate").unwrap();
+    common::write_entry(store.join("Internet/a.gpg"), "a\nurl: a.com\n");
+    common::write_entry(store.join("Internet/b.gpg"), "b\n");
+    common::write_entry(store.join("Other/c.gpg"), "c\nuser: me\n");
+    common::write_entry(store.join("otp.gpg"), "otpauth://totp/c?secret=AAAA\n");
+    common::configure(&repo);
+    common::commit_all(&repo, "Add entries");
+
+    let internet = rotate::find_entries(&["Internet/*"]).unwrap();
+
+    assert_eq!(
+        internet,
+        [store.join("Internet/a.gpg"), store.join("Internet/b.gpg")]
+    );
+    assert_eq!(rotate::find_entries(&["Internet"]).unwrap(), internet);
+    assert_eq!(
+        rotate::find_entries(&["**/c", "Internet/a"]).unwrap(),
+        [store.join("Internet/a.gpg"), store.join("Other/c.gpg")]
+    );
+    assert!(rotate::find_entries(&["Missing/*"]).is_err());
+
+    // A failure leaves every entry alone
+    let head = repo.refname_to_id("HEAD").unwrap();
+    let all = rotate::find_entries(&["**"]).unwrap();
+
+    assert_eq!(all.len(), 4);
+    assert!(rotate::rotate(&all, false, |_| Ok(String::from("new"))).is_err());
+    assert_eq!(
+        read_entry(store, "Internet/a.gpg"),
+        common::fake_entry("a\nurl: a.com\n")
+    );
+    assert_eq!(repo.refname_to_id("HEAD").unwrap(), head);
+
+    // Every rotation ends up in one commit
+    let entries = rotate::find_entries(&["Internet", "Other/c"]).unwrap();
+    let rotations = rotate::rotate(&entries, true, |path| {
+        Ok(format!(
+            "new {}",
+            path.file_stem().unwrap().to_string_lossy()
+        ))
+    })
+    .unwrap();
+
+    assert_eq!(
+        rotations,
+        [
+            Rotation {
+                name: String::from("Internet/a"),
+                url: Some(String::from("a.com")),
+            },
+            Rotation {
+                name: String::from("Internet/b"),
+                url: None,
+            },
+            Rotation {
+                name: String::from("Other/c"),
+                url: None,
+            },
+        ]
+    );
+    assert_eq!(
+        read_entry(store, "Internet/a.gpg"),
+        common::fake_entry_for(&["passrs@teammate"], "new a\nprevious: a\nurl: a.com\n")
+    );
+    assert_eq!(
+        read_entry(store, "Other/c.gpg"),
+        common::fake_entry_for(&["passrs@teammate"], "new c\nprevious: c\nuser: me\n")
+    );
+
+    let commit = repo.head().unwrap().peel_to_commit().unwrap();
+
+    assert_eq!(commit.parent_id(0).unwrap(), head);
+    assert_eq!(
+        commit.message().unwrap(),
+        "Rotate secrets for 3 entries\n\nInternet/a\nInternet/b\nOther/c"
+    );
+    assert!(repo.statuses(None).unwrap().is_empty());
+}
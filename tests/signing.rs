@@ -0,0 +1,84 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use gpgme::{Context, Protocol};
+use passrs::error::PassrsError;
+use passrs::util::{self, EditMode, EntryKind};
+
+use common::{Store, TempDir};
+
+const SIGNING_KEY: &str = "4B0D9BBAC5C8329C035B125CF6EF0D39C5F84192";
+
+fn sign(path: &Path) {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp).unwrap();
+    let key = ctx.get_secret_key(SIGNING_KEY).unwrap();
+    let mut sig = Vec::new();
+
+    ctx.add_signer(&key).unwrap();
+    ctx.sign_detached(fs::read(path).unwrap(), &mut sig)
+        .unwrap();
+    fs::write(format!("{}.sig", path.display()), sig).unwrap();
+}
+
+#[test]
+fn unsigned_gpg_id() {
+    common::fake_test_setup(Store::Empty, &[("PASSWORD_STORE_SIGNING_KEY", SIGNING_KEY)]);
+
+    let dir = TempDir::in_store("unsigned");
+    let entry = dir.join("entry.gpg");
+
+    fs::write(dir.join(".gpg-id"), "passrs@testuser").unwrap();
+    common::write_entry(&entry, "secret");
+
+    let err = common::passrs_error(util::encrypt_bytes_into_file(
+        "secret",
+        &entry,
+        EditMode::Clobber,
+    ));
+
+    assert!(matches!(err, PassrsError::NoSignatureFound(_)));
+    assert!(matches!(
+        common::passrs_error(util::recrypt_file(&entry, None)),
+        PassrsError::NoSignatureFound(_)
+    ));
+    assert!(matches!(
+        common::passrs_error(util::recrypt_dir(&dir, None, EntryKind::Gpg)),
+        PassrsError::NoSignatureFound(_)
+    ));
+
+    // Explicitly specified keys don't come from a `.gpg-id`
+    util::recrypt_file(&entry, Some(&[String::from("passrs@teammate")])).unwrap();
+}
+
+#[test]
+#[ignore] // Don't spawn gpg-agent
+fn signed_gpg_id() {
+    common::fake_test_setup(Store::Empty, &[("PASSWORD_STORE_SIGNING_KEY", SIGNING_KEY)]);
+
+    let dir = TempDir::in_store("signed");
+    let entry = dir.join("entry.gpg");
+    let gpg_id = dir.join(".gpg-id");
+
+    fs::write(&gpg_id, "passrs@testuser").unwrap();
+    sign(&gpg_id);
+
+    util::verify_file(&gpg_id).unwrap();
+    util::encrypt_bytes_into_file("secret", &entry, EditMode::Clobber).unwrap();
+
+    // Somebody sneaked in another recipient
+    fs::write(&gpg_id, "passrs@testuser\npassrs@teammate").unwrap();
+
+    let err = common::passrs_error(util::encrypt_bytes_into_file(
+        "secret",
+        &entry,
+        EditMode::Clobber,
+    ));
+
+    assert!(matches!(err, PassrsError::InvalidSignature(_)));
+    assert!(matches!(
+        common::passrs_error(util::recrypt_file(&entry, None)),
+        PassrsError::InvalidSignature(_)
+    ));
+}
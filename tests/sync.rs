@@ -0,0 +1,165 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use git2::Repository;
+use passrs::consts;
+use passrs::sync::{self, SyncOutcome};
+use passrs::util;
+
+use common::{Store, TempDir};
+
+fn push(repo: &Repository) {
+    repo.find_remote("origin")
+        .unwrap()
+        .push(&["refs/heads/master:refs/heads/master"], None)
+        .unwrap();
+}
+
+fn plaintext(path: &Path) -> String {
+    String::from_utf8(util::decrypt_file_into_bytes(path).unwrap()).unwrap()
+}
+
+#[test]
+fn sync_with_bare_remote() {
+    common::fake_test_setup(Store::Empty, &[]);
+
+    let store = &*consts::PASSWORD_STORE_DIR;
+    let dir = TempDir::new("sync");
+    let remote_path = fs::canonicalize(&dir).unwrap().join("remote.git");
+    let remote = Repository::init_bare(&remote_path).unwrap();
+
+    // Push the initial store
+    let repo = Repository::init(store).unwrap();
+
+    common::configure(&repo);
+    fs::write(store.join(".gpg-id"), "passrs@testuser").unwrap();
+    common::commit_entry(&repo, "a.gpg", "user: me\npassword\n");
+    repo.remote("origin", remote_path.to_str().unwrap())
+        .unwrap();
+
+    let no_conflicts = |_: &str, _: &sync::Merge| -> passrs::error::Result<Vec<u8>> {
+        panic!("nothing should conflict")
+    };
+
+    assert_eq!(
+        sync::sync("origin", true, no_conflicts).unwrap(),
+        SyncOutcome::UpToDate
+    );
+    assert_eq!(
+        remote.refname_to_id("refs/heads/master").unwrap(),
+        repo.refname_to_id("refs/heads/master").unwrap()
+    );
+
+    // Another clone adds an entry
+    let other = Repository::clone(remote_path.to_str().unwrap(), dir.join("other")).unwrap();
+
+    common::configure(&other);
+    common::commit_entry(&other, "b.gpg", "b\n");
+    push(&other);
+
+    assert_eq!(
+        sync::sync("origin", true, no_conflicts).unwrap(),
+        SyncOutcome::FastForwarded
+    );
+    assert_eq!(plaintext(&store.join("b.gpg")), "b\n");
+
+    // Both sides change the same entry
+    common::commit_entry(&other, "a.gpg", "user: me\nother password\n");
+    push(&other);
+    common::commit_entry(&repo, "a.gpg", "user: myself\npassword\n");
+
+    let outcome = sync::sync("origin", true, |entry, merge| {
+        assert_eq!(entry, "a.gpg");
+        assert_eq!(merge.conflicts, 0);
+
+        Ok(merge.text.clone())
+    })
+    .unwrap();
+
+    assert_eq!(outcome, SyncOutcome::Merged(1));
+    assert_eq!(
+        plaintext(&store.join("a.gpg")),
+        "user: myself\nother password\n"
+    );
+    assert_eq!(
+        repo.head()
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .parent_count(),
+        2
+    );
+    assert_eq!(
+        remote.refname_to_id("refs/heads/master").unwrap(),
+        repo.refname_to_id("refs/heads/master").unwrap()
+    );
+    assert!(repo.statuses(None).unwrap().is_empty());
+
+    // Conflicting changes are left to the resolver
+    common::commit_entry(&other, "b.gpg", "from other\n");
+    push(&other);
+    common::commit_entry(&repo, "b.gpg", "from store\n");
+
+    let head = repo.refname_to_id("refs/heads/master").unwrap();
+
+    assert!(sync::sync("origin", true, |_, merge| {
+        assert_eq!(merge.conflicts, 1);
+        Err("give up".into())
+    })
+    .is_err());
+    // Nothing changed locally
+    assert_eq!(repo.refname_to_id("refs/heads/master").unwrap(), head);
+    assert_eq!(plaintext(&store.join("b.gpg")), "from store\n");
+
+    sync::sync("origin", false, |_, _| Ok(b"resolved\n".to_vec())).unwrap();
+    assert_eq!(plaintext(&store.join("b.gpg")), "resolved\n");
+    assert_ne!(
+        remote.refname_to_id("refs/heads/master").unwrap(),
+        repo.refname_to_id("refs/heads/master").unwrap()
+    );
+}
+
+#[test]
+fn merge3() {
+    let base = b"user: me\nurl: example.com\npassword\n";
+
+    // Changes to different lines merge cleanly
+    let merge = sync::merge3(
+        base,
+        b"user: myself\nurl: example.com\npassword\n",
+        b"user: me\nurl: example.com\nnew password\nnote\n",
+    );
+
+    assert_eq!(merge.conflicts, 0);
+    assert_eq!(
+        merge.text,
+        b"user: myself\nurl: example.com\nnew password\nnote\n".to_vec()
+    );
+
+    // So do identical changes
+    let merge = sync::merge3(base, b"password\n", b"password\n");
+
+    assert_eq!(merge.conflicts, 0);
+    assert_eq!(merge.text, b"password\n".to_vec());
+
+    // Different changes to the same line don't
+    let merge = sync::merge3(
+        base,
+        b"user: me\nurl: a.com\npassword\n",
+        b"user: me\nurl: b.com",
+    );
+
+    assert_eq!(merge.conflicts, 1);
+    assert_eq!(
+        String::from_utf8(merge.text).unwrap(),
+        format!(
+            "user: me\n{}\nurl: a.com\npassword\n{}\nurl: example.com\npassword\n{}\nurl: b.com\n{}\n",
+            sync::CONFLICT_START,
+            sync::CONFLICT_BASE,
+            sync::CONFLICT_SEPARATOR,
+            sync::CONFLICT_END
+        )
+    );
+}
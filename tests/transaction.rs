@@ -0,0 +1,216 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use git2::{Repository, Status};
+use once_cell::sync::Lazy;
+use passrs::consts;
+use passrs::transaction::Transaction;
+use passrs::util::{self, EntryKind};
+
+use common::Store;
+
+/// Every test gets a fresh store, so they can't run at the same time.
+static STORE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+fn fresh_store() -> MutexGuard<'static, ()> {
+    let guard = STORE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    common::fake_test_setup(Store::Empty, &[]);
+
+    let store = &*consts::PASSWORD_STORE_DIR;
+
+    let _ = fs::remove_dir_all(store);
+
+    fs::create_dir_all(store).unwrap();
+    fs::write(store.join(".gpg-id"), "passrs@testuser").unwrap();
+
+    guard
+}
+
+fn entry(name: &str, contents: &str) -> PathBuf {
+    let path = consts::PASSWORD_STORE_DIR.join(name);
+
+    common::write_entry(&path, contents);
+
+    path
+}
+
+fn contents(path: &Path) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+/// Every file in the store, so that leftover staged files and backups show up.
+fn listing() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = walkdir::WalkDir::new(&*consts::PASSWORD_STORE_DIR)
+        .into_iter()
+        .filter_entry(|e| e.file_name() != ".git")
+        .map(|e| e.unwrap().into_path())
+        .collect();
+
+    files.sort();
+    files
+}
+
+#[test]
+fn recrypt_dir_failure() {
+    let _guard = fresh_store();
+
+    let a = entry("dir/a.gpg", "a");
+    let b = entry("dir/b.gpg", "inject failure");
+    let c = entry("dir/sub/c.gpg", "c");
+    let before = listing();
+
+    let keys = [String::from("passrs@teammate")];
+
+    assert!(util::recrypt_dir(a.parent().unwrap(), Some(&keys), EntryKind::Gpg).is_err());
+    assert_eq!(contents(&a), common::fake_entry("a"));
+    assert_eq!(contents(&b), common::fake_entry("inject failure"));
+    assert_eq!(contents(&c), common::fake_entry("c"));
+    assert_eq!(listing(), before);
+
+    common::write_entry(&b, "b");
+    util::recrypt_dir(a.parent().unwrap(), Some(&keys), EntryKind::Gpg).unwrap();
+    assert_eq!(
+        contents(&a),
+        common::fake_entry_for(&["passrs@teammate"], "a")
+    );
+    assert_eq!(
+        contents(&b),
+        common::fake_entry_for(&["passrs@teammate"], "b")
+    );
+    assert_eq!(
+        contents(&c),
+        common::fake_entry_for(&["passrs@teammate"], "c")
+    );
+    assert_eq!(listing(), before);
+
+    fs::remove_dir_all(&*consts::PASSWORD_STORE_DIR).unwrap();
+}
+
+#[test]
+fn apply_failure() {
+    let _guard = fresh_store();
+
+    let a = entry("a.gpg", "a");
+    let b = entry("b.gpg", "b");
+    // A folder where an entry should go can't be replaced
+    let conflict = entry("conflict.gpg/c.gpg", "c");
+    let before = listing();
+    let mut tx = Transaction::new();
+
+    tx.write(&a, "new a").unwrap();
+    tx.remove(&b).unwrap();
+    tx.write(consts::PASSWORD_STORE_DIR.join("new/dir/d.gpg"), "d")
+        .unwrap();
+    tx.write(conflict.parent().unwrap(), "conflict").unwrap();
+
+    assert!(tx.commit(|| Ok(())).is_err());
+    assert_eq!(contents(&a), common::fake_entry("a"));
+    assert_eq!(contents(&b), common::fake_entry("b"));
+    assert_eq!(listing(), before);
+
+    fs::remove_dir_all(&*consts::PASSWORD_STORE_DIR).unwrap();
+}
+
+#[test]
+fn commit_failure() {
+    let _guard = fresh_store();
+
+    let store = &*consts::PASSWORD_STORE_DIR;
+    let a = entry("a.gpg", "a");
+    let b = entry("b.gpg", "b");
+    let repo = Repository::init(store).unwrap();
+
+    common::configure(&repo);
+    common::commit_all(&repo, "Initial commit");
+
+    let head = repo.head().unwrap().target().unwrap();
+    let before = listing();
+    let mut tx = Transaction::new();
+
+    tx.write(&a, "new a").unwrap();
+    tx.remove(&b).unwrap();
+
+    // Fails after the commit was made
+    let ret = tx.commit(|| {
+        util::commit(Some([&a, &b]), "Change a, remove b")?;
+        Err("injected failure".into())
+    });
+
+    assert!(ret.is_err());
+    assert_eq!(repo.head().unwrap().target(), Some(head));
+    assert_eq!(contents(&a), common::fake_entry("a"));
+    assert_eq!(contents(&b), common::fake_entry("b"));
+    assert_eq!(listing(), before);
+
+    for path in &["a.gpg", "b.gpg"] {
+        assert_eq!(repo.status_file(Path::new(path)).unwrap(), Status::CURRENT);
+    }
+
+    fs::remove_dir_all(store).unwrap();
+}
+
+#[test]
+fn successful_commit() {
+    let _guard = fresh_store();
+
+    let a = entry("a.gpg", "a");
+    let b = entry("dir/sub/b.gpg", "b");
+    let c = entry("dir/c.gpg", "c");
+    let d = consts::PASSWORD_STORE_DIR.join("new/d.gpg");
+    let mut tx = Transaction::new();
+
+    tx.write(&a, "new a").unwrap();
+    tx.copy(&b, &d).unwrap();
+    tx.remove(c.parent().unwrap()).unwrap();
+    tx.commit(|| Ok(())).unwrap();
+
+    assert_eq!(contents(&a), "new a");
+    assert_eq!(contents(&d), common::fake_entry("b"));
+    assert!(!b.exists());
+    assert!(!c.exists());
+    // Empty folders are removed, but never the store itself
+    assert!(!c.parent().unwrap().exists());
+    assert_eq!(
+        listing(),
+        vec![
+            consts::PASSWORD_STORE_DIR.clone(),
+            consts::PASSWORD_STORE_DIR.join(".gpg-id"),
+            a,
+            consts::PASSWORD_STORE_DIR.join("new"),
+            d,
+        ]
+    );
+
+    fs::remove_dir_all(&*consts::PASSWORD_STORE_DIR).unwrap();
+}
+
+#[test]
+fn uncommitted_transaction() {
+    let _guard = fresh_store();
+
+    let gpg_id = consts::PASSWORD_STORE_DIR.join(".gpg-id");
+    let marker = consts::PASSWORD_STORE_DIR.join(".gpg-id.hidden");
+    let a = entry("a.gpg", "a");
+    let before = listing();
+
+    {
+        let mut tx = Transaction::new();
+
+        tx.track(&gpg_id).unwrap();
+        tx.track(&marker).unwrap();
+        fs::write(&gpg_id, "passrs@teammate").unwrap();
+        fs::write(&marker, "").unwrap();
+        tx.write(&a, "new a").unwrap();
+        tx.remove(&a).unwrap();
+    }
+
+    assert_eq!(contents(&gpg_id), "passrs@testuser");
+    assert_eq!(contents(&a), common::fake_entry("a"));
+    assert_eq!(listing(), before);
+
+    fs::remove_dir_all(&*consts::PASSWORD_STORE_DIR).unwrap();
+}
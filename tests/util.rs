@@ -1,18 +1,18 @@
-use std::env;
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
 use std::path::PathBuf;
 
+use gpgme::{Context, Protocol};
 use passrs::consts;
-use passrs::util;
+use passrs::util::{self, EditMode, EntryKind};
 
-fn test_setup() {
-    env::set_var("RUST_BACKTRACE", "1");
-    env::set_var("PASSWORD_STORE_DIR", "./tests/test_repo");
-    env::set_var("GNUPGHOME", "./tests/gnupg");
-}
+use common::{Store, TempDir};
 
 #[test]
 fn canonicalize_path() {
-    test_setup();
+    common::test_setup(Store::TestRepo, &[]);
 
     let paths = [
         "Internet/amazon.com/password",
@@ -32,7 +32,7 @@ fn canonicalize_path() {
 
 #[test]
 fn exact_path() {
-    test_setup();
+    common::test_setup(Store::TestRepo, &[]);
 
     let paths = [
         "Internet/amazon.com/password",
@@ -52,7 +52,7 @@ fn exact_path() {
 
 #[test]
 fn check_sneaky_paths() {
-    test_setup();
+    common::test_setup(Store::TestRepo, &[]);
 
     assert!(util::check_sneaky_paths("../../password").is_err());
     assert!(util::check_sneaky_paths("..").is_err());
@@ -63,7 +63,7 @@ fn check_sneaky_paths() {
 
 #[test]
 fn find_matches() {
-    test_setup();
+    common::test_setup(Store::TestRepo, &[]);
 
     assert!(util::find_matches(".").unwrap().len() > 0);
     assert!(util::find_matches("a").unwrap().len() == 1);
@@ -75,9 +75,9 @@ fn find_matches() {
 #[test]
 #[ignore] // Don't spawn gpg-agent
 fn decrypt_file_into_bytes() {
-    test_setup();
+    common::test_setup(Store::TestRepo, &[]);
 
-    let file = "./tests/test_repo/a.gpg";
+    let file = consts::PASSWORD_STORE_DIR.join("a.gpg");
     let contents = util::decrypt_file_into_bytes(&file).unwrap();
 
     assert_eq!(contents, "eHy;CDpa&4]Sf1g*rx1Zlrig".as_bytes());
@@ -86,9 +86,9 @@ fn decrypt_file_into_bytes() {
 #[test]
 #[ignore] // Don't spawn gpg-agent
 fn decrypt_file_into_strings() {
-    test_setup();
+    common::test_setup(Store::TestRepo, &[]);
 
-    let file = "./tests/test_repo/f.gpg";
+    let file = consts::PASSWORD_STORE_DIR.join("f.gpg");
     let contents = util::decrypt_file_into_strings(&file).unwrap();
     let mut iter = contents.iter();
 
@@ -104,8 +104,201 @@ fn decrypt_file_into_strings() {
 
 #[test]
 fn find_gpg_id() {
-    test_setup();
+    common::test_setup(Store::TestRepo, &[]);
 
     assert!(util::find_gpg_id("/").is_err());
     assert!(util::find_gpg_id(&*consts::PASSWORD_STORE_DIR).is_ok());
 }
+
+#[test]
+fn get_encryption_keys() {
+    common::test_setup(Store::TestRepo, &[]);
+
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp).unwrap();
+    let keys = [
+        String::from("passrs@testuser"),
+        String::from("passrs@teammate"),
+    ];
+
+    // We only hold the public half of the teammate's key
+    assert!(ctx.get_secret_key("passrs@teammate").is_err());
+    assert_eq!(util::get_encryption_keys(&mut ctx, &keys).unwrap().len(), 2);
+    assert!(util::get_encryption_keys(&mut ctx, &[String::from("passrs@nobody")]).is_err());
+    assert!(util::get_encryption_keys(&mut ctx, &[String::new()])
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn encrypt_bytes_into_file() {
+    common::test_setup(Store::TestRepo, &[]);
+
+    let dir = TempDir::in_store("recipients");
+    let entry = dir.join("entry.gpg");
+
+    fs::write(dir.join(".gpg-id"), "passrs@testuser\npassrs@nobody").unwrap();
+    assert!(util::encrypt_bytes_into_file("secret", &entry, EditMode::Clobber).is_err());
+    assert!(!entry.exists());
+
+    fs::write(dir.join(".gpg-id"), "passrs@testuser\npassrs@teammate").unwrap();
+    assert!(util::encrypt_bytes_into_file("secret", &entry, EditMode::Clobber).is_ok());
+    assert!(fs::metadata(&entry).unwrap().len() > 0);
+}
+
+#[test]
+fn strip_extension() {
+    assert_eq!(
+        util::strip_extension("Internet/amazon.com/password.gpg"),
+        "Internet/amazon.com/password"
+    );
+    assert_eq!(
+        util::strip_extension("Internet/amazon.com/password.age"),
+        "Internet/amazon.com/password"
+    );
+    assert_eq!(
+        util::strip_extension("Internet/amazon.com"),
+        "Internet/amazon.com"
+    );
+    assert!(util::is_entry("a.age"));
+    assert!(!util::is_entry("a.gpg-id"));
+    assert_eq!(EntryKind::from_path("a.age"), EntryKind::Age);
+    assert_eq!(EntryKind::from_path("a.gpg"), EntryKind::Gpg);
+}
+
+#[test]
+fn age_substore() {
+    common::test_setup(Store::TestRepo, &[]);
+
+    let dir = TempDir::in_store("age");
+    let recipient = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(
+        dir.join(".age-recipients"),
+        format!("# passrs@testuser\n{}\n\n", recipient),
+    )
+    .unwrap();
+
+    assert_eq!(
+        util::closest_entry_kind(dir.join("sub/entry")),
+        EntryKind::Age
+    );
+    assert_eq!(
+        util::closest_entry_kind(consts::PASSWORD_STORE_DIR.join("entry")),
+        EntryKind::Gpg
+    );
+    assert_eq!(
+        util::canonicalize_path(format!("{}/sub/entry", dir.name())).unwrap(),
+        dir.join("sub/entry.age")
+    );
+    assert_eq!(
+        util::get_recipients(dir.join("sub/entry.age"), EntryKind::Age).unwrap(),
+        vec![String::from(recipient)]
+    );
+    assert!(
+        util::get_recipients(consts::PASSWORD_STORE_DIR.join("entry.age"), EntryKind::Age).is_err()
+    );
+}
+
+#[test]
+fn write_atomically() {
+    use std::os::unix::fs::PermissionsExt;
+
+    common::test_setup(Store::TestRepo, &[]);
+
+    let dir = TempDir::in_store("atomic");
+    let file = dir.join("entry.gpg");
+
+    util::write_atomically(&file, "a longer first version").unwrap();
+    util::write_atomically(&file, "shorter").unwrap();
+
+    // Nothing of the old contents or the temporary file is left behind
+    assert_eq!(fs::read_to_string(&file).unwrap(), "shorter");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    assert_eq!(
+        fs::metadata(&file).unwrap().permissions().mode() & 0o777,
+        0o666 - (0o666 & *consts::PASSWORD_STORE_UMASK)
+    );
+
+    assert!(util::write_atomically(dir.join("nonexistent").join("entry.gpg"), "").is_err());
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+    util::copy(&file, &dir.join("copy.gpg")).unwrap();
+
+    assert_eq!(fs::read_to_string(dir.join("copy.gpg")).unwrap(), "shorter");
+    assert_eq!(
+        fs::metadata(dir.join("copy.gpg"))
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o777,
+        0o640
+    );
+}
+
+/// Generates `draws` characters from `set` and returns Pearson's chi-squared
+/// statistic for them being uniformly distributed over `set`.
+fn chi_squared(set: &[char], draws: usize) -> f64 {
+    let secret = util::generate_chars_from_set(set, draws).unwrap();
+    let mut counts: HashMap<char, usize> = set.iter().map(|&c| (c, 0)).collect();
+
+    for c in secret.chars() {
+        *counts.get_mut(&c).expect("character not from the set") += 1;
+    }
+
+    let expected = draws as f64 / counts.len() as f64;
+
+    counts
+        .values()
+        .map(|&n| (n as f64 - expected).powi(2) / expected)
+        .sum()
+}
+
+// The critical values below are those of the chi-squared distribution at a
+// p-value of 0.0001, so a uniform generator fails these 1 in 10000 runs
+
+#[test]
+fn generate_chars_uniformly() {
+    // The 94 characters of the default set, which a byte modulo skews heavily
+    let set: Vec<char> = [
+        consts::DIGITS,
+        consts::ALPHA_LOWER,
+        consts::ALPHA_UPPER,
+        consts::SPECIAL,
+    ]
+    .concat()
+    .into_iter()
+    .map(char::from)
+    .collect();
+
+    assert!(chi_squared(&set, 94 * 500) < 152.45);
+
+    // Multibyte characters are picked whole
+    let set = ['a', 'é', 'ß', '€', '漢', '字', '🦀'];
+
+    assert!(chi_squared(&set, 7 * 10_000) < 27.86);
+}
+
+#[test]
+fn generate_chars_from_large_set() {
+    // More characters than a byte can index
+    let set: Vec<char> = (0x4e00..0x4e00 + 300)
+        .map(|c| std::char::from_u32(c).unwrap())
+        .collect();
+
+    assert!(chi_squared(&set, 300 * 200) < 398.6);
+}
+
+#[test]
+fn generate_chars_ignores_duplicates() {
+    assert!(chi_squared(&['a', 'a', 'a', 'b'], 20_000) < 15.14);
+}
+
+#[test]
+fn generate_chars_length() {
+    let secret = util::generate_chars_from_set(['é', '🦀'], 24).unwrap();
+
+    assert_eq!(secret.chars().count(), 24);
+    assert!(util::generate_chars_from_set([], 24).is_err());
+}